@@ -0,0 +1,8528 @@
+//! Parses TV episode file names (`show.name.s01e02.720p.mkv`, `Show - 1x02`, ...) and renames
+//! them to `Show.Name.S01E02.720p.mkv`.
+//!
+//! `plan_renames` works out the new names in a directory without prompting or touching any
+//! file, and `apply_renames` carries out such a plan. `run` is the whole interactive flow the
+//! `rename-episodes` binary drives, prompts included. Single names can be parsed with
+//! `parse_filename`, or `parse_stem` when there is no file on disk.
+
+mod nfc;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::Chars;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Set by `--json` and `--emit-script`: stdout then carries only the JSON plan or the script and
+/// everything else goes to stderr.
+static STDOUT_IS_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--quiet` and `--verbose`; holds a `Verbosity` as u8.
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Routes and filters this crate's console output as `options.json` and `options.verbosity` ask.
+/// Output settings are process-wide; call this before `run`, `plan_renames` or `apply_renames`.
+pub fn configure_output(options: &Options) {
+    STDOUT_IS_JSON.store(options.json || options.emit_script, Ordering::Relaxed);
+    VERBOSITY.store(options.verbosity as u8, Ordering::Relaxed);
+}
+
+/// `println!` for the closing report, printed at every verbosity. Moves to stderr under `--json`.
+macro_rules! report {
+    ($($arg:tt)*) => {
+        if STDOUT_IS_JSON.load(Ordering::Relaxed) {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+/// `println!` for human-readable progress and warnings, silenced by `--quiet`.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if Verbosity::current() >= Verbosity::Normal {
+            report!($($arg)*)
+        }
+    };
+}
+
+/// `println!` for per-file parse decisions, shown only with `--verbose`.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if Verbosity::current() >= Verbosity::Verbose {
+            report!($($arg)*)
+        }
+    };
+}
+
+lazy_static! {
+    // Numbers are matched as ASCII [0-9]: `\d` also matches digits of other scripts ("١٢"),
+    // which don't parse as numbers and would turn into episode 0.
+
+    // Regex to find SxxExx or SxxExxx patterns, case-insensitive. Captures season and episode numbers.
+    // A single separator between the two halves ("S01.E03", "S01 E03", "S01_E03") is tolerated so
+    // those styles normalize to the same SxxExx as the rest of the folder.
+    static ref SE_RE: Regex = Regex::new(r"(?i)S([0-9]{1,3})[ ._-]?E([0-9]{1,3})").unwrap();
+    // Regex to find the older "1x02" / "01x02" notation as a standalone token. Captures the
+    // surrounding separators so a rewrite keeps them, and rejects resolutions like "1920x1080".
+    static ref SEASON_X_RE: Regex =
+        Regex::new(r"(?i)(^|[^\pL\pN])([0-9]{1,2})x([0-9]{1,3})($|[^\pL\pN])").unwrap();
+    // Regex to find Exx or Exxx patterns (if Sxx is missing), case-insensitive, starting a word so
+    // the "e" inside words doesn't count. Captures the tag and its episode number; see `find_e_tag`.
+    static ref E_RE: Regex = Regex::new(r"(?i)(?:^|[^\pL\pN])(E([0-9]{1,3}))").unwrap();
+    // Regex for a further episode directly after a tag ("E02" in S01E01E02, "-E03" in S01E01-E03).
+    static ref MORE_E_RE: Regex = Regex::new(r"(?i)^(-?)E([0-9]{1,3})").unwrap();
+    // Regex for a release group token leading the stem: "[SubsPlease] Show", "{Group}Show".
+    static ref LEADING_GROUP_RE: Regex =
+        Regex::new(r"^\s*(?:\[([^\]]+)\]|\{([^}]+)\})[ ._-]*").unwrap();
+    // Regex to find common suffix patterns like "-GroupName[Source]" at the end of the filename stem.
+    static ref SUFFIX_RE: Regex = Regex::new(r"-(?:[^-]+)(\[[^\]]+\])$").unwrap();
+    // A bracketed group closing the stem after a dash: "-[EZTV]", "-{Group}".
+    static ref BRACKET_SUFFIX_RE: Regex =
+        Regex::new(r"-\s*(?:\[([^\]]+)\]|\{([^}]+)\})$").unwrap();
+    // A bare word closing the stem after a dash, stripped only for known groups: "-RARBG".
+    static ref GROUP_SUFFIX_RE: Regex = Regex::new(r"-([\pL\pN.]+)$").unwrap();
+    // Regex to find a part indicator ("part.1", "pt2", "part.one", "part.ii") in a cleaned, dot-separated segment.
+    static ref PART_RE: Regex = Regex::new(
+        r"(?i)(?:^|\.)(?:(?:part|pt)\.?([0-9]{1,2}|one|two|three|four|five|six|seven|eight|nine|ten|first|second|third|fourth|fifth|sixth|seventh|eighth|ninth|tenth|i|ii|iii|iv|v|vi|vii|viii|ix|x)|\(([0-9]{1,2})\))(?:\.|$)"
+    ).unwrap();
+    // Regex to find inline provider ID tags like "[tmdbid-1396]" or "{tvdb-78901}", case-insensitive.
+    static ref ID_TAG_RE: Regex = Regex::new(
+        r"(?i)([\[{])\s*(tmdbid|tmdb|tvdbid|tvdb|imdbid|imdb)-([a-z0-9]+)\s*([\]}])"
+    ).unwrap();
+    // Regex for a British-style "Series 2" folder name, the whole name only.
+    static ref SERIES_FOLDER_RE: Regex = Regex::new(r"(?i)^series[ ._-]*([0-9]{1,3})$").unwrap();
+    // Regex for a "Season 3" / "Season03" / "S03" folder name, the whole name only.
+    static ref SEASON_FOLDER_RE: Regex = Regex::new(r"(?i)^s(?:eason)?[ ._-]*([0-9]{1,3})$").unwrap();
+    // Regex for a standalone number ("Show - 14"), used as an absolute episode in Series folders.
+    static ref BARE_NUMBER_RE: Regex = Regex::new(r"(?:^|[ ._-])([0-9]{1,3})(?:$|[ ._-])").unwrap();
+    // Regex for a `key = [ ... ]` array in words.toml; arrays may span lines.
+    static ref WORDS_KEY_RE: Regex =
+        Regex::new(r"(?m)^\s*(lowercase|acronyms)\s*=\s*\[([^\]]*)\]").unwrap();
+    // Regex to find "{placeholder}" fields in a --template.
+    static ref TEMPLATE_FIELD_RE: Regex = Regex::new(r"\{([a-z_]+)\}").unwrap();
+    // "[Group] Show - 135 [1080p]": absolute episode numbering, as used by anime releases
+    static ref ABSOLUTE_RE: Regex = Regex::new(
+        r"^(?:\[[^\]]*\][ ._]*)?(.+?)[ ._]+-[ ._]+([0-9]{1,4})(?:v[0-9]+)?(?:$|[ ._\[(])"
+    )
+    .unwrap();
+    // Regex for one --episodes endpoint: "S01E05", or a whole season "S02". Case-insensitive.
+    static ref EPISODE_SPEC_RE: Regex = Regex::new(r"(?i)^S([0-9]{1,3})(?:E([0-9]{1,3}))?$").unwrap();
+    // "sample" as a word of a file stem ("sample", "Show.S01E02-sample", "[Sample] Show"), not "Sampler".
+    static ref SAMPLE_RE: Regex = Regex::new(r"(?i)(?:^|[ ._\-\[\(])sample(?:$|[ ._\-\]\)])").unwrap();
+    // A run of ASCII digits, for re-padding the numbers of formatted tags.
+    static ref DIGITS_RE: Regex = Regex::new(r"[0-9]+").unwrap();
+    // A bracketed year closing a show name: "Show (2019)", "Show [2019]".
+    static ref SHOW_YEAR_RE: Regex = Regex::new(r"[\(\[]((?:19|20)[0-9]{2})[\)\]]$").unwrap();
+
+    // A field of a Kodi episode .nfo: "<season>1</season>", attributes allowed.
+    static ref NFO_FIELD_RE: Regex =
+        Regex::new(r"<(showtitle|season|episode)(?:\s[^>]*)?>([^<]*)</").unwrap();
+}
+
+/// What was read from one file name. Parts are `None` when the name doesn't carry them.
+#[derive(Debug, Clone)]
+pub struct ParsedInfo {
+    pub original_path: PathBuf,
+    pub original_filename: String, // Lossy UTF-8 copy of the file name, for display only
+    pub extension: OsString,       // Taken verbatim from the original path so it round-trips
+    pub show_name_part: Option<String>, // Cleaned, lowercase, dot-separated part before SxxExx
+    pub show_name_source: Option<String>, // Show part with separators normalized but original casing kept
+    pub season_prefix_part: Option<String>, // Formatted as "Sxx"
+    pub episode_number_part: Option<String>, // Formatted as "Exx"
+    pub remainder_part: Option<String>,   // Cleaned, lowercase, dot-separated part after SxxExx
+    pub remainder_source: Option<String>, // Remainder with separators normalized but original casing kept
+    pub part: Option<u32>, // Multi-part indicator (Pt.1, Part One, Part I), rendered as "Part.N"
+    pub id_tags: Vec<String>, // Provider ID tags ("[tmdbid-1396]"), only extracted with --keep-ids
+    pub second_tag: Option<String>, // A further SxxExx in the remainder, e.g. left by a botched rename
+    pub release_group: Option<String>, // Leading "[Group]"/"{Group}" token, kept out of the show name
+    pub needs_user_input: bool,        // Flag if show name or season needs to be derived/confirmed
+}
+
+/// Show/Season typed at the prompts for the files of one folder that lacked them.
+#[derive(Debug, Clone)]
+struct FolderInput {
+    show_name: Option<String>,     // Cleaned/lowercase version
+    show_source: Option<String>,   // As typed, for acronym casing
+    season_prefix: Option<String>, // "Sxx"; None when --season supplies it
+}
+
+/// Why a file can't be given a new name. Pass 1 reports the file-level variants; the naming
+/// variants are raised once prompts had their chance to fill in what the filename lacked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    NotAFile,
+    NoFileName,
+    NonUtf8Name, // Stem isn't valid UTF-8, so a rebuilt name could not preserve it
+    EmptyShowName,
+    MissingShowName,
+    MissingSeason,
+    NoEpisodeMarker,
+    UnparseableSeason(String), // Season typed at the prompt, as entered
+    Inaccessible(String),      // The file's metadata can't be read (permissions, a locked file)
+}
+
+impl ParseError {
+    /// The component a near-miss file lacked, for `--report-unrenamable`.
+    fn missing_component(&self) -> &'static str {
+        match self {
+            ParseError::EmptyShowName => "show name (empty)",
+            ParseError::MissingShowName => "show name",
+            ParseError::MissingSeason | ParseError::UnparseableSeason(_) => "season",
+            ParseError::NoEpisodeMarker => "episode",
+            ParseError::NotAFile | ParseError::NoFileName | ParseError::NonUtf8Name => {
+                "usable file name"
+            }
+            ParseError::Inaccessible(_) => "access",
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::NotAFile => write!(f, "not a regular file"),
+            ParseError::NoFileName => write!(f, "path has no file name"),
+            ParseError::NonUtf8Name => {
+                write!(f, "name is not valid UTF-8 and cannot be rebuilt safely")
+            }
+            ParseError::EmptyShowName => write!(f, "the show name is empty"),
+            ParseError::MissingShowName => write!(f, "no show name could be determined"),
+            ParseError::MissingSeason => write!(f, "no season number could be determined"),
+            ParseError::NoEpisodeMarker => write!(f, "no episode marker (Exx) was found"),
+            ParseError::UnparseableSeason(input) => {
+                write!(f, "could not parse season number '{}'", input)
+            }
+            ParseError::Inaccessible(error) => write!(f, "cannot be accessed ({})", error),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// How the planned renames are applied to the filesystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Rename files in place inside their current directory. The default, and the only mode
+    /// that never writes outside the scanned directory. `--out-dir` is rejected in this mode.
+    Rename,
+    /// Copy files to their new names, leaving the originals untouched. Copies go into
+    /// `--out-dir` when given, otherwise next to the originals.
+    Copy,
+    /// Move files into `--out-dir` under their new names. Requires `--out-dir`, so a plain
+    /// in-place rename can never be mistaken for a move.
+    Move,
+    /// Create hard links under the new names, leaving the originals untouched. Links go into
+    /// `--out-dir` when given, otherwise next to the originals.
+    Link,
+}
+
+impl Mode {
+    /// Parses a `--mode` value.
+    pub fn parse(value: &str) -> Result<Mode, String> {
+        match value.to_lowercase().as_str() {
+            "rename" => Ok(Mode::Rename),
+            "copy" => Ok(Mode::Copy),
+            "move" => Ok(Mode::Move),
+            "link" => Ok(Mode::Link),
+            other => Err(format!(
+                "Unknown mode '{}' (expected rename, copy, move or link)",
+                other
+            )),
+        }
+    }
+
+    /// Whether the source file disappears once the action succeeds.
+    fn removes_source(self) -> bool {
+        matches!(self, Mode::Rename | Mode::Move)
+    }
+
+    fn verb(self) -> &'static str {
+        match self {
+            Mode::Rename => "renaming",
+            Mode::Copy => "copying",
+            Mode::Move => "moving",
+            Mode::Link => "linking",
+        }
+    }
+
+    fn past_tense(self) -> &'static str {
+        match self {
+            Mode::Rename => "Renamed",
+            Mode::Copy => "Copied",
+            Mode::Move => "Moved",
+            Mode::Link => "Linked",
+        }
+    }
+}
+
+/// Filesystem naming rules used to decide which characters are illegal in new names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsType {
+    /// Detect from the destination's mount; falls back to `Windows` when detection isn't possible.
+    Auto,
+    /// Only `/` (and NUL) are illegal.
+    Posix,
+    /// FAT/exFAT/NTFS/SMB rules: `< > : " / \ | ? *` and control characters are illegal.
+    Windows,
+}
+
+impl FsType {
+    /// Parses a `--target-fs` value.
+    pub fn parse(value: &str) -> Result<FsType, String> {
+        match value.to_lowercase().as_str() {
+            "auto" => Ok(FsType::Auto),
+            "posix" => Ok(FsType::Posix),
+            "windows" => Ok(FsType::Windows),
+            other => Err(format!(
+                "Unknown filesystem type '{}' (expected auto, posix or windows)",
+                other
+            )),
+        }
+    }
+
+    /// The filesystem type that applies to `dir`, detecting it for `Auto`.
+    fn resolve(self, dir: &Path) -> FsType {
+        match self {
+            FsType::Auto => detect_fs_type(dir),
+            fs_type => fs_type,
+        }
+    }
+
+    fn is_illegal(self, c: char) -> bool {
+        match self {
+            FsType::Posix => c == '/' || c == '\0',
+            FsType::Windows | FsType::Auto => {
+                matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*') || c.is_control()
+            }
+        }
+    }
+}
+
+/// Where preserved provider ID tags are placed in the new name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdPosition {
+    AfterShow, // "Show.{tvdb-78901}.S01E01..." (the Plex/Jellyfin convention)
+    End,       // "Show.S01E01.remainder.{tvdb-78901}"
+}
+
+/// How much the run prints. Errors and the closing summary are printed at every level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,   // Errors and the closing summary only
+    Normal,  // Progress, warnings and the proposed renames
+    Verbose, // Also how each file was parsed and why it was left alone
+}
+
+impl Verbosity {
+    fn current() -> Verbosity {
+        match VERBOSITY.load(Ordering::Relaxed) {
+            0 => Verbosity::Quiet,
+            1 => Verbosity::Normal,
+            _ => Verbosity::Verbose,
+        }
+    }
+}
+
+/// One `--episodes` selector. Episodes are compared as (season, episode) pairs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EpisodeFilter {
+    Season(u32),                   // "S02"
+    Range((u32, u32), (u32, u32)), // "S01E05-S01E08" (inclusive); "S01E05" is a range of one
+}
+
+impl EpisodeFilter {
+    /// Whether the selector covers episode `episode` of season `season`.
+    pub fn matches(self, season: u32, episode: u32) -> bool {
+        match self {
+            EpisodeFilter::Season(s) => s == season,
+            EpisodeFilter::Range(start, end) => (start..=end).contains(&(season, episode)),
+        }
+    }
+}
+
+/// How a `&` in a show name or title is written in the new name. It always becomes a
+/// dot-separated word of its own, so "Tom&Jerry" and "Tom & Jerry" come out alike.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ampersand {
+    Keep, // "Tom.&.Jerry"
+    And,  // "Tom.and.Jerry"
+    Plus, // "Tom.+.Jerry"
+}
+
+impl Ampersand {
+    /// Parses an `--ampersand` value.
+    pub fn parse(value: &str) -> Result<Ampersand, String> {
+        match value.to_lowercase().as_str() {
+            "keep" => Ok(Ampersand::Keep),
+            "and" => Ok(Ampersand::And),
+            "plus" => Ok(Ampersand::Plus),
+            other => Err(format!(
+                "Unknown ampersand handling '{}' (expected keep, and or plus)",
+                other
+            )),
+        }
+    }
+
+    /// Rewrites every `&` in `text` as a separate word, ready for separator normalization.
+    fn apply(self, text: &str) -> String {
+        let word = match self {
+            Ampersand::Keep => " & ",
+            Ampersand::And => " and ",
+            Ampersand::Plus => " + ",
+        };
+        text.replace('&', word)
+    }
+}
+
+/// Zero-padding of the season and episode numbers in new names (`--pad`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    Width(usize), // At least this many digits: "E009" for 3
+    Auto,         // Enough digits for the batch's largest number, at least 2
+}
+
+impl Padding {
+    /// Parses a `--pad` value: a width from 1 to 4, or "auto".
+    pub fn parse(value: &str) -> Result<Padding, String> {
+        match value.to_lowercase().as_str() {
+            "auto" => Ok(Padding::Auto),
+            other => match other.parse() {
+                Ok(width @ 1..=4) => Ok(Padding::Width(width)),
+                _ => Err(format!(
+                    "Invalid padding '{}' (expected a width from 1 to 4, or auto)",
+                    value
+                )),
+            },
+        }
+    }
+
+    /// The season and episode widths for a batch, so every file in it shares one width.
+    fn widths(self, infos: &[ParsedInfo]) -> (usize, usize) {
+        match self {
+            Padding::Width(width) => (width, width),
+            Padding::Auto => {
+                let digits = |n: u32| n.to_string().len();
+                let season = infos
+                    .iter()
+                    .filter_map(|info| info.season_prefix_part.as_deref().and_then(season_number))
+                    .map(digits)
+                    .max();
+                let episode = infos
+                    .iter()
+                    .filter_map(|info| info.episode_number_part.as_deref())
+                    .flat_map(episode_numbers)
+                    .map(digits)
+                    .max();
+                (season.unwrap_or(0).max(2), episode.unwrap_or(0).max(2))
+            }
+        }
+    }
+}
+
+/// Whether the proposed-renames table highlights what changes (`--color`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,   // When stdout is a terminal and NO_COLOR isn't set
+    Always, // Even when piped
+    Never,  // Plain text
+}
+
+impl ColorChoice {
+    /// Parses a `--color` value.
+    pub fn parse(value: &str) -> Result<ColorChoice, String> {
+        match value.to_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            other => Err(format!(
+                "Unknown color setting '{}' (expected auto, always or never)",
+                other
+            )),
+        }
+    }
+
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Order of the proposed-renames table and of the actions applied (`--sort`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Old,     // By original path
+    New,     // By new path
+    Episode, // By the new name's season and episode numbers, so E2 comes before E10
+}
+
+impl SortOrder {
+    /// Parses a `--sort` value.
+    pub fn parse(value: &str) -> Result<SortOrder, String> {
+        match value.to_lowercase().as_str() {
+            "old" => Ok(SortOrder::Old),
+            "new" => Ok(SortOrder::New),
+            "episode" => Ok(SortOrder::Episode),
+            other => Err(format!(
+                "Unknown sort order '{}' (expected old, new or episode)",
+                other
+            )),
+        }
+    }
+
+    /// Sorts planned (old, new) pairs. Names without an SxxExx tag follow those with one: first
+    /// the ones with an Exx tag alone, then the rest, by new path.
+    fn sort(self, renames: &mut [(&PathBuf, &PathBuf)]) {
+        match self {
+            SortOrder::Old => renames.sort_by_key(|(old, _)| *old),
+            SortOrder::New => renames.sort_by_key(|(old, new)| (*new, *old)),
+            SortOrder::Episode => renames.sort_by_cached_key(|(old, new)| {
+                let name = display_name(new).into_owned();
+                let numbers = match SE_RE.captures(&name) {
+                    Some(caps) => (
+                        0,
+                        caps[1].parse().unwrap_or(0),
+                        caps[2].parse().unwrap_or(0),
+                    ),
+                    None => find_e_tag(&name).map_or((2, 0, 0), |(_, episode)| (1, 0, episode)),
+                };
+                (numbers, (*new).clone(), (*old).clone())
+            }),
+        }
+    }
+}
+
+/// Rewrites every number in a formatted tag ("S01", "E01E02", "E01-E03") with `width` digits.
+fn pad_tag(tag: &str, width: usize) -> String {
+    DIGITS_RE
+        .replace_all(tag, |caps: &regex::Captures| {
+            let digits = caps.get(0).unwrap();
+            if tag[..digits.start()].ends_with('.') {
+                return digits.as_str().to_string(); // The ".5" of "E02.5"
+            }
+            let number: u32 = caps[0].parse().unwrap_or(0);
+            format!("{:0width$}", number, width = width)
+        })
+        .into_owned()
+}
+
+/// How the show name is cased in the new name (`--case`). `Lower` and `Upper` apply to the
+/// remainder as well; the SxxExx tag always stays uppercase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    Title, // "Breaking.Bad", with the casing exceptions and acronyms of words.toml
+    Lower, // "breaking.bad"
+    Upper, // "BREAKING.BAD"
+    Keep,  // As written in the source (or typed at the prompt)
+}
+
+impl Case {
+    /// Parses a `--case` value.
+    pub fn parse(value: &str) -> Result<Case, String> {
+        match value.to_lowercase().as_str() {
+            "title" => Ok(Case::Title),
+            "lower" => Ok(Case::Lower),
+            "upper" => Ok(Case::Upper),
+            "keep" => Ok(Case::Keep),
+            other => Err(format!(
+                "Unknown case '{}' (expected title, lower, upper or keep)",
+                other
+            )),
+        }
+    }
+
+    /// Renders the cleaned, lowercase `show`; `source` is the same name in its original casing.
+    fn show_name(self, show: &str, source: Option<&str>, title_words: &TitleWords) -> String {
+        match self {
+            Case::Title => {
+                restore_source_acronyms(&capitalize_title_case(show, title_words), source)
+            }
+            Case::Lower => show.to_string(),
+            Case::Upper => show.to_uppercase(),
+            Case::Keep => source.unwrap_or(show).to_string(),
+        }
+    }
+}
+
+/// Video extensions from `--ext` or `RENAME_EPISODES_EXTS`, or sidecar extensions from
+/// `--sidecar-ext`: "mkv,mp4,m4v" replaces the built-in list, "+m4v,divx" adds to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionList {
+    pub extend_defaults: bool,
+    pub extensions: Vec<String>, // Lowercase, without a leading dot
+}
+
+impl ExtensionList {
+    /// Parses an `--ext` (or `RENAME_EPISODES_EXTS`) or `--sidecar-ext` value.
+    pub fn parse(value: &str) -> Result<ExtensionList, String> {
+        let (extend_defaults, list) = match value.trim().strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, value),
+        };
+        let extensions: Vec<String> = list
+            .split(',')
+            .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+            .filter(|ext| !ext.is_empty())
+            .collect();
+        if extensions.is_empty() {
+            return Err("the extension list is empty".to_string());
+        }
+        Ok(ExtensionList {
+            extend_defaults,
+            extensions,
+        })
+    }
+}
+
+/// Command-line options. Defaults reproduce the original interactive, in-place behavior.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub show_help: bool,
+    pub mode: Mode,
+    pub out_dir: Option<PathBuf>,
+    pub summary_json: bool,
+    pub summary_out: Option<PathBuf>, // Implies summary_json
+    pub profile_timing: bool,
+    pub target_fs: FsType,
+    pub illegal_replacement: String, // Substitute for illegal characters without a specific mapping
+    pub illegal_map: HashMap<char, String>,
+    pub group_episodes_into_ranges: bool, // Report present episodes per show/season instead of renaming
+    pub report_duplicates: bool, // Report episodes present in more than one file instead of renaming
+    pub gaps: bool, // Also list episodes missing between the first and last of each season
+    pub check: bool, // Only verify every file already has its canonical name; exit 1 if not
+    pub emit_script: bool, // Print the actions as a shell script instead of applying them
+    pub specials_folder_name: String, // How season 0 is labelled as a folder ("Specials", "Season 00", ...)
+    pub preview_open: bool, // Page the proposed-renames table when it overflows the terminal
+    pub mark_processed: bool, // Drop a marker in the folder after a clean run
+    pub skip_processed: bool, // Skip folders whose marker is still current
+    pub confirm_threshold: usize, // Batches larger than this need the confirmation word
+    pub confirm_word: Option<String>, // Word required above the threshold; defaults to the mode name in capitals
+    pub keep_ids: bool, // Preserve provider ID tags verbatim instead of cleaning them into the name
+    pub id_position: IdPosition,
+    pub dry_run_interactive: bool, // Review the numbered plan and deselect entries before one apply
+    pub dry_run: bool, // Print the plan and run conflict checks without touching the filesystem
+    pub report_unrenamable: bool, // List near-miss files that could not be given a valid name
+    pub directory: Option<PathBuf>, // Positional target directory; the current directory when absent
+    pub recursive: bool, // Also scan subdirectories; symlinked directories are never followed
+    pub newer_than: Option<Duration>, // Only process videos modified within this long
+    pub max_depth: Option<usize>, // Folder levels --recursive scans, 1 being the scanned folder alone
+    pub case_insensitive: bool, // Check targets for case-only clashes even where that isn't detected
+    pub sort: SortOrder,        // Order of the proposed-renames table and of the actions
+    pub color: ColorChoice,     // Highlighting of what changes in the proposed-renames table
+    pub retries: u32,           // Extra attempts for an action failing with a transient error
+    pub atomic: bool,           // On the first failed action, revert the ones already applied
+    pub subtitle_fallback: bool, // Borrow season/episode from a parsable sidecar subtitle
+    pub read_nfo: bool, // Take show/season/episode from a video's Kodi .nfo sidecar over its name
+    pub episode_filters: Vec<EpisodeFilter>, // Only process these episodes; empty means all
+    pub nfc_only: bool, // Only renormalize file names to NFC, changing nothing else
+    pub template: Option<String>, // Output naming template; the dot format when absent
+    pub ampersand: Ampersand, // How "&" is written in new names
+    pub undo: bool,     // Revert the most recent run recorded in the undo log
+    pub assume_yes: bool, // Apply without the confirmation prompt; files needing input are an error
+    pub extensions: Option<ExtensionList>, // Overrides the built-in video extensions
+    pub max_parallel_io: usize, // Concurrency limit for directory reads and metadata calls while scanning
+    pub rename_sidecars: bool,  // Rename subtitle sidecars along with their video
+    pub sidecar_extensions: Option<ExtensionList>, // Overrides the built-in sidecar extensions
+    pub title_words: TitleWords, // Casing exceptions and acronyms, from words.toml when present
+    pub show_aliases: ShowAliases, // Show-name variants mapped to one canonical name, from aliases.toml
+    pub canonical_tags: bool, // Write known release tags (1080p, WEB-DL, x264) in canonical casing
+    pub strip_remainder: bool, // Drop the remainder after SxxExx (Part.N and ID tags stay)
+    pub absolute: bool, // Parse "Show - 135" as an absolute episode number when there's no SxxExx
+    pub absolute_season: Option<u32>, // Season given to absolute-numbered episodes; none when absent
+    pub json: bool, // Print the plan (and results, with --yes) as JSON on stdout; never confirm
+    pub case: Case, // Casing of the show name (and, for lower/upper, the remainder)
+    pub separator: char, // Between the words and parts of new names: '.', ' ', '_' or '-'
+    pub interactive: bool, // Accept, edit or skip each proposed rename before the confirmation
+    pub verbosity: Verbosity, // --quiet or --verbose
+    pub force: bool, // Ask to overwrite existing targets instead of aborting on them as conflicts
+    pub progress: bool, // Show file counters while scanning and applying, even when not on a terminal
+    pub pad: Padding,   // Digits in season and episode numbers
+    pub include_samples: bool, // Treat files named like sample clips as episodes too
+    pub organize: bool, // Put each file in a "Season NN" folder under its destination
+    pub rename_dirs: bool, // Also normalize the names of the season and show folders holding the files
+    pub keep_remainder_case: bool, // Keep the remainder's casing from the source instead of lowercasing it
+    pub pattern: Option<Regex>,    // Custom filename pattern, tried before the built-in tag rules
+    pub lowercase_ext: bool, // Write the extension in lowercase instead of keeping the original case
+    pub show_name: Option<String>, // --name: show for files whose names lack one, instead of prompting
+    pub season: Option<u32>, // --season: season for files whose names lack one, instead of prompting
+    pub force_name: bool,    // Use --name for every file, even those naming their own show
+    pub plan_source: Option<PathBuf>, // --plan: the file to read the plan from, "-" for stdin
+    pub plan: Option<Vec<(PathBuf, PathBuf)>>, // The plan read from it: (old, new) pairs; no parsing
+    pub infer_show: bool, // Give files without a show the one most files in their folder name
+    pub keep_suffix: bool, // Keep trailing release-group suffixes ("-RARBG", "-[EZTV]") in the name
+    pub suffix_groups: Vec<String>, // Groups stripped as a bare trailing "-GROUP"
+    pub title_command: Option<String>, // Command printing an episode's title, given show, season and episode
+    pub show_from_level: usize, // Folder level (1 = the file's own folder) giving the show prompt's default
+    pub season_from_level: usize, // Folder level giving the season prompt's default
+}
+
+/// Release groups stripped by default when they close a name as "-GROUP".
+pub const DEFAULT_SUFFIX_GROUPS: [&str; 8] = [
+    "RARBG", "EZTV", "EZTVx.to", "ETTV", "TGx", "YTS", "YIFY", "GalaxyTV",
+];
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            show_help: false,
+            mode: Mode::Rename,
+            out_dir: None,
+            summary_json: false,
+            summary_out: None,
+            profile_timing: false,
+            target_fs: FsType::Auto,
+            illegal_replacement: String::new(),
+            illegal_map: HashMap::new(),
+            group_episodes_into_ranges: false,
+            report_duplicates: false,
+            gaps: false,
+            check: false,
+            emit_script: false,
+            specials_folder_name: "Specials".to_string(),
+            preview_open: false,
+            mark_processed: false,
+            skip_processed: false,
+            confirm_threshold: 100,
+            confirm_word: None,
+            keep_ids: false,
+            id_position: IdPosition::AfterShow,
+            dry_run_interactive: false,
+            dry_run: false,
+            report_unrenamable: false,
+            directory: None,
+            recursive: false,
+            subtitle_fallback: false,
+            read_nfo: true,
+            episode_filters: Vec::new(),
+            nfc_only: false,
+            template: None,
+            ampersand: Ampersand::Keep,
+            undo: false,
+            assume_yes: false,
+            extensions: None,
+            max_parallel_io: 1,
+            rename_sidecars: true,
+            sidecar_extensions: None,
+            title_words: TitleWords::default(),
+            show_aliases: ShowAliases::default(),
+            canonical_tags: true,
+            strip_remainder: false,
+            absolute: false,
+            absolute_season: None,
+            json: false,
+            case: Case::Title,
+            separator: '.',
+            interactive: false,
+            verbosity: Verbosity::Normal,
+            force: false,
+            progress: false,
+            pad: Padding::Width(2),
+            include_samples: false,
+            organize: false,
+            rename_dirs: false,
+            keep_remainder_case: false,
+            pattern: None,
+            lowercase_ext: false,
+            show_name: None,
+            season: None,
+            force_name: false,
+            plan_source: None,
+            plan: None,
+            infer_show: false,
+            keep_suffix: false,
+            newer_than: None,
+            max_depth: None,
+            case_insensitive: false,
+            sort: SortOrder::Old,
+            retries: 1,
+            atomic: false,
+            color: ColorChoice::Auto,
+            suffix_groups: DEFAULT_SUFFIX_GROUPS
+                .iter()
+                .map(|g| g.to_string())
+                .collect(),
+            title_command: None,
+            show_from_level: 2,
+            season_from_level: 1,
+        }
+    }
+}
+
+impl Options {
+    /// Whether the run stops after planning: `--dry-run`, or `--json` without `--yes`, which has
+    /// no confirmation prompt to answer.
+    fn plan_only(&self) -> bool {
+        self.dry_run || self.emit_script || (self.json && !self.assume_yes)
+    }
+
+    /// How many folder levels a scan reads: 1 (the scanned folder alone) without `--recursive`,
+    /// otherwise `--max-depth` or no limit.
+    fn scan_depth(&self) -> usize {
+        match (self.recursive, self.max_depth) {
+            (false, _) => 1,
+            (true, depth) => depth.unwrap_or(usize::MAX),
+        }
+    }
+
+    /// Whether `--name` and `--season` supply everything the file's name lacked, so its folder
+    /// needs no prompts.
+    fn fills_in(&self, info: &ParsedInfo) -> bool {
+        (info.show_name_part.is_some() || self.show_name.is_some())
+            && (info.season_prefix_part.is_some() || self.season.is_some() || self.absolute)
+            && info.episode_number_part.is_some()
+    }
+}
+
+/// Parses a `c=replacement,c=replacement` list into a character map.
+pub fn parse_char_map(value: &str) -> Result<HashMap<char, String>, String> {
+    let mut map = HashMap::new();
+    for entry in value.split(',').filter(|e| !e.is_empty()) {
+        let mut chars = entry.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), Some('=')) => {
+                map.insert(c, chars.as_str().to_string());
+            }
+            _ => {
+                return Err(format!(
+                    "Invalid character mapping '{}' (expected e.g. ':=-')",
+                    entry
+                ));
+            }
+        }
+    }
+    Ok(map)
+}
+
+/// Compiles a `--pattern` regex. It must have an `episode` group and may have `show`, `season`
+/// and `remainder` groups; any other named group is a mistake worth reporting.
+pub fn parse_pattern(value: &str) -> Result<Regex, String> {
+    let regex = Regex::new(value).map_err(|e| format!("Invalid --pattern: {}", e))?;
+    let names: Vec<&str> = regex.capture_names().flatten().collect();
+    if let Some(unknown) = names
+        .iter()
+        .find(|name| !["show", "season", "episode", "remainder"].contains(name))
+    {
+        return Err(format!(
+            "Unknown group '{}' in --pattern (expected show, season, episode or remainder)",
+            unknown
+        ));
+    }
+    if !names.contains(&"episode") {
+        return Err(
+            "--pattern needs a named 'episode' group, e.g. (?P<episode>[0-9]+)".to_string(),
+        );
+    }
+    Ok(regex)
+}
+
+/// Parses an `--episodes` list such as "S01E05,S01E07-S01E09,S03". The end of a range may
+/// omit its season ("S01E05-E08").
+pub fn parse_episode_filters(value: &str) -> Result<Vec<EpisodeFilter>, String> {
+    let invalid = |spec: &str| {
+        format!(
+            "Invalid --episodes selector '{}' (expected e.g. S01E05, S01E05-S01E08 or S02)",
+            spec
+        )
+    };
+    let endpoint = |text: &str| -> Option<(u32, Option<u32>)> {
+        let caps = EPISODE_SPEC_RE.captures(text.trim())?;
+        let season = caps[1].parse().ok()?;
+        let episode = caps.get(2).and_then(|e| e.as_str().parse().ok());
+        Some((season, episode))
+    };
+
+    let mut filters = Vec::new();
+    for spec in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let filter = match spec.split_once('-') {
+            None => match endpoint(spec).ok_or_else(|| invalid(spec))? {
+                (season, None) => EpisodeFilter::Season(season),
+                (season, Some(episode)) => {
+                    EpisodeFilter::Range((season, episode), (season, episode))
+                }
+            },
+            Some((from, to)) => {
+                let (from_season, from_episode) = endpoint(from).ok_or_else(|| invalid(spec))?;
+                let from_episode = from_episode.ok_or_else(|| invalid(spec))?;
+                let to = to.trim();
+                let (to_season, to_episode) = if to.to_uppercase().starts_with('E') {
+                    endpoint(&format!("S{}{}", from_season, to)).ok_or_else(|| invalid(spec))?
+                } else {
+                    endpoint(to).ok_or_else(|| invalid(spec))?
+                };
+                let to_episode = to_episode.ok_or_else(|| invalid(spec))?;
+                if (to_season, to_episode) < (from_season, from_episode) {
+                    return Err(format!("--episodes range '{}' ends before it starts", spec));
+                }
+                EpisodeFilter::Range((from_season, from_episode), (to_season, to_episode))
+            }
+        };
+        filters.push(filter);
+    }
+    if filters.is_empty() {
+        return Err("--episodes needs at least one selector".to_string());
+    }
+    Ok(filters)
+}
+
+/// Result of one planned action, as recorded in the run summary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActionStatus {
+    NotApplied, // Planned, but the run stopped before applying it (conflicts or cancelled)
+    Done,
+    Failed(String),
+    RolledBack, // Applied, then reverted by --atomic because another action failed
+}
+
+/// One planned rename (or copy, move or link) and what became of it.
+#[derive(Debug, Clone)]
+pub struct ActionRecord {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub conflict: bool, // Target collides with an existing file or another action's target
+    pub overwrite: bool, // --force: the existing file at the target is replaced
+    pub status: ActionStatus,
+}
+
+impl ActionRecord {
+    /// The record as a JSON object; `--json` output also says whether it conflicts.
+    pub fn to_json(&self, include_conflict: bool) -> String {
+        let path_json = |p: &Path| json_string(&p.to_string_lossy());
+        let (status, error) = match &self.status {
+            ActionStatus::NotApplied => ("not_applied", "null".to_string()),
+            ActionStatus::Done => ("done", "null".to_string()),
+            ActionStatus::Failed(e) => ("failed", json_string(e)),
+            ActionStatus::RolledBack => ("rolled_back", "null".to_string()),
+        };
+        let conflict = if include_conflict {
+            format!(", \"conflict\": {}", self.conflict)
+        } else {
+            String::new()
+        };
+        format!(
+            "{{\"from\": {}, \"to\": {}{}, \"status\": \"{}\", \"error\": {}}}",
+            path_json(&self.from),
+            path_json(&self.to),
+            conflict,
+            status,
+            error
+        )
+    }
+}
+
+/// Outcome of a run, accumulated as it progresses and written by `--summary-json`.
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    pub target_directory: Option<PathBuf>,
+    pub outcome: &'static str,
+    pub found: usize,                          // Video files parsed
+    pub correct: usize,                        // Already carrying their final name
+    pub needs_input: usize,                    // Missing show or season in the filename
+    pub skipped: usize,                        // Unparseable, or no valid name could be formed
+    pub skip_reasons: BTreeMap<String, usize>, // Skips by what was missing, for the closing report
+    pub renamed: usize,
+    pub failed: usize,
+    pub conflicts: usize,
+    pub actions: Vec<ActionRecord>,
+    pub unrenamable: Vec<(String, String)>, // (file name, missing component) for near-miss skips
+    pub needs_review: Vec<String>, // Files carrying two season/episode tags, left untouched
+    pub mixed_show_names: Vec<(String, Vec<String>)>, // (folder, show names) where files disagree
+    pub timings: Vec<(&'static str, Duration)>,
+}
+
+/// Quotes and escapes a string for inclusion in JSON output.
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl RunSummary {
+    /// Counts a skipped file under `reason` ("no show name", "needs manual review", ...).
+    fn count_skip(&mut self, reason: &str) {
+        self.skipped += 1;
+        *self.skip_reasons.entry(reason.to_string()).or_default() += 1;
+    }
+
+    /// Prints what the run leaves for the user: files flagged for review, near misses when
+    /// `--report-unrenamable` asked for them, and the closing tally.
+    pub fn print_closing_report(&self, options: &Options) {
+        if !self.needs_review.is_empty() {
+            report!("\nFiles flagged for manual review (more than one season/episode tag):");
+            report!("--------------------");
+            for file in &self.needs_review {
+                report!("{}", file);
+            }
+            report!("--------------------");
+        }
+
+        if options.report_unrenamable && !self.unrenamable.is_empty() {
+            report!("\nFiles that could not be renamed (missing component):");
+            report!("--------------------");
+            for (file, missing) in &self.unrenamable {
+                report!("{} (missing {})", file, missing);
+            }
+            report!("--------------------");
+        }
+
+        if self.found > 0 {
+            self.print_report(options);
+        }
+    }
+
+    /// Prints the closing tally of what happened to the files found.
+    fn print_report(&self, options: &Options) {
+        // --normalize-to-utf8nfc-only looks at every file, not just the videos
+        let found_label = if options.nfc_only {
+            "Files checked"
+        } else {
+            "Files found"
+        };
+        let mut rows: Vec<(String, usize)> = vec![
+            (found_label.to_string(), self.found),
+            ("Already correct".to_string(), self.correct),
+            ("Planned".to_string(), self.actions.len()), // Sidecars included
+        ];
+        if self.outcome == "completed" {
+            rows.push((options.mode.past_tense().to_string(), self.renamed));
+        }
+        for (reason, count) in &self.skip_reasons {
+            rows.push((format!("Skipped ({})", reason), *count));
+        }
+        rows.push(("Conflicts".to_string(), self.conflicts));
+        rows.push(("Failed".to_string(), self.failed));
+        let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+
+        report!("\nSummary:");
+        report!("--------------------");
+        for (label, count) in &rows {
+            report!("{:<width$}  {:>5}", label, count, width = width);
+        }
+        report!("--------------------");
+    }
+
+    /// Counts a Pass 2 skip. Files that carried a season or episode tag are near misses and are
+    /// also remembered, with what was missing, for `--report-unrenamable`.
+    fn note_skip(&mut self, info: &ParsedInfo, missing: &str) {
+        self.count_skip(&format!("no {}", missing));
+        if info.season_prefix_part.is_some() || info.episode_number_part.is_some() {
+            self.unrenamable
+                .push((info.original_filename.clone(), missing.to_string()));
+        }
+    }
+
+    /// The planned actions as the JSON array printed by `--json`.
+    pub fn actions_json(&self) -> String {
+        let actions: Vec<String> = self
+            .actions
+            .iter()
+            .map(|action| format!("  {}", action.to_json(true)))
+            .collect();
+        if actions.is_empty() {
+            "[]\n".to_string()
+        } else {
+            format!("[\n{}\n]\n", actions.join(",\n"))
+        }
+    }
+
+    /// The summary as the JSON document written by `--summary-json`.
+    pub fn to_json(&self, include_timings: bool, include_unrenamable: bool) -> String {
+        let path_json = |p: &Path| json_string(&p.to_string_lossy());
+        let mut out = String::from("{\n");
+        out.push_str(&format!(
+            "  \"target_directory\": {},\n",
+            self.target_directory
+                .as_deref()
+                .map_or("null".to_string(), path_json)
+        ));
+        out.push_str(&format!("  \"outcome\": {},\n", json_string(self.outcome)));
+        out.push_str(&format!(
+            "  \"counts\": {{\"found\": {}, \"correct\": {}, \"needs_input\": {}, \"skipped\": {}, \"renamed\": {}, \"failed\": {}, \"conflicts\": {}}},\n",
+            self.found,
+            self.correct,
+            self.needs_input,
+            self.skipped,
+            self.renamed,
+            self.failed,
+            self.conflicts
+        ));
+        if include_timings {
+            let timings: Vec<String> = self
+                .timings
+                .iter()
+                .map(|(phase, elapsed)| {
+                    format!("\"{}\": {:.3}", phase, elapsed.as_secs_f64() * 1000.0)
+                })
+                .collect();
+            out.push_str(&format!("  \"timings_ms\": {{{}}},\n", timings.join(", ")));
+        }
+        let review: Vec<String> = self.needs_review.iter().map(|f| json_string(f)).collect();
+        out.push_str(&format!("  \"needs_review\": [{}],\n", review.join(", ")));
+        if include_unrenamable {
+            let entries: Vec<String> = self
+                .unrenamable
+                .iter()
+                .map(|(file, missing)| {
+                    format!(
+                        "{{\"file\": {}, \"missing\": {}}}",
+                        json_string(file),
+                        json_string(missing)
+                    )
+                })
+                .collect();
+            out.push_str(&format!("  \"unrenamable\": [{}],\n", entries.join(", ")));
+        }
+        out.push_str("  \"actions\": [");
+        for (index, action) in self.actions.iter().enumerate() {
+            out.push_str(if index == 0 { "\n    " } else { ",\n    " });
+            out.push_str(&action.to_json(false));
+        }
+        out.push_str(if self.actions.is_empty() {
+            "]\n"
+        } else {
+            "\n  ]\n"
+        });
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Works out which naming rules apply to `dir` from the filesystem it is mounted on.
+/// Uses /proc/mounts where available; anything undetectable gets the restrictive Windows rules.
+fn detect_fs_type(dir: &Path) -> FsType {
+    if cfg!(windows) {
+        return FsType::Windows;
+    }
+    // The destination may not exist yet (--out-dir); resolve through its nearest existing ancestor.
+    let Some(resolved) = dir.ancestors().find_map(|p| fs::canonicalize(p).ok()) else {
+        return FsType::Windows;
+    };
+    let Ok(mounts) = fs::read_to_string("/proc/mounts") else {
+        return FsType::Windows;
+    };
+
+    // The longest mount point containing the directory is the filesystem it lives on.
+    let fs_name = mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount_point = fields.nth(1)?.replace("\\040", " ");
+            let fs_name = fields.next()?;
+            resolved
+                .starts_with(&mount_point)
+                .then(|| (mount_point.len(), fs_name.to_string()))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, fs_name)| fs_name);
+
+    match fs_name.as_deref() {
+        Some(
+            "vfat" | "msdos" | "exfat" | "ntfs" | "ntfs3" | "fuseblk" | "cifs" | "smb3" | "smbfs",
+        ) => FsType::Windows,
+        Some(_) => FsType::Posix,
+        None => FsType::Windows,
+    }
+}
+
+/// Replaces characters that are illegal under `fs_type` in a new filename, using the per-character
+/// map first and the default replacement otherwise. Dots doubled up by removals are collapsed.
+pub fn sanitize_filename(name: &str, fs_type: FsType, options: &Options) -> String {
+    let mut sanitized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if fs_type.is_illegal(c) {
+            sanitized.push_str(
+                options
+                    .illegal_map
+                    .get(&c)
+                    .unwrap_or(&options.illegal_replacement),
+            );
+        } else {
+            sanitized.push(c);
+        }
+    }
+    if sanitized == name {
+        return sanitized;
+    }
+    while sanitized.contains("..") {
+        sanitized = sanitized.replace("..", ".");
+    }
+    sanitized.trim_matches('.').to_string()
+}
+
+/// Name of the marker file recording that a folder was processed.
+const PROCESSED_MARKER: &str = ".rename-episodes-processed";
+
+/// Sorted names of the video files directly inside `dir`; the fingerprint a processed marker
+/// is checked against.
+fn video_file_names(dir: &Path, video_extensions: &HashSet<String>) -> io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(OsStr::to_str)
+                    .is_some_and(|ext| video_extensions.contains(&ext.to_lowercase()))
+        })
+        .map(|path| display_name(&path).into_owned())
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Writes the processed marker for `dir`: a timestamp plus the current video file names.
+fn write_processed_marker(dir: &Path, video_extensions: &HashSet<String>) -> io::Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut contents = format!(
+        "# Written by rename-episodes; delete to force reprocessing.\nprocessed_at={}\n",
+        timestamp
+    );
+    for name in video_file_names(dir, video_extensions)? {
+        contents.push_str(&format!("file={}\n", name));
+    }
+    fs::write(dir.join(PROCESSED_MARKER), contents)
+}
+
+/// Returns the marker's timestamp if `dir` has a processed marker whose recorded file list
+/// still matches the folder, i.e. nothing was added, removed or renamed since.
+fn current_processed_marker(dir: &Path, video_extensions: &HashSet<String>) -> Option<u64> {
+    let contents = fs::read_to_string(dir.join(PROCESSED_MARKER)).ok()?;
+    let mut processed_at = None;
+    let mut recorded: Vec<&str> = Vec::new();
+    for line in contents.lines() {
+        if let Some(ts) = line.strip_prefix("processed_at=") {
+            processed_at = ts.parse().ok();
+        } else if let Some(name) = line.strip_prefix("file=") {
+            recorded.push(name);
+        }
+    }
+    let current = video_file_names(dir, video_extensions).ok()?;
+    (current == recorded).then_some(processed_at?)
+}
+
+/// Ignore file in the scanned directory: one glob per line, matched against file names.
+const IGNORE_FILE: &str = ".renameignore";
+
+/// Reads the glob patterns of `dir`'s ignore file, if any. Blank lines and `#` comments are
+/// skipped, as in .gitignore; a pattern that can't be compiled is reported and skipped.
+fn load_ignore_patterns(dir: &Path) -> Vec<Regex> {
+    let Ok(contents) = fs::read_to_string(dir.join(IGNORE_FILE)) else {
+        return Vec::new();
+    };
+    let mut patterns = Vec::new();
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match glob_to_regex(line) {
+            Ok(regex) => patterns.push(regex),
+            Err(e) => eprintln!(
+                "Warning: Ignoring pattern '{}' in {}: {}",
+                line, IGNORE_FILE, e
+            ),
+        }
+    }
+    patterns
+}
+
+/// Compiles a glob (`*`, `?` and `[...]` classes, `[!...]` negated) into an anchored regex.
+fn glob_to_regex(glob: &str) -> Result<Regex, regex::Error> {
+    let mut pattern = String::from("^");
+    let mut in_class = false;
+    for (index, c) in glob.char_indices() {
+        match c {
+            _ if in_class => {
+                in_class = c != ']';
+                match c {
+                    '!' if glob[..index].ends_with('[') => pattern.push('^'),
+                    '\\' => pattern.push_str("\\\\"),
+                    _ => pattern.push(c),
+                }
+            }
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '[' => {
+                in_class = true;
+                pattern.push('[');
+            }
+            _ => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern)
+}
+
+/// Undo log in the scanned directory: one JSON object per line, one line per applied run.
+const UNDO_LOG: &str = ".rename-episodes-undo.json";
+
+/// Appends one run's completed renames (or moves) to the undo log in `dir`.
+fn append_undo_log(dir: &Path, entries: &[(PathBuf, PathBuf)]) -> io::Result<()> {
+    let mut records: Vec<String> = Vec::new();
+    for (from, to) in entries {
+        match (from.to_str(), to.to_str()) {
+            (Some(from), Some(to)) => records.push(format!(
+                "{{\"from\": {}, \"to\": {}}}",
+                json_string(from),
+                json_string(to)
+            )),
+            _ => eprintln!(
+                "Warning: '{}' can't be recorded in the undo log (path is not valid UTF-8).",
+                from.display()
+            ),
+        }
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(UNDO_LOG))?;
+    writeln!(
+        log,
+        "{{\"time\": {}, \"renames\": [{}]}}",
+        timestamp,
+        records.join(", ")
+    )
+}
+
+/// Decodes every JSON string literal in `text`, in order. Enough to read back the undo log,
+/// whose only strings are keys and the paths written by `json_string`.
+fn json_strings(text: &str) -> Result<Vec<String>, String> {
+    let mut strings = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            strings.push(json_string_body(&mut chars)?);
+        }
+    }
+    Ok(strings)
+}
+
+/// Decodes the rest of a JSON string literal whose opening quote has been read.
+fn json_string_body(chars: &mut impl Iterator<Item = char>) -> Result<String, String> {
+    let mut value = String::new();
+    loop {
+        match chars.next().ok_or("unterminated string")? {
+            '"' => return Ok(value),
+            '\\' => match chars.next().ok_or("unterminated escape")? {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let code = u32::from_str_radix(&hex, 16)
+                        .map_err(|_| format!("bad escape \\u{}", hex))?;
+                    value.push(char::from_u32(code).ok_or("bad escape")?);
+                }
+                other => value.push(other), // \" \\ \/
+            },
+            c => value.push(c),
+        }
+    }
+}
+
+/// Reads the members of a JSON plan object whose `{` has been read, returning its `from` and
+/// `to`. Other members (status, error, ...) are passed over; `number` counts objects from 1.
+fn json_plan_object(
+    chars: &mut Peekable<Chars<'_>>,
+    number: usize,
+) -> Result<(PathBuf, PathBuf), String> {
+    let mut from: Option<Option<String>> = None; // Some(None): present, but not a string
+    let mut to: Option<Option<String>> = None;
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some(',') => {}
+            Some(c) if c.is_whitespace() => {}
+            Some('"') => {
+                let key = json_string_body(chars)?;
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                if chars.next() != Some(':') {
+                    return Err(format!(
+                        "expected ':' after \"{}\" in object {}",
+                        key, number
+                    ));
+                }
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                let value = if chars.next_if_eq(&'"').is_some() {
+                    Some(json_string_body(chars)?)
+                } else {
+                    skip_json_value(chars)?;
+                    None
+                };
+                let slot = match key.as_str() {
+                    "from" => &mut from,
+                    "to" => &mut to,
+                    _ => continue,
+                };
+                if slot.is_some() {
+                    return Err(format!("object {} has more than one \"{}\"", number, key));
+                }
+                *slot = Some(value);
+            }
+            Some(c) => return Err(format!("unexpected '{}' in object {}", c, number)),
+            None => return Err("unterminated object".to_string()),
+        }
+    }
+    let string = |value: Option<Option<String>>, key: &str| match value {
+        Some(Some(path)) => Ok(PathBuf::from(path)),
+        Some(None) => Err(format!("\"{}\" of object {} is not a string", key, number)),
+        None => Err(format!("object {} has no \"{}\"", number, key)),
+    };
+    Ok((string(from, "from")?, string(to, "to")?))
+}
+
+/// Passes over a non-string JSON value (null, a number, a nested object, ...), stopping at the
+/// `,` or `}` that ends it.
+fn skip_json_value(chars: &mut Peekable<Chars<'_>>) -> Result<(), String> {
+    let mut depth = 0;
+    while let Some(&c) = chars.peek() {
+        match c {
+            ',' | '}' if depth == 0 => return Ok(()),
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth -= 1,
+            '"' => {
+                chars.next();
+                json_string_body(chars)?;
+                continue;
+            }
+            _ => {}
+        }
+        chars.next();
+    }
+    Err("unterminated object".to_string())
+}
+
+/// Parses a `--plan`: lines of `old<TAB>new`, or the JSON array `--json` prints (objects with
+/// `from` and `to`). Relative paths are taken relative to the scanned directory later.
+pub fn parse_plan(text: &str) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    if text.trim_start().starts_with(['[', '{']) {
+        let invalid = |e: String| format!("Invalid JSON plan: {}", e);
+        let mut pairs = Vec::new();
+        let mut chars = text.chars().peekable();
+        // Each object stands alone, with exactly one string `from` and one string `to`
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => pairs.push(json_plan_object(&mut chars, pairs.len() + 1).map_err(invalid)?),
+                '[' | ']' | ',' => {}
+                c if c.is_whitespace() => {}
+                c => return Err(invalid(format!("unexpected '{}' outside an object", c))),
+            }
+        }
+        return Ok(pairs);
+    }
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| match line.split_once('\t') {
+            Some((old, new)) if !old.is_empty() && !new.is_empty() => {
+                Ok((PathBuf::from(old), PathBuf::from(new)))
+            }
+            _ => Err(format!(
+                "Invalid plan line {}: expected 'old<TAB>new'",
+                index + 1
+            )),
+        })
+        .collect()
+}
+
+/// Parses one undo log line back into its (from, to) pairs.
+fn parse_undo_entry(line: &str) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let strings = json_strings(line)?;
+    let renames = strings
+        .iter()
+        .position(|s| s == "renames")
+        .ok_or("missing renames")?;
+    strings[renames + 1..]
+        .chunks(4)
+        .map(|chunk| match chunk {
+            [from_key, from, to_key, to] if from_key == "from" && to_key == "to" => {
+                Ok((PathBuf::from(from), PathBuf::from(to)))
+            }
+            _ => Err("malformed rename record".to_string()),
+        })
+        .collect()
+}
+
+/// Applies a single planned action according to the selected mode.
+fn apply_action(mode: Mode, old: &Path, new: &Path) -> io::Result<()> {
+    match mode {
+        Mode::Rename => fs::rename(old, new),
+        Mode::Copy => fs::copy(old, new).map(|_| ()),
+        Mode::Move => match fs::rename(old, new) {
+            // Different filesystem: fall back to copy + delete.
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                fs::copy(old, new)?;
+                fs::remove_file(old)
+            }
+            result => result,
+        },
+        Mode::Link => fs::hard_link(old, new),
+    }
+}
+
+/// Whether an action failing with `error` may succeed when tried again, as on network shares
+/// that briefly drop or lock files. Missing files and denied permissions fail straight away.
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::ResourceBusy
+            | io::ErrorKind::StaleNetworkFileHandle
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NetworkDown
+    )
+}
+
+/// Applies an action, trying it again up to `--retries` times after a transient error with a
+/// delay doubling from 200ms.
+fn apply_with_retries(options: &Options, old: &Path, new: &Path) -> io::Result<()> {
+    let mut delay = Duration::from_millis(200);
+    let mut attempt = 0;
+    loop {
+        match apply_action(options.mode, old, new) {
+            Err(e) if attempt < options.retries && is_transient(&e) => {
+                attempt += 1;
+                debug!(
+                    "Retrying '{}' in {}ms (attempt {} of {}): {}",
+                    display_name(old),
+                    delay.as_millis(),
+                    attempt,
+                    options.retries,
+                    e
+                );
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Normalizes separators in a segment: replaces spaces (and the `--separator` in use, so names
+/// already in that style parse alike) with dots and removes multiple dots, keeping the original
+/// casing. Leading/trailing dots are always dropped, so a segment of only separators (e.g. the
+/// ".." left between a show name and its SxxExx tag) becomes an empty string.
+pub fn normalize_separators(segment: &str, separator: char) -> String {
+    let mut cleaned = segment.trim().replace([' ', separator], ".");
+    while cleaned.contains("..") {
+        cleaned = cleaned.replace("..", ".");
+    }
+    cleaned.trim_matches('.').to_string()
+}
+
+/// Normalizes the show name read before an episode tag like any segment, and gives a year
+/// disambiguating it the plain form: "Show Name (2019)" and "Show.Name.2019" both become
+/// "Show.Name.2019".
+fn normalize_show(segment: &str, separator: char) -> String {
+    let show = normalize_separators(segment, separator);
+    normalize_separators(&SHOW_YEAR_RE.replace(&show, ".$1"), separator)
+}
+
+/// Cleans a string segment: converts to lowercase, replaces spaces with dots, removes multiple dots.
+pub fn clean_segment(segment: &str, separator: char) -> String {
+    normalize_separators(segment, separator).to_lowercase()
+}
+
+/// Words with fixed casing in show names: lowercase exceptions (except as the first word) and
+/// acronyms, which always keep their capitals. Loaded from `words.toml` over built-in defaults.
+/// Words are compared lowercased and NFC-normalized, so "Élite" written decomposed still matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TitleWords {
+    lowercase: HashSet<String>,
+    acronyms: Vec<Vec<String>>, // Dot-separated parts as written: "S.W.A.T" -> S, W, A, T
+}
+
+impl Default for TitleWords {
+    fn default() -> Self {
+        TitleWords {
+            lowercase: [
+                "the", "of", "and", "a", "an", "in", "on", "at", "to", "for", "or",
+            ]
+            .iter()
+            .map(|w| w.to_string())
+            .collect(),
+            acronyms: ["CSI", "NCIS", "FBI", "S.W.A.T"]
+                .iter()
+                .map(|a| TitleWords::acronym_parts(a))
+                .collect(),
+        }
+    }
+}
+
+/// `name` in `$XDG_CONFIG_HOME/rename-episodes`, or under `~/.config` without XDG.
+fn config_file(name: &str) -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("rename-episodes").join(name))
+}
+
+impl TitleWords {
+    /// `$XDG_CONFIG_HOME/rename-episodes/words.toml`, or under `~/.config` without XDG.
+    pub fn config_path() -> Option<PathBuf> {
+        config_file("words.toml")
+    }
+
+    fn acronym_parts(acronym: &str) -> Vec<String> {
+        acronym
+            .split('.')
+            .filter(|part| !part.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Reads the `lowercase = [...]` and `acronyms = [...]` arrays of a words file. A key that is
+    /// present replaces its defaults; a missing key keeps them.
+    pub fn parse(contents: &str) -> Result<TitleWords, String> {
+        let mut words = TitleWords::default();
+        let uncommented: String = contents
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        for caps in WORDS_KEY_RE.captures_iter(&uncommented) {
+            let values = json_strings(&caps[2])?;
+            match &caps[1] {
+                "lowercase" => {
+                    words.lowercase = values.iter().map(|w| word_key(w)).collect();
+                }
+                _ => {
+                    words.acronyms = values
+                        .iter()
+                        .map(|a| TitleWords::acronym_parts(a))
+                        .filter(|parts| !parts.is_empty())
+                        .collect();
+                }
+            }
+        }
+        Ok(words)
+    }
+}
+
+/// Show-name variants and the canonical name each stands for, so "theoffice", "the.office.us"
+/// and "Office" all become "The.Office.US". A variant is compared with the cleaned show name
+/// (lowercase, dot-separated); one written `/.../` is a case-insensitive regex over it instead.
+/// The first matching entry wins.
+#[derive(Debug, Clone, Default)]
+pub struct ShowAliases(Vec<(ShowPattern, String)>);
+
+#[derive(Debug, Clone)]
+enum ShowPattern {
+    Exact(String), // Cleaned, like the show names it's compared with
+    Regex(Regex),
+}
+
+impl ShowAliases {
+    /// `$XDG_CONFIG_HOME/rename-episodes/aliases.toml`, or under `~/.config` without XDG.
+    pub fn config_path() -> Option<PathBuf> {
+        config_file("aliases.toml")
+    }
+
+    /// Reads `"variant" = "Canonical Name"` lines. Blank lines and `#` comments are skipped.
+    pub fn parse(contents: &str) -> Result<ShowAliases, String> {
+        let mut aliases = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                format!(
+                    "line {}: expected \"variant\" = \"Canonical Name\"",
+                    index + 1
+                )
+            };
+            let strings = json_strings(line).map_err(|_| invalid())?;
+            let [variant, canonical] = strings.as_slice() else {
+                return Err(invalid());
+            };
+            let pattern = match variant
+                .strip_prefix('/')
+                .and_then(|rest| rest.strip_suffix('/'))
+            {
+                Some(regex) => ShowPattern::Regex(
+                    Regex::new(&format!("(?i){}", regex))
+                        .map_err(|e| format!("line {}: invalid regex: {}", index + 1, e))?,
+                ),
+                None => ShowPattern::Exact(clean_segment(variant, '.')),
+            };
+            aliases.push((pattern, canonical.clone()));
+        }
+        Ok(ShowAliases(aliases))
+    }
+
+    /// The canonical name for cleaned show name `show`, if an alias matches it.
+    fn canonical(&self, show: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(pattern, _)| match pattern {
+                ShowPattern::Exact(variant) => variant == show,
+                ShowPattern::Regex(regex) => regex.is_match(show),
+            })
+            .map(|(_, canonical)| canonical.as_str())
+    }
+}
+
+/// A word's form for case-insensitive comparison: lowercased and NFC-normalized.
+fn word_key(word: &str) -> String {
+    nfc::to_nfc(&word.to_lowercase())
+}
+
+/// Capitalizes words in a dot-separated string according to Title Case rules, keeping the
+/// configured exceptions lowercase and the configured acronyms in their given capitals.
+pub fn capitalize_title_case(text: &str, title_words: &TitleWords) -> String {
+    let mut words: Vec<String> = text
+        .split('.')
+        .filter(|word| !word.is_empty())
+        .enumerate()
+        .map(|(index, word)| {
+            if index == 0 || !title_words.lowercase.contains(&word_key(word)) {
+                // Capitalize the first word OR any word not in exceptions, and each part of a
+                // hyphenated word ("Spider-Man")
+                word.split('-')
+                    .map(sentence_case)
+                    .collect::<Vec<String>>()
+                    .join("-")
+            } else {
+                // Keep exception words lowercase (unless first word)
+                word.to_string()
+            }
+        })
+        .collect();
+
+    // Acronyms override the rules above, including across dots ("s.w.a.t" -> "S.W.A.T")
+    for acronym in &title_words.acronyms {
+        let len = acronym.len();
+        let mut index = 0;
+        while index + len <= words.len() {
+            let matches = words[index..index + len]
+                .iter()
+                .zip(acronym)
+                .all(|(word, part)| word_key(word) == word_key(part));
+            if matches {
+                words.splice(index..index + len, acronym.iter().cloned());
+                index += len;
+            } else {
+                index += 1;
+            }
+        }
+    }
+    words.join(".")
+}
+
+/// Joins words with `separator`. Runs of single letters keep their dots, so an initialism
+/// stays one ("Agents of S.H.I.E.L.D", not "Agents of S H I E L D").
+fn join_words<S: AsRef<str>>(words: impl IntoIterator<Item = S>, separator: &str) -> String {
+    let is_initial = |word: &str| {
+        let mut chars = word.chars();
+        matches!((chars.next(), chars.next()), (Some(c), None) if c.is_alphabetic())
+    };
+    let mut joined = String::new();
+    let mut previous: Option<S> = None;
+    for word in words {
+        if let Some(previous) = &previous {
+            let initialism = is_initial(previous.as_ref()) && is_initial(word.as_ref());
+            joined.push_str(if initialism { "." } else { separator });
+        }
+        joined.push_str(word.as_ref());
+        previous = Some(word);
+    }
+    joined
+}
+
+/// Uppercases the first letter of `text` and leaves the rest as it is: each part of a hyphenated
+/// title word ("Spider-Man"), and messages like "Renaming files...".
+fn sentence_case(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        None => String::new(),
+        Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+    }
+}
+
+/// Restores words the source deliberately wrote in capitals ("CSI", "The.Office.US") after title
+/// casing. A source written entirely in capitals carries no such signal, so it stays title-cased.
+fn restore_source_acronyms(title_cased: &str, source: Option<&str>) -> String {
+    let Some(source) = source else {
+        return title_cased.to_string();
+    };
+    let all_caps = !source.chars().any(char::is_lowercase);
+    let source_words: Vec<&str> = source.split('.').collect();
+    let words: Vec<&str> = title_cased.split('.').collect();
+    if all_caps || source_words.len() != words.len() {
+        return title_cased.to_string();
+    }
+
+    words
+        .iter()
+        .zip(source_words)
+        .map(|(word, source_word)| {
+            // Composed first, so a decomposed "É" counts as one uppercase letter
+            let composed = nfc::to_nfc(source_word);
+            let is_acronym = composed.chars().count() >= 2
+                && composed
+                    .chars()
+                    .all(|c| c.is_alphabetic() && c.is_uppercase());
+            if is_acronym && word_key(source_word) == word_key(word) {
+                source_word
+            } else {
+                word
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join(".")
+}
+
+/// Converts a part token (digits, number word, ordinal word or Roman numeral) to its number.
+fn part_number(token: &str) -> Option<u32> {
+    if let Ok(num) = token.parse::<u32>() {
+        return Some(num);
+    }
+    let num = match token.to_lowercase().as_str() {
+        "one" | "first" | "i" => 1,
+        "two" | "second" | "ii" => 2,
+        "three" | "third" | "iii" => 3,
+        "four" | "fourth" | "iv" => 4,
+        "five" | "fifth" | "v" => 5,
+        "six" | "sixth" | "vi" => 6,
+        "seven" | "seventh" | "vii" => 7,
+        "eight" | "eighth" | "viii" => 8,
+        "nine" | "ninth" | "ix" => 9,
+        "ten" | "tenth" | "x" => 10,
+        _ => return None,
+    };
+    Some(num)
+}
+
+/// Finds the first standalone Exx tag of a stem without SxxExx, returning the tag's match and its
+/// episode number. Tags inside brackets ("[E1-Raws]"), resolution tokens ("e720p", "E1080i") and
+/// numbers too long for an episode don't count.
+fn find_e_tag(stem: &str) -> Option<(regex::Match<'_>, u32)> {
+    E_RE.captures_iter(stem).find_map(|caps| {
+        let tag = caps.get(1).unwrap();
+        let resolution = stem[tag.end()..]
+            .starts_with(|c: char| c.is_ascii_digit() || matches!(c, 'p' | 'P' | 'i' | 'I'));
+        if resolution || in_brackets(stem, tag.start()) {
+            return None;
+        }
+        Some((tag, caps[2].parse().ok()?))
+    })
+}
+
+/// Pulls a part indicator out of a dot-separated remainder, returning the part number and what
+/// is left (in its original casing).
+fn extract_part(remainder: &str) -> (Option<u32>, Option<String>) {
+    let Some(caps) = PART_RE.captures(remainder) else {
+        return (None, Some(remainder.to_string()));
+    };
+    // "Part.2" and "Pt2" name their part in the first group, a bare "(2)" in the second
+    let token = caps.get(1).or_else(|| caps.get(2)).unwrap();
+    let part = part_number(token.as_str());
+    let whole = caps.get(0).unwrap();
+    let rest = normalize_separators(
+        &format!(
+            "{}.{}",
+            &remainder[..whole.start()],
+            &remainder[whole.end()..]
+        ),
+        '.', // Already dot-separated
+    );
+    (part, (!rest.is_empty()).then_some(rest))
+}
+
+/// The season named by the folder directly holding a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FolderSeason {
+    Season(u32), // "Season 3", "S03", or the specials folder (0)
+    Series(u32), // British-style "Series 2", whose files may number episodes with a bare "14"
+}
+
+impl FolderSeason {
+    fn number(self) -> u32 {
+        match self {
+            FolderSeason::Season(n) | FolderSeason::Series(n) => n,
+        }
+    }
+}
+
+/// The season given by the name of the folder directly holding `path`. Folder names with
+/// anything more ("Season 1-2", "Series 2 Extras") are ambiguous and yield nothing, leaving it
+/// to the prompts.
+fn folder_season(path: &Path, options: &Options) -> Option<FolderSeason> {
+    season_of_folder(path.parent()?, options)
+}
+
+/// The season named by `folder` itself.
+fn season_of_folder(folder: &Path, options: &Options) -> Option<FolderSeason> {
+    let folder = get_dir_name(folder)?;
+    if let Some(caps) = SERIES_FOLDER_RE.captures(&folder) {
+        return caps[1].parse().ok().map(FolderSeason::Series);
+    }
+    if let Some(caps) = SEASON_FOLDER_RE.captures(&folder) {
+        return caps[1].parse().ok().map(FolderSeason::Season);
+    }
+    is_specials_folder(&folder, options).then_some(FolderSeason::Season(0))
+}
+
+/// The only standalone number in `stem` and its byte span. None when there are several.
+fn sole_bare_number(stem: &str) -> Option<(u32, usize, usize)> {
+    let mut numbers = BARE_NUMBER_RE
+        .captures_iter(stem)
+        .filter_map(|caps| caps.get(1));
+    let number = numbers.next()?;
+    if numbers.next().is_some() {
+        return None;
+    }
+    Some((number.as_str().parse().ok()?, number.start(), number.end()))
+}
+
+/// Release tags written in their canonical casing when they appear in the remainder.
+const RELEASE_TAGS: [&str; 66] = [
+    "480p",
+    "576p",
+    "720p",
+    "1080p",
+    "1080i",
+    "2160p",
+    "4K",
+    "UHD",
+    "HDR",
+    "HDR10",
+    "HDR10+",
+    "DV",
+    "DoVi",
+    "HLG",
+    "SDR",
+    "WEB-DL",
+    "WEBRip",
+    "WEB",
+    "BluRay",
+    "BDRip",
+    "BRRip",
+    "DVDRip",
+    "HDTV",
+    "PDTV",
+    "REMUX",
+    "x264",
+    "x265",
+    "H264",
+    "H265",
+    "H.264",
+    "H.265",
+    "HEVC",
+    "AVC",
+    "AV1",
+    "XviD",
+    "DivX",
+    "10bit",
+    "8bit",
+    "AAC",
+    "AAC2.0",
+    "AC3",
+    "EAC3",
+    "DTS",
+    "DTS-HD",
+    "DTS-HD.MA",
+    "DTS-X",
+    "DTS-ES",
+    "TrueHD",
+    "Atmos",
+    "LPCM",
+    "DD5.1",
+    "DD7.1",
+    "DDP5.1",
+    "DDP7.1",
+    "DDP2.0",
+    "DD+",
+    "FLAC",
+    "Opus",
+    "AMZN",
+    "NF",
+    "HULU",
+    "DSNP",
+    "ATVP",
+    "HMAX",
+    "PROPER",
+    "REPACK",
+];
+
+/// Release tags that are also ordinary words ("Web of Lies", "Magnum Opus"). They only count
+/// as tags next to an unambiguous one, as in "2160p.DV.HDR10" or "NF.WEB-DL".
+const AMBIGUOUS_RELEASE_TAGS: [&str; 7] = ["4K", "DV", "WEB", "Atmos", "Opus", "NF", "PROPER"];
+
+/// Splits a cleaned remainder into segments, writing known release tags in their canonical
+/// casing ("1080p.web-dl.ddp5.1.x265" -> 1080p, WEB-DL, DDP5.1, x265). Tags may span dots, and
+/// "-" and "." are interchangeable inside them; anything else is left as it was. Ambiguous tags
+/// are only rewritten in a run of adjacent tags that holds an unambiguous one.
+fn canonicalize_release_tags(remainder: &str) -> Vec<String> {
+    let segments: Vec<&str> = remainder.split('.').collect();
+    // Each token as written, with the tag it matched
+    let mut tokens: Vec<(String, Option<&'static str>)> = Vec::new();
+    let mut index = 0;
+    while index < segments.len() {
+        match release_tag_at(&segments, index) {
+            Some((len, tag)) => {
+                tokens.push((segments[index..index + len].join("."), Some(tag)));
+                index += len;
+            }
+            None => {
+                tokens.push((segments[index].to_string(), None));
+                index += 1;
+            }
+        }
+    }
+
+    let mut out: Vec<String> = Vec::new();
+    for run in tokens.chunk_by(|a, b| a.1.is_some() == b.1.is_some()) {
+        let confirmed = run
+            .iter()
+            .any(|(_, tag)| tag.is_some_and(|tag| !AMBIGUOUS_RELEASE_TAGS.contains(&tag)));
+        for (text, tag) in run {
+            match tag {
+                Some(tag) if confirmed => out.push(tag.to_string()),
+                _ => out.extend(text.split('.').map(str::to_string)),
+            }
+        }
+    }
+    out
+}
+
+/// The release tag starting at `segments[index]` and how many segments it spans. Longest
+/// match first, up to three segments ("dts.hd.ma" before "dts.hd", "dd5.1").
+fn release_tag_at(segments: &[&str], index: usize) -> Option<(usize, &'static str)> {
+    let key = |text: &str| text.to_lowercase().replace('-', ".");
+    (1..=3.min(segments.len() - index)).rev().find_map(|len| {
+        RELEASE_TAGS
+            .iter()
+            .find(|tag| key(tag) == key(&segments[index..index + len].join(".")))
+            .map(|tag| (len, *tag))
+    })
+}
+
+/// Splits a remainder into the probable episode title and the release tags: the title is
+/// every segment before the first unambiguous release tag ("The.Pilot.1080p.WEB-DL" gives
+/// "The.Pilot" and "1080p.WEB-DL"). Tags that are also words don't end the title
+/// ("Opus.Magnum.720p" keeps "Opus.Magnum"), unless everything before the first unambiguous
+/// tag is such a tag ("PROPER.720p" has no title). A remainder without an unambiguous tag is
+/// all title.
+fn split_episode_title(remainder: &str) -> (&str, &str) {
+    let segments: Vec<&str> = remainder.split('.').collect();
+    let tag_at = |index: usize| release_tag_at(&segments, index).map(|(_, tag)| tag);
+    let Some(mut first_tag) = (0..segments.len())
+        .find(|&index| tag_at(index).is_some_and(|tag| !AMBIGUOUS_RELEASE_TAGS.contains(&tag)))
+    else {
+        return (remainder, "");
+    };
+    if (0..first_tag).all(|index| tag_at(index).is_some()) {
+        first_tag = 0;
+    }
+    let title_len: usize = segments[..first_tag]
+        .iter()
+        .map(|segment| segment.len() + 1)
+        .sum();
+    if title_len == 0 {
+        ("", remainder)
+    } else {
+        (&remainder[..title_len - 1], &remainder[title_len..])
+    }
+}
+
+/// Where a trailing release-group suffix starts in `stem`: "-Group[Source]", a bracketed
+/// "-[Group]"/"-{Group}" (not an ID or SxxExx tag), or "-GROUP" for a group in
+/// `--suffix-groups`. Other words after a dash ("Spider-Man") are part of the name.
+fn release_suffix_start(stem: &str, options: &Options) -> Option<usize> {
+    if let Some(mat) = SUFFIX_RE.find(stem) {
+        return Some(mat.start());
+    }
+    if let Some(caps) = BRACKET_SUFFIX_RE.captures(stem) {
+        let whole = caps.get(0).unwrap();
+        let token = caps.get(1).or_else(|| caps.get(2)).unwrap().as_str();
+        if !ID_TAG_RE.is_match(whole.as_str()) && !SE_RE.is_match(token) {
+            return Some(whole.start());
+        }
+    }
+    let caps = GROUP_SUFFIX_RE.captures(stem)?;
+    options
+        .suffix_groups
+        .iter()
+        .any(|group| group.eq_ignore_ascii_case(&caps[1]))
+        .then(|| caps.get(0).unwrap().start())
+}
+
+/// Whether byte offset `at` of `stem` lies inside a [...] or (...) group.
+fn in_brackets(stem: &str, at: usize) -> bool {
+    let mut depth: i32 = 0;
+    for c in stem[..at].chars() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth = (depth - 1).max(0),
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+/// Formats the episode part for a tag whose first episode is `first`, absorbing any further
+/// episodes that directly follow in `rest` ("E02" -> "E01E02", "-E03" -> "E01-E03"), or the
+/// half of a recap episode (".5" -> "E02.5"). Returns the formatted part and how many bytes of
+/// `rest` it consumed.
+fn episode_run(first: u32, rest: &str) -> (String, usize) {
+    let mut part = format!("E{:02}", first); // Force uppercase E
+    let mut consumed = 0;
+    while let Some(caps) = MORE_E_RE.captures(&rest[consumed..]) {
+        let episode: u32 = caps[2].parse().unwrap_or(0);
+        part.push_str(&format!("{}E{:02}", &caps[1], episode));
+        consumed += caps[0].len();
+    }
+    // A single digit after a dot and before a separator or the end; "E02.1080p" and
+    // "E02.5.1" (audio channels) are not fractions
+    let bytes = rest.as_bytes();
+    if consumed == 0
+        && bytes.first() == Some(&b'.')
+        && bytes.get(1).is_some_and(u8::is_ascii_digit)
+        && match bytes.get(2) {
+            None => true,
+            Some(b'.') => {
+                !bytes.get(3).is_some_and(u8::is_ascii_digit)
+                    || bytes.get(4).is_some_and(u8::is_ascii_digit)
+            }
+            Some(next) => b" _-[(".contains(next),
+        }
+    {
+        part.push_str(&rest[..2]);
+        consumed = 2;
+    }
+    (part, consumed)
+}
+
+/// Parses the file at `path`: checks it is a regular file with a usable name, then hands the
+/// name to `parse_stem`.
+pub fn parse_filename(path: &Path, options: &Options) -> Result<ParsedInfo, ParseError> {
+    // Unlike Path::is_file, tells an unreadable file apart from something that isn't a file
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => {}
+        Ok(_) => return Err(ParseError::NotAFile),
+        Err(e) => return Err(ParseError::Inaccessible(e.to_string())),
+    }
+
+    let original_filename = path
+        .file_name()
+        .ok_or(ParseError::NoFileName)?
+        .to_string_lossy()
+        .into_owned();
+
+    // The new stem is rebuilt from this text, so a lossy conversion would silently replace
+    // bytes in the target name. Refuse instead of renaming to a mangled name.
+    let stem = match path.file_stem() {
+        Some(s) => s.to_str().ok_or(ParseError::NonUtf8Name)?,
+        None => "",
+    };
+
+    let extension = path.extension().unwrap_or_default();
+
+    Ok(ParsedInfo {
+        original_path: path.to_path_buf(),
+        original_filename,
+        ..parse_stem(stem, extension, folder_season(path, options), options)
+    })
+}
+
+/// Parses a file name into its components (show, season, episode, remainder, extension)
+/// without touching the filesystem. `folder` is the season named by the parent folder, if any,
+/// which files with an episode but no season take. The result's original path and file name are
+/// the bare name; `parse_filename` fills in the real ones.
+pub fn parse_stem(
+    stem: &str,
+    extension: &OsStr,
+    folder: Option<FolderSeason>,
+    options: &Options,
+) -> ParsedInfo {
+    let original_filename = if extension.is_empty() {
+        stem.to_string()
+    } else {
+        format!("{}.{}", stem, extension.to_string_lossy())
+    };
+    let mut stem = stem.to_string();
+    let extension = extension.to_os_string();
+
+    // 0. Lift out provider ID tags so cleaning can't mangle them; they are re-emitted verbatim,
+    // normalized only to a lowercase provider name without inner spaces.
+    let mut id_tags: Vec<String> = Vec::new();
+    if options.keep_ids {
+        for caps in ID_TAG_RE.captures_iter(&stem) {
+            id_tags.push(format!(
+                "{}{}-{}{}",
+                &caps[1],
+                caps[2].to_lowercase(),
+                &caps[3],
+                &caps[4]
+            ));
+        }
+        stem = ID_TAG_RE.replace_all(&stem, " ").into_owned();
+    }
+
+    // 0a. A --pattern is matched against the name as given, before any built-in cleanup. A
+    // match only counts when its episode (and season, if captured) is a number.
+    let pattern_stem = stem.clone();
+    let pattern_caps = options
+        .pattern
+        .as_ref()
+        .and_then(|re| re.captures(&pattern_stem))
+        .filter(|caps| {
+            let number = |name| caps.name(name).map(|m| m.as_str().parse::<u32>().is_ok());
+            number("episode") == Some(true) && number("season") != Some(false)
+        });
+
+    // 0b. Drop a leading release group ("[Group] Show...") so it can't end up in the show name.
+    // Provider ID tags and bracketed SxxExx tags aren't groups; brackets later on are left alone.
+    let mut release_group = None;
+    if let Some(caps) = LEADING_GROUP_RE.captures(&stem) {
+        let token = caps.get(1).or_else(|| caps.get(2)).unwrap().as_str();
+        let whole = caps.get(0).unwrap();
+        if !ID_TAG_RE.is_match(whole.as_str()) && !SE_RE.is_match(token) && whole.end() < stem.len()
+        {
+            release_group = Some(token.trim().to_string());
+            stem = stem[whole.end()..].to_string();
+        }
+    }
+
+    // With --absolute, "Show - 135 [1080p]" would otherwise lose its number to the suffix rule
+    let absolute = options.absolute
+        && pattern_caps.is_none()
+        && !SE_RE.is_match(&stem)
+        && !SEASON_X_RE.is_match(&stem);
+    let absolute = absolute && ABSOLUTE_RE.is_match(&stem);
+
+    // 1. Remove suffix like -GroupName[Source], -[Group] or -RARBG first
+    if !absolute
+        && !options.keep_suffix
+        && let Some(start) = release_suffix_start(&stem, options)
+    {
+        stem.truncate(start);
+        stem = stem.trim_end().to_string();
+    }
+
+    // 1a. Give "&" a consistent form as a word of its own ("Tom&Jerry" -> "Tom & Jerry")
+    stem = options.ampersand.apply(&stem);
+
+    // 1b. Rewrite the "1x02" notation as "S1E02" so it parses like any other SxxExx tag
+    if !SE_RE.is_match(&stem) {
+        stem = SEASON_X_RE
+            .replacen(&stem, 1, "${1}S${2}E${3}${4}")
+            .into_owned();
+    }
+
+    // 2. Try to find SxxExx
+    let mut show_name_part: Option<String> = None;
+    let mut show_name_source: Option<String> = None;
+    let mut season_prefix_part: Option<String> = None;
+    let mut episode_number_part: Option<String> = None;
+    let mut remainder_part: Option<String> = None;
+    let mut second_tag: Option<String> = None;
+    let mut needs_user_input = false;
+
+    if let Some(caps) = &pattern_caps {
+        let episode_num: u32 = caps["episode"].parse().unwrap_or(0);
+        episode_number_part = Some(format!("E{:02}", episode_num));
+        season_prefix_part = match caps.name("season") {
+            Some(season) => Some(format!(
+                "S{:02}",
+                season.as_str().parse::<u32>().unwrap_or(0)
+            )),
+            None => folder.map(|folder| format!("S{:02}", folder.number())),
+        };
+
+        let potential_show = caps
+            .name("show")
+            .map(|show| normalize_separators(show.as_str(), options.separator))
+            .unwrap_or_default();
+        if !potential_show.is_empty() {
+            show_name_part = Some(potential_show.to_lowercase());
+            show_name_source = Some(potential_show);
+        }
+
+        let potential_remainder = caps
+            .name("remainder")
+            .map(|rem| normalize_separators(rem.as_str(), options.separator))
+            .unwrap_or_default();
+        if !potential_remainder.is_empty() {
+            remainder_part = Some(potential_remainder);
+        }
+    } else if let Some(se_match) = SE_RE.find(&stem) {
+        let mut tag_end = se_match.end();
+        let potential_show = normalize_show(&stem[..se_match.start()], options.separator);
+        if !potential_show.is_empty() {
+            show_name_part = Some(potential_show.to_lowercase());
+            show_name_source = Some(potential_show);
+        } else {
+            needs_user_input = true; // Show name missing before SxxExx
+        }
+
+        if let Some(caps) = SE_RE.captures(se_match.as_str()) {
+            let season_num: u32 = caps.get(1).unwrap().as_str().parse().unwrap_or(0);
+            season_prefix_part = Some(format!("S{:02}", season_num)); // Force uppercase S
+
+            let episode_num: u32 = caps.get(2).unwrap().as_str().parse().unwrap_or(0);
+            let (episodes, consumed) = episode_run(episode_num, &stem[se_match.end()..]);
+            episode_number_part = Some(episodes); // "E01", or "E01E02" for multi-episode files
+            tag_end += consumed;
+        } else {
+            // This case should be unlikely if SE_RE.find matched, but handle defensively
+            needs_user_input = true;
+        }
+
+        // A second tag after the first ("Show.S01E01.S01E02") means the name can't be trusted
+        second_tag = SE_RE.find(&stem[tag_end..]).map(|m| m.as_str().to_string());
+
+        let potential_remainder = normalize_separators(&stem[tag_end..], options.separator);
+        if !potential_remainder.is_empty() {
+            remainder_part = Some(potential_remainder);
+        }
+    } else if let Some(caps) = ABSOLUTE_RE.captures(&stem).filter(|_| absolute) {
+        // --absolute: "[Group] Show - 135 [1080p]". The leading group tag is dropped and the
+        // season is the supplied one, if any; without it the name is just Show.E135.
+        let episode_num: u32 = caps[2].parse().unwrap_or(0);
+        episode_number_part = Some(format!("E{:02}", episode_num));
+        season_prefix_part = options
+            .absolute_season
+            .map(|season| format!("S{:02}", season));
+
+        let potential_show = normalize_show(&caps[1], options.separator);
+        if !potential_show.is_empty() {
+            show_name_part = Some(potential_show.to_lowercase());
+            show_name_source = Some(potential_show);
+        } else {
+            needs_user_input = true;
+        }
+
+        // Quality tags usually come bracketed ("[1080p]"); keep their text, not the brackets
+        let rest = stem[caps.get(0).unwrap().end()..].replace(['[', ']', '(', ')'], " ");
+        let potential_remainder = normalize_separators(&rest, options.separator);
+        if !potential_remainder.is_empty() {
+            remainder_part = Some(potential_remainder);
+        }
+    } else {
+        // SxxExx not found, will need input for Season
+        needs_user_input = true;
+        // Still try to find Exx independently for later reconstruction, ignoring bracketed
+        // release group and quality tags ("[E1-Raws]")
+        if let Some((e_match, episode_num)) = find_e_tag(&stem) {
+            let (episodes, consumed) = episode_run(episode_num, &stem[e_match.end()..]);
+            episode_number_part = Some(episodes);
+            // "Season 3/Show.E05.mkv": the folder gives the season
+            season_prefix_part = folder.map(|folder| format!("S{:02}", folder.number()));
+
+            let potential_show = normalize_show(&stem[..e_match.start()], options.separator);
+            if !potential_show.is_empty() {
+                show_name_part = Some(potential_show.to_lowercase()); // May be overridden by user input later
+                show_name_source = Some(potential_show);
+            }
+
+            let potential_remainder =
+                normalize_separators(&stem[e_match.end() + consumed..], options.separator);
+            if !potential_remainder.is_empty() {
+                remainder_part = Some(potential_remainder);
+            }
+            if show_name_part.is_some() && season_prefix_part.is_some() {
+                needs_user_input = false;
+            }
+        } else if let Some(FolderSeason::Series(series)) = folder
+            && let Some((episode_num, number_start, number_end)) = sole_bare_number(&stem)
+        {
+            // "Series 2/Show - 14.mkv": the folder gives the season, the number the episode
+            season_prefix_part = Some(format!("S{:02}", series));
+            episode_number_part = Some(format!("E{:02}", episode_num));
+
+            let potential_show = normalize_separators(
+                stem[..number_start].trim_end_matches([' ', '.', '_', '-']),
+                options.separator,
+            );
+            if !potential_show.is_empty() {
+                show_name_part = Some(potential_show.to_lowercase());
+                show_name_source = Some(potential_show);
+                needs_user_input = false;
+            }
+
+            let potential_remainder = normalize_separators(
+                stem[number_end..].trim_start_matches([' ', '.', '_', '-']),
+                options.separator,
+            );
+            if !potential_remainder.is_empty() {
+                remainder_part = Some(potential_remainder);
+            }
+        } else {
+            // Neither SxxExx nor Exx found. Treat the whole stem as potential show name.
+            let potential_show = normalize_separators(&stem, options.separator);
+            if !potential_show.is_empty() {
+                show_name_part = Some(potential_show.to_lowercase());
+                show_name_source = Some(potential_show);
+            }
+        }
+    }
+
+    // Lift a part indicator out of the remainder so it can be rendered in one canonical form.
+    // Up to here the remainder keeps its casing; the lowercase copy is the one normally used.
+    let mut part = None;
+    let mut remainder_source = None;
+    if let Some(rem) = remainder_part.take() {
+        (part, remainder_source) = extract_part(&rem);
+        remainder_part = remainder_source.as_deref().map(str::to_lowercase);
+    }
+
+    // If essential info (Show or Season) is missing after parsing, confirm user input is needed.
+    // Absolute-numbered episodes need no season.
+    if show_name_part.is_none() || (season_prefix_part.is_none() && !absolute) {
+        needs_user_input = true;
+    }
+
+    // If user input is needed for Season, we *must* have found an Episode number.
+    if needs_user_input && season_prefix_part.is_none() && episode_number_part.is_none() {
+        // A warning will be printed later if this is a video file.
+    }
+
+    ParsedInfo {
+        original_path: PathBuf::from(&original_filename),
+        original_filename,
+        extension,      // Preserve original extension case (--lowercase-ext applies later)
+        show_name_part, // Store cleaned/lowercase for now
+        show_name_source,
+        season_prefix_part,  // Store "Sxx"
+        episode_number_part, // Store "Exx"
+        remainder_part,      // Store cleaned/lowercase
+        remainder_source,
+        part,
+        id_tags,
+        second_tag,
+        release_group,
+        needs_user_input,
+    }
+}
+
+/// Extracts the season number from a formatted "Sxx" prefix.
+fn season_number(season_prefix: &str) -> Option<u32> {
+    season_prefix.trim_start_matches(['S', 's']).parse().ok()
+}
+
+/// Extracts every episode number from a formatted episode part. "E01E02" yields both numbers
+/// and a span like "E01-E03" yields every episode it covers.
+fn episode_numbers(episode_part: &str) -> Vec<u32> {
+    let mut numbers: Vec<u32> = Vec::new();
+    for (index, span) in episode_part.split('-').enumerate() {
+        // A recap's "E02.5" counts as episode 2
+        let span_numbers: Vec<u32> = span
+            .split(['E', 'e'])
+            .filter_map(|n| n.split('.').next()?.parse().ok())
+            .collect();
+        match (index, numbers.last().copied(), span_numbers.first()) {
+            // Fill in the episodes between the end of the previous span and the start of this one
+            (1.., Some(last), Some(&first)) if first > last => numbers.extend(last + 1..first),
+            _ => {}
+        }
+        numbers.extend(span_numbers);
+    }
+    numbers
+}
+
+/// Collapses episode numbers into consecutive runs, e.g. [1, 2, 3, 5] -> [(1, 3), (5, 5)].
+fn group_into_ranges(numbers: &[u32]) -> Vec<(u32, u32)> {
+    let mut sorted = numbers.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for num in sorted {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == num => *end = num,
+            _ => ranges.push((num, num)),
+        }
+    }
+    ranges
+}
+
+/// Formats episode runs for display, e.g. "E01-E10, E12".
+fn format_episode_ranges(ranges: &[(u32, u32)]) -> String {
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                format!("E{:02}", start)
+            } else {
+                format!("E{:02}-E{:02}", start, end)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Folder name for a season: "Season NN", or the configured specials name for season 0.
+fn season_folder_name(season: u32, options: &Options) -> String {
+    if season == 0 {
+        options.specials_folder_name.clone()
+    } else {
+        format!("Season {:02}", season)
+    }
+}
+
+/// The episode numbers present for each show/season, sorted by show and season, and the number
+/// of files lacking a season or episode number.
+fn episodes_by_season(
+    parsed_files_info: &[ParsedInfo],
+    options: &Options,
+) -> (BTreeMap<(String, u32), Vec<u32>>, usize) {
+    let mut by_season: BTreeMap<(String, u32), Vec<u32>> = BTreeMap::new();
+    let mut unplaced = 0;
+
+    for info in parsed_files_info {
+        let season = info.season_prefix_part.as_deref().and_then(season_number);
+        let episodes = info
+            .episode_number_part
+            .as_deref()
+            .map(episode_numbers)
+            .unwrap_or_default();
+        match season {
+            Some(season) if !episodes.is_empty() => {
+                let show = info
+                    .show_name_part
+                    .as_deref()
+                    .map_or("(unknown show)".to_string(), |show| {
+                        capitalize_title_case(show, &options.title_words)
+                    });
+                by_season
+                    .entry((show, season))
+                    .or_default()
+                    .extend(episodes);
+            }
+            _ => unplaced += 1,
+        }
+    }
+    (by_season, unplaced)
+}
+
+/// Prints which episodes are present for each show/season, summarized as ranges.
+fn print_episode_ranges(parsed_files_info: &[ParsedInfo], options: &Options) {
+    let (groups, unplaced) = episodes_by_season(parsed_files_info, options);
+
+    say!("\nEpisodes by show and season:");
+    say!("--------------------");
+    for ((show, season), episodes) in &groups {
+        say!(
+            "{} / {}: {}",
+            show,
+            season_folder_name(*season, options),
+            format_episode_ranges(&group_into_ranges(episodes))
+        );
+    }
+    say!("--------------------");
+    if unplaced > 0 {
+        say!(
+            "{} file(s) without a season and episode number are not included.",
+            unplaced
+        );
+    }
+}
+
+/// The episode numbers missing between the first and last episode present in each show/season,
+/// for the seasons that have any.
+fn find_gaps(
+    parsed_files_info: &[ParsedInfo],
+    options: &Options,
+) -> Vec<((String, u32), Vec<u32>)> {
+    let (by_season, _) = episodes_by_season(parsed_files_info, options);
+    by_season
+        .into_iter()
+        .filter_map(|(season, episodes)| {
+            let present: BTreeSet<u32> = episodes.into_iter().collect();
+            let (&first, &last) = (present.first()?, present.last()?);
+            let missing: Vec<u32> = (first..=last).filter(|e| !present.contains(e)).collect();
+            (!missing.is_empty()).then_some((season, missing))
+        })
+        .collect()
+}
+
+/// `--gaps`: lists the episodes missing in the middle of each season, e.g. after an incomplete
+/// download. Informational only; the run goes on as usual.
+fn print_gaps(parsed_files_info: &[ParsedInfo], options: &Options) {
+    let gaps = find_gaps(parsed_files_info, options);
+    if gaps.is_empty() {
+        say!("No episodes missing between the first and last episode of any season.");
+        return;
+    }
+    say!("\nMissing episodes:");
+    say!("--------------------");
+    for ((show, season), missing) in &gaps {
+        let missing: Vec<String> = missing.iter().map(|e| format!("E{:02}", e)).collect();
+        say!(
+            "{} / {} missing: {}",
+            show,
+            season_folder_name(*season, options),
+            missing.join(", ")
+        );
+    }
+    say!("--------------------");
+}
+
+/// Groups the files holding the same show/season/episode, keeping groups of more than one file,
+/// sorted. Multi-episode files belong to every episode they contain.
+fn find_duplicates<'a>(
+    parsed_files_info: &'a [ParsedInfo],
+    options: &Options,
+) -> BTreeMap<(String, u32, u32), Vec<&'a ParsedInfo>> {
+    let mut by_episode: BTreeMap<(String, u32, u32), Vec<&ParsedInfo>> = BTreeMap::new();
+    for info in parsed_files_info {
+        let (Some(show), Some(season)) = (
+            &info.show_name_part,
+            info.season_prefix_part.as_deref().and_then(season_number),
+        ) else {
+            continue; // Without both, files can't be told to hold the same episode
+        };
+        let show = capitalize_title_case(show, &options.title_words);
+        for episode in info
+            .episode_number_part
+            .as_deref()
+            .map(episode_numbers)
+            .unwrap_or_default()
+        {
+            by_episode
+                .entry((show.clone(), season, episode))
+                .or_default()
+                .push(info);
+        }
+    }
+    by_episode.retain(|_, files| files.len() > 1);
+    by_episode
+}
+
+/// Prints the episodes present in more than one file, with each file's remainder to tell the
+/// copies apart.
+fn print_duplicates(parsed_files_info: &[ParsedInfo], options: &Options, target_directory: &Path) {
+    let duplicates = find_duplicates(parsed_files_info, options);
+    if duplicates.is_empty() {
+        say!("\nNo episode is present in more than one file.");
+        return;
+    }
+    say!("\nEpisodes present in more than one file:");
+    say!("--------------------");
+    for ((show, season, episode), files) in &duplicates {
+        say!("{}.S{:02}E{:02}:", show, season, episode);
+        for info in files {
+            say!(
+                "  {} ({})",
+                relative_name(&info.original_path, target_directory),
+                info.remainder_part.as_deref().unwrap_or("no remainder")
+            );
+        }
+    }
+    say!("--------------------");
+}
+
+/// Gets the directory name (last component) of a path, if possible. Used for default suggestions.
+fn get_dir_name(path: &Path) -> Option<String> {
+    path.file_name().and_then(OsStr::to_str).map(str::to_string)
+}
+
+/// File name of a path for display. Lossy, so it must never be used to build a path.
+fn display_name(path: &Path) -> Cow<'_, str> {
+    path.file_name()
+        .map_or(Cow::Borrowed("?"), |n| n.to_string_lossy())
+}
+
+/// Path relative to the scanned directory, so files from different subfolders stay distinguishable.
+fn relative_name<'a>(path: &'a Path, base: &Path) -> Cow<'a, str> {
+    match path.strip_prefix(base) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative.to_string_lossy(),
+        _ => display_name(path),
+    }
+}
+
+/// Number of rows in the controlling terminal, from $LINES or `stty size`.
+fn terminal_height() -> Option<usize> {
+    if let Some(lines) = env::var("LINES").ok().and_then(|l| l.parse().ok()) {
+        return Some(lines);
+    }
+    let output = Command::new("stty")
+        .arg("size")
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// `--title-command`: runs `command` through `sh -c`, so it is quoted as in a shell, with the
+/// show, season and episode number as its last three arguments, and returns the first line it
+/// prints, with spaces turned into dots. A failing command or empty output gives None, so the
+/// name keeps the title it had.
+fn fetch_episode_title(
+    command: &str,
+    show: &str,
+    season: u32,
+    episode: u32,
+    separator: char,
+) -> Option<String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", command))
+        .arg("rename-episodes") // $0, so the episode fields are $1 to $3
+        .args([show.to_string(), season.to_string(), episode.to_string()])
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output();
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            say!(
+                "Warning: --title-command failed for {} S{:02}E{:02} ({}); keeping the title from the name.",
+                show,
+                season,
+                episode,
+                output.status
+            );
+            return None;
+        }
+        Err(e) => {
+            say!(
+                "Warning: Could not run --title-command '{}': {}.",
+                command,
+                e
+            );
+            return None;
+        }
+    };
+    let text = String::from_utf8_lossy(&output.stdout);
+    let title = normalize_separators(text.lines().next().unwrap_or(""), separator);
+    (!title.is_empty()).then_some(title)
+}
+
+/// Splits a name into words and the separators between them, each separator its own token.
+fn name_tokens(name: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    for (index, c) in name.char_indices() {
+        if matches!(c, '.' | ' ' | '-' | '_' | '/') {
+            if start < index {
+                tokens.push(&name[start..index]);
+            }
+            tokens.push(&name[index..index + c.len_utf8()]);
+            start = index + c.len_utf8();
+        }
+    }
+    if start < name.len() {
+        tokens.push(&name[start..]);
+    }
+    tokens
+}
+
+/// Highlights what differs between `old` and `new` word by word: tokens outside their longest
+/// common subsequence are shown red in the old name and green in the new one.
+fn highlight_changes(old: &str, new: &str) -> (String, String) {
+    let (old_tokens, new_tokens) = (name_tokens(old), name_tokens(new));
+    let (n, m) = (old_tokens.len(), new_tokens.len());
+    // common[i][j]: common subsequence length of old_tokens[i..] and new_tokens[j..]
+    let mut common = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = if old_tokens[i] == new_tokens[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut old_kept, mut new_kept) = (vec![false; n], vec![false; m]);
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old_tokens[i] == new_tokens[j] {
+            (old_kept[i], new_kept[j]) = (true, true);
+            (i, j) = (i + 1, j + 1);
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    let paint = |tokens: &[&str], kept: &[bool], code: &str| {
+        let mut painted = String::new();
+        for (index, token) in tokens.iter().enumerate() {
+            let starts_change = !kept[index] && (index == 0 || kept[index - 1]);
+            if starts_change {
+                painted.push_str(&format!("\x1b[{}m", code));
+            }
+            painted.push_str(token);
+            if !kept[index] && kept.get(index + 1).is_none_or(|&next| next) {
+                painted.push_str("\x1b[0m");
+            }
+        }
+        painted
+    };
+    (
+        paint(&old_tokens, &old_kept, "31"),
+        paint(&new_tokens, &new_kept, "32"),
+    )
+}
+
+/// Pipes `text` through $PAGER (default `less`) and waits for the user to quit it.
+fn show_in_pager(text: &str) -> io::Result<()> {
+    let pager = env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| "less".to_string());
+    let mut words = pager.split_whitespace();
+    let program = words.next().unwrap_or("less");
+
+    let mut command = Command::new(program);
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "R"); // Show --color's highlighting rather than its escape codes
+    }
+    let mut child = command.args(words).stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The user may quit the pager before reading everything; that's not an error.
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// The streams interactive prompts are written to and answered from. The CLI wires in
+/// stdin/stdout; a host application can supply its own reader/writer to answer prompts
+/// programmatically and capture the prompt text.
+pub struct Prompter {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+}
+
+impl Prompter {
+    /// A prompter reading answers from `input` and writing prompts to `output`.
+    pub fn new(input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        Prompter { input, output }
+    }
+
+    /// A prompter on the terminal: stdin, and stdout (stderr under `--json`).
+    pub fn stdio() -> Self {
+        // Under --json, prompts must not end up in the JSON on stdout
+        let output: Box<dyn Write> = if STDOUT_IS_JSON.load(Ordering::Relaxed) {
+            Box::new(io::stderr())
+        } else {
+            Box::new(io::stdout())
+        };
+        Prompter::new(Box::new(io::stdin().lock()), output)
+    }
+
+    /// Writes `prompt_text` and returns the answer line with surrounding whitespace removed.
+    /// End of input counts as an empty answer.
+    fn ask(&mut self, prompt_text: &str) -> io::Result<String> {
+        write!(self.output, "{}", prompt_text)?;
+        self.output.flush()?;
+
+        let mut input = String::new();
+        self.input.read_line(&mut input)?;
+        Ok(input.trim().to_string())
+    }
+}
+
+/// Prompts the user for input with an optional default value.
+fn prompt_user(
+    prompter: &mut Prompter,
+    prompt_text: &str,
+    default_value: Option<&str>,
+) -> io::Result<String> {
+    let input = match default_value {
+        Some(def) if !def.is_empty() => {
+            prompter.ask(&format!("{} [Default: {}]: ", prompt_text, def))?
+        }
+        _ => prompter.ask(&format!("{}: ", prompt_text))?,
+    };
+    match default_value {
+        Some(def) if input.is_empty() => Ok(def.to_string()),
+        _ => Ok(input),
+    }
+}
+
+/// Parses a deselection like "2 5-7, 9" into zero-based indices, validating against `count`.
+fn parse_selection(input: &str, count: usize) -> Result<HashSet<usize>, String> {
+    let mut selected = HashSet::new();
+    for token in input.split([' ', ',']).filter(|t| !t.is_empty()) {
+        let (start, end) = match token.split_once('-') {
+            Some((start, end)) => (start, end),
+            None => (token, token),
+        };
+        let parse = |n: &str| -> Result<usize, String> {
+            match n.trim().parse::<usize>() {
+                Ok(num) if (1..=count).contains(&num) => Ok(num),
+                _ => Err(format!("'{}' is not an entry number (1-{})", token, count)),
+            }
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start > end {
+            return Err(format!("'{}' is not a valid range", token));
+        }
+        selected.extend(start - 1..end);
+    }
+    Ok(selected)
+}
+
+/// Lets the user prune a numbered plan. Returns the original paths of the deselected entries.
+fn select_entries_to_skip(
+    prompter: &mut Prompter,
+    sorted_renames: &[(&PathBuf, &PathBuf)],
+) -> io::Result<Vec<PathBuf>> {
+    let mut deselected: HashSet<usize> = HashSet::new();
+    loop {
+        let input = prompt_user(
+            prompter,
+            "Entry numbers to deselect (e.g. 2 5-7), or Enter to continue",
+            None,
+        )?;
+        if input.is_empty() {
+            break;
+        }
+        match parse_selection(&input, sorted_renames.len()) {
+            Ok(selection) => {
+                deselected.extend(selection);
+                let mut numbers: Vec<usize> = deselected.iter().map(|i| i + 1).collect();
+                numbers.sort_unstable();
+                say!("Deselected so far: {:?}", numbers);
+            }
+            Err(message) => say!("{}", message),
+        }
+    }
+    Ok(sorted_renames
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| deselected.contains(index))
+        .map(|(_, (old, _))| (*old).clone())
+        .collect())
+}
+
+/// `--interactive`: walks the plan one entry at a time, asking to accept, edit or skip each.
+/// Returns the entries to go ahead with, edited targets included.
+fn review_each_entry(
+    prompter: &mut Prompter,
+    sorted_renames: &[(&PathBuf, &PathBuf)],
+    base: &Path,
+) -> io::Result<HashMap<PathBuf, PathBuf>> {
+    let mut kept: HashMap<PathBuf, PathBuf> = HashMap::new();
+    for (index, (old, new)) in sorted_renames.iter().enumerate() {
+        say!(
+            "\n[{}/{}] {} -> {}",
+            index + 1,
+            sorted_renames.len(),
+            relative_name(old, base),
+            display_name(new)
+        );
+        loop {
+            let answer = prompt_user(prompter, "Accept (a), edit (e) or skip (s)", Some("a"))?;
+            match answer.to_lowercase().as_str() {
+                "a" | "accept" => {
+                    kept.insert((*old).clone(), (*new).clone());
+                }
+                "s" | "skip" => {}
+                "e" | "edit" => {
+                    let name = prompt_user(prompter, "New name", Some(&display_name(new)))?;
+                    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".."
+                    {
+                        say!("'{}' is not a usable file name.", name);
+                        continue;
+                    }
+                    kept.insert((*old).clone(), new.with_file_name(name));
+                }
+                _ => {
+                    say!("Please answer a, e or s.");
+                    continue;
+                }
+            }
+            break;
+        }
+    }
+    Ok(kept)
+}
+
+/// Whether a folder name means season 0: "Specials", "Extras" (and singulars) or the
+/// configured `--specials-folder-name`. "Season 00" needs no special case.
+fn is_specials_folder(name: &str, options: &Options) -> bool {
+    let name = name.trim().to_lowercase();
+    ["specials", "special", "extras", "extra"].contains(&name.as_str())
+        || name == options.specials_folder_name.to_lowercase()
+}
+
+/// Parses a season typed at the prompt ("1", "02", "Season 3") into the "Sxx" form. Specials
+/// folder names, which the prompt offers as the default in such folders, mean "S00".
+fn parse_season_input(input: &str, options: &Options) -> Result<String, ParseError> {
+    if is_specials_folder(input, options) {
+        return Ok("S00".to_string());
+    }
+    parse_season(input)
+        .map(|num| format!("S{:02}", num)) // Ensure uppercase S
+        .ok_or_else(|| ParseError::UnparseableSeason(input.to_string()))
+}
+
+/// Parses a `--newer-than` duration: a whole number with a unit of s, m, h, d or w ("90m",
+/// "2h", "1d").
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let unit = text.chars().last()?;
+    let number: u64 = text[..text.len() - unit.len_utf8()].parse().ok()?;
+    let seconds = match unit.to_ascii_lowercase() {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    number.checked_mul(seconds).map(Duration::from_secs)
+}
+
+/// Reads a season number the way the prompt does, skipping any leading non-digits ("3", "03",
+/// "S03", "Season 3"). Used for `--season` too.
+pub fn parse_season(input: &str) -> Option<u32> {
+    input
+        .trim_start_matches(|c: char| !c.is_ascii_digit())
+        .parse()
+        .ok()
+}
+
+/// Asks the user to confirm applying `count` actions. Batches above the configured threshold
+/// must be confirmed by typing the exact confirmation word, so a reflexive "y" can't start a
+/// mass rename.
+fn confirm_batch(prompter: &mut Prompter, count: usize, options: &Options) -> io::Result<bool> {
+    if options.assume_yes {
+        return Ok(true);
+    }
+    if count > options.confirm_threshold {
+        let word = options
+            .confirm_word
+            .clone()
+            .unwrap_or_else(|| match options.mode {
+                Mode::Rename => "RENAME".to_string(),
+                Mode::Copy => "COPY".to_string(),
+                Mode::Move => "MOVE".to_string(),
+                Mode::Link => "LINK".to_string(),
+            });
+        let confirmation = prompter.ask(&format!(
+            "\nThis will affect {} file(s). Type {} to proceed with {}: ",
+            count,
+            word,
+            options.mode.verb()
+        ))?;
+        return Ok(confirmation == word);
+    }
+
+    let confirmation = prompter.ask(&format!(
+        "\nProceed with {} {} file(s)? (y/n) [default: n]: ",
+        options.mode.verb(),
+        count
+    ))?;
+
+    let trimmed_confirmation = confirmation.to_lowercase();
+    Ok(trimmed_confirmation == "y" || trimmed_confirmation == "yes")
+}
+
+/// Placeholders a `--template` may use.
+const TEMPLATE_FIELDS: [&str; 14] = [
+    "show",
+    "show_spaced",
+    "ids",
+    "season",
+    "episode",
+    "part",
+    "remainder",
+    "remainder_spaced",
+    "title",
+    "title_spaced",
+    "tags",
+    "tags_spaced",
+    "group",
+    "ext",
+];
+
+/// The default `--template`: the Show.Name.SxxExx.remainder.ext layout, with preserved ID tags
+/// after the show name or at the end as `--id-position` asks. Parts are joined by `separator`;
+/// the extension always follows a dot.
+pub fn default_template(id_position: IdPosition, separator: char) -> String {
+    let template = match id_position {
+        IdPosition::AfterShow => "{show}.{ids}.{season}{episode}.{part}.{remainder}",
+        IdPosition::End => "{show}.{season}{episode}.{part}.{remainder}.{ids}",
+    };
+    format!("{}.{{ext}}", template.replace('.', &separator.to_string()))
+}
+
+/// Checks that a `--template` only uses known placeholders and ends in `{ext}`, so the
+/// original extension can never be dropped.
+pub fn validate_template(template: &str) -> Result<(), String> {
+    for caps in TEMPLATE_FIELD_RE.captures_iter(template) {
+        if !TEMPLATE_FIELDS.contains(&&caps[1]) {
+            return Err(format!(
+                "Unknown placeholder '{}' in --template (expected one of {{{}}})",
+                &caps[0],
+                TEMPLATE_FIELDS.join("}, {")
+            ));
+        }
+    }
+    if !template.ends_with("{ext}") {
+        return Err("--template must end with {ext}".to_string());
+    }
+    Ok(())
+}
+
+/// Fills in `template`. An empty field takes the literal text leading into it along, so
+/// "{show}.{part}.{remainder}.{ext}" never leaves doubled or dangling separators; a field directly
+/// following it gets that text instead ("{show}.{season}{episode}" without a season is
+/// "Show.E135"). Everything but the extension goes through `sanitize`; the extension is appended
+/// as its original bytes.
+fn render_template(
+    template: &str,
+    fields: &HashMap<&str, String>,
+    extension: &OsStr,
+    sanitize: impl Fn(&str) -> String,
+) -> OsString {
+    let mut out = OsString::new();
+    let mut seen_placeholder = false;
+    let mut last_end = 0;
+    let mut dropped = ""; // Literal leading into the previous placeholder, if it was empty
+    for caps in TEMPLATE_FIELD_RE.captures_iter(template) {
+        let whole = caps.get(0).unwrap();
+        let mut literal = &template[last_end..whole.start()]; // Text since the previous placeholder
+        last_end = whole.end();
+        if literal.is_empty() {
+            literal = dropped;
+        }
+
+        let value: OsString = match &caps[1] {
+            "ext" => extension.to_os_string(),
+            name => OsString::from(sanitize(fields.get(name).map_or("", String::as_str))),
+        };
+        // Separators after an empty leading field would otherwise start the name
+        let keeps_literal = !(out.is_empty() && seen_placeholder);
+        if !value.is_empty() && keeps_literal {
+            out.push(sanitize(literal));
+        }
+        dropped = if value.is_empty() { literal } else { "" };
+        out.push(value);
+        seen_placeholder = true;
+    }
+    out.push(sanitize(&template[last_end..]));
+    out
+}
+
+/// Whether `original` already conforms to its reconstructed name `new`: the same path, or a
+/// name that only differs in Unicode normalization (looks the same, so renaming it would be
+/// invisible churn; `--normalize-to-utf8nfc-only` is there for that).
+fn already_correct(original: &Path, new: &Path) -> bool {
+    if original == new {
+        return true;
+    }
+    match (
+        original.file_name().and_then(OsStr::to_str),
+        new.file_name(),
+    ) {
+        (Some(name), Some(new_name)) if original.parent() == new.parent() => {
+            new_name.to_str() == Some(nfc::to_nfc(name).as_str())
+        }
+        _ => false,
+    }
+}
+
+/// Subtitle extensions considered when looking for a video's sidecar (lowercase).
+const SUBTITLE_EXTENSIONS: [&str; 6] = ["srt", "ass", "ssa", "sub", "vtt", "sup"];
+
+/// The sidecar extensions to rename (lowercase, without the dot): the subtitle extensions and
+/// the ".idx" index of VobSub subtitles, replaced or extended by `--sidecar-ext`. `.nfo` files
+/// are often per show rather than per episode, so they must be opted into.
+fn sidecar_extensions(options: &Options) -> HashSet<String> {
+    let mut extensions: HashSet<String> = SUBTITLE_EXTENSIONS
+        .iter()
+        .chain(&["idx"])
+        .map(|&s| s.to_string())
+        .collect();
+    if let Some(list) = &options.sidecar_extensions {
+        if !list.extend_defaults {
+            extensions.clear();
+        }
+        extensions.extend(list.extensions.iter().cloned());
+    }
+    extensions
+}
+
+/// Plans renames for the companion files of each renamed video: files in the video's folder
+/// named after its original stem plus any suffix and one of `extensions` ("Show.s01e02.en.srt").
+/// The suffix is kept, so "Show.s01e02.en.srt" follows "Show.S01E02.mkv" as "Show.S01E02.en.srt".
+/// When stems overlap, a sidecar belongs to the video with the longest matching stem.
+fn plan_sidecar_renames(
+    video_renames: &HashMap<PathBuf, PathBuf>,
+    known_paths: &HashSet<PathBuf>,
+    extensions: &HashSet<String>,
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut videos: Vec<(&PathBuf, &PathBuf)> = video_renames.iter().collect();
+    videos.sort_by_key(|(old, _)| std::cmp::Reverse(old.file_stem().map_or(0, OsStr::len)));
+
+    let mut claimed: HashSet<&PathBuf> = HashSet::new();
+    let mut sidecar_renames = Vec::new();
+    for (old, new) in videos {
+        let (Some(old_stem), Some(new_stem)) = (
+            old.file_stem().and_then(OsStr::to_str),
+            new.file_stem().and_then(OsStr::to_str),
+        ) else {
+            continue;
+        };
+        let prefix = format!("{}.", old_stem);
+        for candidate in known_paths {
+            if candidate.parent() != old.parent()
+                || video_renames.contains_key(candidate)
+                || claimed.contains(candidate)
+            {
+                continue;
+            }
+            let Some(suffix) = candidate
+                .file_name()
+                .and_then(OsStr::to_str)
+                .and_then(|name| name.strip_prefix(&prefix))
+            else {
+                continue;
+            };
+            let extension = suffix.rsplit('.').next().unwrap_or_default().to_lowercase();
+            if extensions.contains(&extension) {
+                claimed.insert(candidate);
+                sidecar_renames.push((
+                    candidate.clone(),
+                    new.with_file_name(format!("{}.{}", new_stem, suffix)),
+                ));
+            }
+        }
+    }
+    sidecar_renames
+}
+
+/// Show, season and episode read from a Kodi episode `.nfo`.
+#[derive(Debug, Default, PartialEq)]
+struct NfoInfo {
+    show: Option<String>,
+    season: Option<u32>,
+    episode: Option<u32>,
+}
+
+/// Reads the `.nfo` sharing `video`'s stem. In an episode .nfo `<title>` is the episode's own
+/// title, so the show comes from `<showtitle>`. Missing or unreadable fields are None; the
+/// first of each counts (multi-episode files list several). None without any usable field.
+fn read_nfo(video: &Path) -> Option<NfoInfo> {
+    let text = ["nfo", "NFO"]
+        .iter()
+        .find_map(|extension| fs::read(video.with_extension(extension)).ok())?;
+    let text = String::from_utf8_lossy(&text);
+    let mut nfo = NfoInfo::default();
+    for caps in NFO_FIELD_RE.captures_iter(&text) {
+        let value = caps[2]
+            .trim()
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&");
+        match &caps[1] {
+            "showtitle" if nfo.show.is_none() && !value.is_empty() => nfo.show = Some(value),
+            "season" if nfo.season.is_none() => nfo.season = value.parse().ok(),
+            "episode" if nfo.episode.is_none() => nfo.episode = value.parse().ok(),
+            _ => {}
+        }
+    }
+    (nfo != NfoInfo::default()).then_some(nfo)
+}
+
+/// Parses the sidecar subtitle of `video`, returning it only if it yields both season and episode.
+/// Sidecars share the video's stem ("name.srt", "name.en.srt"); when none does, a folder holding
+/// exactly one video and one subtitle pairs those two.
+fn subtitle_sidecar_info(
+    video: &Path,
+    video_extensions: &HashSet<String>,
+    options: &Options,
+) -> Option<ParsedInfo> {
+    let folder = video.parent()?;
+    let video_stem = video.file_stem()?.to_str()?;
+    let lowercase_extension = |path: &Path| {
+        path.extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_lowercase)
+            .unwrap_or_default()
+    };
+
+    let mut subtitles: Vec<PathBuf> = Vec::new();
+    let mut video_count = 0;
+    for entry in fs::read_dir(folder).ok()?.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        let extension = lowercase_extension(&path);
+        if video_extensions.contains(&extension) {
+            video_count += 1;
+        } else if SUBTITLE_EXTENSIONS.contains(&extension.as_str()) {
+            subtitles.push(path);
+        }
+    }
+    subtitles.sort();
+
+    let mut candidates: Vec<&PathBuf> = subtitles
+        .iter()
+        .filter(|sub| {
+            sub.file_stem().and_then(OsStr::to_str).is_some_and(|stem| {
+                stem == video_stem
+                    || stem
+                        .strip_prefix(video_stem)
+                        .is_some_and(|rest| rest.starts_with('.'))
+            })
+        })
+        .collect();
+    if candidates.is_empty() && video_count == 1 && subtitles.len() == 1 {
+        candidates.push(&subtitles[0]);
+    }
+
+    candidates
+        .into_iter()
+        .filter_map(|sub| parse_filename(sub, options).ok())
+        .find(|info| info.season_prefix_part.is_some() && info.episode_number_part.is_some())
+}
+
+/// One entry found by `scan_tree`, with the metadata Pass 1 needs.
+struct ScannedEntry {
+    path: PathBuf,
+    is_file: bool, // Follows symlinks, like Path::is_file
+}
+
+/// Maps `items` through `f` on at most `max_parallel` threads, keeping the input order. With a
+/// limit of 1 everything runs on the calling thread.
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    max_parallel: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    if max_parallel <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let chunk_size = items.len().div_ceil(max_parallel);
+    thread::scope(|scope| {
+        let workers: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<R>>()))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("scan worker panicked"))
+            .collect()
+    })
+}
+
+/// Lists the entries of `root` and of its subdirectories not reached through a symlink (so links
+/// can never form a cycle), down to `max_depth` levels (1 is `root` alone). Directories are read level by level, and both
+/// the reads and the per-entry metadata calls use at most `max_parallel` threads, so a slow
+/// network share isn't flooded. Unreadable subdirectories, entries and files are reported and
+/// skipped; only an unreadable `root` is an error.
+fn scan_tree(root: &Path, max_depth: usize, max_parallel: usize) -> io::Result<Vec<ScannedEntry>> {
+    let mut scanned = Vec::new();
+    let mut level = vec![root.to_path_buf()];
+    let mut depth = 1;
+    while !level.is_empty() {
+        let listings = parallel_map(&level, max_parallel, |dir| {
+            fs::read_dir(dir).map(|entries| {
+                entries
+                    .map(|entry| entry.map(|e| e.path()))
+                    .collect::<Vec<_>>()
+            })
+        });
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for (dir, listing) in level.iter().zip(listings) {
+            match listing {
+                Ok(listing) => {
+                    for entry in listing {
+                        match entry {
+                            Ok(path) => paths.push(path),
+                            Err(e) => eprintln!(
+                                "Warning: Cannot read an entry of '{}': {}",
+                                dir.display(),
+                                e
+                            ),
+                        }
+                    }
+                }
+                Err(e) if dir == root => return Err(e),
+                Err(e) => eprintln!("Warning: Cannot read directory '{}': {}", dir.display(), e),
+            }
+        }
+
+        // symlink_metadata() does not follow symlinks; metadata() does, like is_file()
+        let kinds = parallel_map(&paths, max_parallel, |path| {
+            let is_real_dir = fs::symlink_metadata(path).is_ok_and(|m| m.is_dir());
+            let file = if is_real_dir {
+                Ok(false)
+            } else {
+                fs::metadata(path).map(|m| m.is_file())
+            };
+            (is_real_dir, file)
+        });
+        level = Vec::new();
+        for (path, (is_real_dir, file)) in paths.into_iter().zip(kinds) {
+            let is_file = file.unwrap_or_else(|e| {
+                eprintln!("Warning: Cannot access '{}': {}", path.display(), e);
+                false
+            });
+            if depth < max_depth && is_real_dir {
+                level.push(path.clone());
+            }
+            scanned.push(ScannedEntry { path, is_file });
+        }
+        depth += 1;
+    }
+    Ok(scanned)
+}
+
+/// Writes the processed marker when `--mark-processed` is set, warning if that fails.
+fn mark_if_requested(options: &Options, dir: &Path, video_extensions: &HashSet<String>) {
+    if options.mark_processed
+        && !options.dry_run
+        && let Err(e) = write_processed_marker(dir, video_extensions)
+    {
+        eprintln!(
+            "Warning: Could not write processed marker in '{}': {}",
+            dir.display(),
+            e
+        );
+    }
+}
+
+/// `--normalize-to-utf8nfc-only`: renames files whose names aren't in NFC to their NFC form and
+/// changes nothing else. Returns the exit code.
+fn renormalize_to_nfc(
+    options: &Options,
+    target_directory: &Path,
+    summary: &mut RunSummary,
+    prompter: &mut Prompter,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let mut renames: Vec<(PathBuf, PathBuf)> = Vec::new();
+    // Every entry as the directory listing spells it, to tell a real NFC twin from the file
+    // itself (a normalization-insensitive filesystem resolves the NFC spelling to it anyway)
+    let mut listed: HashSet<PathBuf> = HashSet::new();
+    let mut pending_dirs = vec![(target_directory.to_path_buf(), 1)];
+    while let Some((dir, depth)) = pending_dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if dir == target_directory => return Err(e.into()),
+            Err(e) => {
+                eprintln!("Warning: Cannot read directory '{}': {}", dir.display(), e);
+                continue;
+            }
+        };
+        for entry_result in entries {
+            let entry = match entry_result {
+                Ok(entry) => entry,
+                Err(e) => {
+                    eprintln!(
+                        "Warning: Cannot read an entry of '{}': {}",
+                        dir.display(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            listed.insert(entry.path());
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(e) => {
+                    eprintln!("Warning: Cannot access '{}': {}", entry.path().display(), e);
+                    continue;
+                }
+            };
+            if file_type.is_dir() {
+                if depth < options.scan_depth() {
+                    pending_dirs.push((entry.path(), depth + 1));
+                }
+                continue;
+            }
+            if !file_type.is_file() {
+                continue;
+            }
+            summary.found += 1; // Every file, reported as "Files checked"
+            let file_name = entry.file_name();
+            let Some(name) = file_name.to_str() else {
+                continue; // Not UTF-8, so there is no NFC form to move to
+            };
+            let normalized = nfc::to_nfc(name);
+            if normalized == name {
+                summary.correct += 1;
+            } else {
+                renames.push((entry.path(), dir.join(normalized)));
+            }
+        }
+    }
+    renames.sort();
+
+    if renames.is_empty() {
+        say!("\nAll file names are already NFC-normalized.");
+        summary.outcome = "nothing_to_do";
+        return Ok(0);
+    }
+
+    // Both spellings look identical on screen, so the table shows where each file lives.
+    say!("\nFiles to renormalize to NFC:");
+    say!("--------------------");
+    for (old, _) in &renames {
+        say!("{}", relative_name(old, target_directory));
+    }
+    say!("--------------------");
+
+    summary.actions = renames
+        .iter()
+        .map(|(old, new)| ActionRecord {
+            from: old.clone(),
+            to: new.clone(),
+            conflict: false,
+            overwrite: false,
+            status: ActionStatus::NotApplied,
+        })
+        .collect();
+
+    // An NFC twin listed next to the file is exactly the duplicate-looking entry this mode is
+    // for; renaming over it would lose a file. So would two spellings that share one NFC form.
+    let mut target_counts: HashMap<&PathBuf, usize> = HashMap::new();
+    for (_, new) in &renames {
+        *target_counts.entry(new).or_insert(0) += 1;
+    }
+    let mut conflicts: Vec<&PathBuf> = Vec::new();
+    for (_, new) in &renames {
+        if conflicts.contains(&new) {
+            continue;
+        }
+        if listed.contains(new) || target_counts[new] > 1 {
+            conflicts.push(new);
+        }
+    }
+    if !conflicts.is_empty() {
+        eprintln!("\nWarning: Potential conflicts detected!");
+        for new in &conflicts {
+            if target_counts[*new] > 1 {
+                eprintln!(
+                    "- Multiple files would be renormalized to '{}'.",
+                    relative_name(new, target_directory)
+                );
+            } else {
+                eprintln!(
+                    "- Target '{}' already exists in NFC form.",
+                    relative_name(new, target_directory)
+                );
+            }
+        }
+        eprintln!("Please resolve conflicts before proceeding.");
+        summary.conflicts = conflicts.len();
+        for action in &mut summary.actions {
+            action.conflict = conflicts.contains(&&action.to);
+        }
+        summary.outcome = "conflicts";
+        return Ok(1);
+    }
+
+    if options.plan_only() {
+        say!("\nDry run: no files were changed.");
+        summary.outcome = "dry_run";
+        return Ok(0);
+    }
+
+    if !confirm_batch(prompter, renames.len(), options)? {
+        say!("Renormalizing cancelled.");
+        summary.outcome = "cancelled";
+        return Ok(EXIT_CANCELLED);
+    }
+    // The same path as any other rename: undo log, --retries and --atomic all apply
+    let (success_count, error_count) =
+        apply_renames(&mut summary.actions, options, target_directory)?;
+    summary.renamed = success_count;
+    summary.failed = error_count;
+    say!(
+        "Renormalized {} file(s) to NFC, {} failed.",
+        summary.renamed,
+        summary.failed
+    );
+    summary.outcome = "completed";
+    if options.atomic && error_count > 0 && success_count == 0 {
+        summary.outcome = "rolled_back";
+        return Ok(1); // Nothing stayed applied
+    }
+    Ok(if summary.failed > 0 {
+        EXIT_PARTIAL_FAILURE
+    } else {
+        0
+    })
+}
+
+/// `--plan`: applies renames decided by another tool, with the same conflict checks and
+/// confirmation as planned ones. Every source must exist. Returns the exit code.
+fn run_plan(
+    plan: &[(PathBuf, PathBuf)],
+    options: &Options,
+    target_directory: &Path,
+    summary: &mut RunSummary,
+    prompter: &mut Prompter,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let mut proposed_renames: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut all_paths_in_dir: HashSet<PathBuf> = HashSet::new();
+    let mut problems: Vec<String> = Vec::new();
+    let mut sources: HashSet<PathBuf> = HashSet::new();
+    for (old, new) in plan {
+        let (old, new) = (target_directory.join(old), target_directory.join(new));
+        summary.found += 1;
+        if fs::symlink_metadata(&old).is_err() {
+            problems.push(format!("'{}' does not exist.", old.display()));
+        } else if !sources.insert(old.clone()) {
+            problems.push(format!("'{}' is listed more than once.", old.display()));
+        } else if old == new {
+            summary.correct += 1;
+        } else {
+            // Existing targets count for conflict checking, as scanned files do
+            if fs::symlink_metadata(&new).is_ok() {
+                all_paths_in_dir.insert(new.clone());
+            }
+            all_paths_in_dir.insert(old.clone());
+            proposed_renames.insert(old, new);
+        }
+    }
+    if !problems.is_empty() {
+        eprintln!("Error: The plan can't be applied:");
+        for problem in &problems {
+            eprintln!("- {}", problem);
+        }
+        summary.outcome = "error";
+        return Ok(1);
+    }
+    if summary.correct > 0 {
+        say!(
+            "{} file(s) already correctly named; leaving them alone.",
+            summary.correct
+        );
+    }
+
+    let scan = Scan {
+        parsed_files_info: Vec::new(),
+        all_paths_in_dir,
+        folders_needing_input: BTreeSet::new(),
+    };
+    execute_renames(
+        options,
+        summary,
+        prompter,
+        &scan,
+        proposed_renames,
+        &[],
+        target_directory,
+    )
+}
+
+/// `--undo`: reverts the most recent run recorded in the undo log of `target_directory`.
+/// Entries whose new name is gone or whose old name is taken again are skipped and stay in
+/// the log, so they can be retried. Returns the exit code.
+fn undo_last_run(
+    options: &Options,
+    target_directory: &Path,
+    summary: &mut RunSummary,
+    prompter: &mut Prompter,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let log_path = target_directory.join(UNDO_LOG);
+    let contents = match fs::read_to_string(&log_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut runs: Vec<&str> = contents.lines().filter(|l| !l.trim().is_empty()).collect();
+    let Some(last_run) = runs.pop() else {
+        say!("\nNothing to undo: no undo log in this directory.");
+        summary.outcome = "nothing_to_do";
+        return Ok(0);
+    };
+    let entries = parse_undo_entry(last_run)
+        .map_err(|e| format!("Corrupt undo log '{}': {}", log_path.display(), e))?;
+
+    say!("\nRenames to revert:");
+    say!("--------------------");
+    for (from, to) in &entries {
+        say!(
+            "{} -> {}",
+            relative_name(to, target_directory),
+            relative_name(from, target_directory)
+        );
+    }
+    say!("--------------------");
+    summary.actions = entries
+        .iter()
+        .map(|(from, to)| ActionRecord {
+            from: to.clone(),
+            to: from.clone(),
+            conflict: false,
+            overwrite: false,
+            status: ActionStatus::NotApplied,
+        })
+        .collect();
+
+    if options.plan_only() {
+        say!("\nDry run: no files were changed.");
+        summary.outcome = "dry_run";
+        return Ok(0);
+    }
+    if !confirm_batch(prompter, entries.len(), options)? {
+        say!("Undo cancelled.");
+        summary.outcome = "cancelled";
+        return Ok(EXIT_CANCELLED);
+    }
+
+    // Revert in the reverse of the order the run applied them
+    let mut remaining: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for (index, (from, to)) in entries.iter().enumerate().rev() {
+        let skip_reason = if !to.exists() {
+            Some("no longer exists")
+        } else if from.exists() {
+            Some("has its original name taken again")
+        } else {
+            None
+        };
+        if let Some(reason) = skip_reason {
+            say!(
+                "Skipping '{}': it {}.",
+                relative_name(to, target_directory),
+                reason
+            );
+            summary.skipped += 1;
+            remaining.push((from.clone(), to.clone()));
+            continue;
+        }
+        // Moves may have crossed devices, so fall back to copy + remove like --mode move
+        match apply_action(Mode::Move, to, from) {
+            Ok(_) => {
+                say!(
+                    "Restored: '{}' to '{}'",
+                    relative_name(to, target_directory),
+                    relative_name(from, target_directory)
+                );
+                summary.actions[index].status = ActionStatus::Done;
+                summary.renamed += 1;
+            }
+            Err(e) => {
+                eprintln!(
+                    "Error restoring '{}': {}",
+                    relative_name(to, target_directory),
+                    e
+                );
+                summary.actions[index].status = ActionStatus::Failed(e.to_string());
+                summary.failed += 1;
+                remaining.push((from.clone(), to.clone()));
+            }
+        }
+    }
+    say!("--------------------");
+    say!(
+        "Undo complete. {} restored, {} skipped, {} failed.",
+        summary.renamed,
+        summary.skipped,
+        summary.failed
+    );
+
+    // Drop the reverted run from the log, keeping whatever couldn't be restored
+    let rewritten: String = runs.iter().map(|run| format!("{}\n", run)).collect();
+    fs::write(&log_path, &rewritten)?;
+    if !remaining.is_empty() {
+        remaining.reverse();
+        append_undo_log(target_directory, &remaining)?;
+    } else if rewritten.is_empty() {
+        fs::remove_file(&log_path)?;
+    }
+    summary.outcome = "completed";
+    Ok(if summary.failed > 0 {
+        EXIT_PARTIAL_FAILURE
+    } else {
+        0
+    })
+}
+
+/// Exit code when files need a prompt that `--yes` can't show.
+pub const EXIT_NEEDS_INPUT: i32 = 2;
+/// Exit code when some actions were applied but at least one failed.
+pub const EXIT_PARTIAL_FAILURE: i32 = 3;
+/// Exit code when the confirmation prompt was declined, so nothing was applied.
+pub const EXIT_CANCELLED: i32 = 4;
+
+/// The video extensions to process (lowercase, without the dot): the built-in list, replaced
+/// or extended by `--ext`.
+fn video_extensions(options: &Options) -> HashSet<String> {
+    // Define common video file extensions (lowercase for comparison)
+    let mut video_extensions: HashSet<String> = [
+        "mkv", "mp4", "avi", "mov", "wmv", "flv", "webm", "mpeg", "mpg", "ts", "m2ts",
+        "vob", // Add others if needed
+    ]
+    .iter()
+    .map(|&s| s.to_lowercase())
+    .collect();
+    if let Some(list) = &options.extensions {
+        if !list.extend_defaults {
+            video_extensions.clear();
+        }
+        video_extensions.extend(list.extensions.iter().cloned());
+    }
+    video_extensions
+}
+
+/// An "X/Y files" counter on stderr, redrawn in place at most ten times a second so big batches
+/// show the tool is working. On with `--progress`, or at normal verbosity when stdout is a
+/// terminal; piped and `--json` runs stay clean.
+struct Progress {
+    label: &'static str,
+    total: usize,
+    done: usize,
+    enabled: bool,
+    drawn: Option<(Instant, usize)>, // When the line was last drawn, and its length
+}
+
+impl Progress {
+    fn new(label: &'static str, total: usize, options: &Options) -> Progress {
+        let enabled = options.progress
+            || (options.verbosity == Verbosity::Normal
+                && !options.json
+                && io::stdout().is_terminal());
+        Progress {
+            label,
+            total,
+            done: 0,
+            enabled,
+            drawn: None,
+        }
+    }
+
+    fn tick(&mut self) {
+        self.done += 1;
+        let due = self.drawn.is_none_or(|(at, _)| {
+            self.done == self.total || at.elapsed() >= Duration::from_millis(100)
+        });
+        if self.enabled && due {
+            // The carriage return goes last so other output overwrites the line, not follows it
+            let line = format!("{} {}/{} files", self.label, self.done, self.total);
+            eprint!("{}\r", line);
+            self.drawn = Some((Instant::now(), line.len()));
+        }
+    }
+
+    /// Blanks the counter line once the work is done.
+    fn finish(&self) {
+        if let Some((_, len)) = self.drawn {
+            eprint!("{}\r", " ".repeat(len));
+        }
+    }
+}
+
+/// Files that are never episodes, whatever their extension (compared in lowercase).
+const JUNK_FILES: [&str; 3] = ["thumbs.db", "desktop.ini", ".ds_store"];
+
+/// Why Pass 1 leaves `path` out, if it does: a hidden file (".DS_Store", "._Show.S01E01.mkv"),
+/// a known junk file, or, unless `--include-samples`, a sample clip whose stem has "sample" as a
+/// word ("sample.mkv", "Show.S01E02-sample.mkv").
+fn junk_reason(path: &Path, options: &Options) -> Option<&'static str> {
+    let name = path.file_name().and_then(OsStr::to_str)?;
+    if name.starts_with('.') {
+        return Some("hidden file");
+    }
+    if JUNK_FILES.contains(&name.to_lowercase().as_str()) {
+        return Some("not an episode");
+    }
+    let stem = path.file_stem().and_then(OsStr::to_str).unwrap_or_default();
+    if !options.include_samples && SAMPLE_RE.is_match(stem) {
+        return Some("sample clip");
+    }
+    None
+}
+
+/// What Pass 1 found: the parsed video files, every path seen (for conflict checking) and the
+/// folders holding files that need a show name or season prompt.
+struct Scan {
+    parsed_files_info: Vec<ParsedInfo>,
+    all_paths_in_dir: HashSet<PathBuf>,
+    folders_needing_input: BTreeSet<PathBuf>,
+}
+
+/// The show most files of each folder name, as (cleaned, as written). Ties go to the show
+/// first in alphabetical order.
+fn common_show_names(infos: &[ParsedInfo]) -> HashMap<PathBuf, (String, String)> {
+    let mut counts: BTreeMap<(&Path, &str), (usize, &str)> = BTreeMap::new();
+    for info in infos {
+        if let (Some(folder), Some(show)) = (info.original_path.parent(), &info.show_name_part) {
+            let source = info.show_name_source.as_deref().unwrap_or(show);
+            counts.entry((folder, show)).or_insert((0, source)).0 += 1;
+        }
+    }
+    let mut common: HashMap<PathBuf, (String, String, usize)> = HashMap::new();
+    for ((folder, show), (count, source)) in counts {
+        let best = common.get(folder).map_or(0, |(_, _, best)| *best);
+        if count > best {
+            common.insert(
+                folder.to_path_buf(),
+                (show.to_string(), source.to_string(), count),
+            );
+        }
+    }
+    common
+        .into_iter()
+        .map(|(folder, (show, source, _))| (folder, (show, source)))
+        .collect()
+}
+
+/// Pass 1: parses every video file under `target_directory`, counting the ones that can't be
+/// parsed at all as skipped.
+fn scan_episodes(
+    options: &Options,
+    target_directory: &Path,
+    destination_dir: &Path,
+    video_extensions: &HashSet<String>,
+    summary: &mut RunSummary,
+) -> io::Result<Scan> {
+    let script_path = env::current_exe().ok(); // To avoid renaming the script itself
+    let mut parsed_files_info: Vec<ParsedInfo> = Vec::new();
+    let mut all_paths_in_dir: HashSet<PathBuf> = HashSet::new(); // Keep track of all items for conflict checking
+    let mut older = 0; // Videos left alone by --newer-than
+
+    // Pass 1: Parse all relevant files and identify if user input is globally needed
+    say!("Filtering for video files: {:?}", video_extensions);
+    let ignore_patterns = load_ignore_patterns(target_directory);
+    let entries = scan_tree(
+        target_directory,
+        options.scan_depth(),
+        options.max_parallel_io,
+    )?;
+    let mut progress = Progress::new("Parsed", entries.len(), options);
+    for entry in entries {
+        progress.tick();
+        let path = entry.path;
+        all_paths_in_dir.insert(path.clone());
+
+        if script_path.as_ref() == Some(&path) {
+            continue; // Skip the running script
+        }
+        if path.file_name() == Some(OsStr::new(PROCESSED_MARKER)) {
+            continue; // Never parse or rename our own marker
+        }
+        if let Some(reason) = junk_reason(&path, options) {
+            debug!("Ignoring '{}': {}.", display_name(&path), reason);
+            continue; // Still in all_paths_in_dir, so nothing is renamed over it
+        }
+        if let Some(name) = path.file_name().and_then(OsStr::to_str)
+            && ignore_patterns.iter().any(|pattern| pattern.is_match(name))
+        {
+            debug!(
+                "Ignoring '{}': listed in {}.",
+                display_name(&path),
+                IGNORE_FILE
+            );
+            continue;
+        }
+
+        if entry.is_file {
+            let extension = path
+                .extension()
+                .and_then(OsStr::to_str)
+                .map(str::to_lowercase)
+                .unwrap_or_default();
+
+            if !video_extensions.contains(&extension) {
+                debug!("Ignoring '{}': not a video file.", display_name(&path));
+                continue; // Skip non-video files
+            }
+
+            // --newer-than: leave files from earlier imports alone
+            if let Some(limit) = options.newer_than {
+                let age = fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .map(|modified| modified.elapsed().unwrap_or_default());
+                match age {
+                    Ok(age) if age > limit => {
+                        debug!(
+                            "Leaving '{}' alone: modified before --newer-than.",
+                            display_name(&path)
+                        );
+                        older += 1;
+                        continue;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        eprintln!(
+                            "Warning: Skipping '{}': could not read its modified time: {}.",
+                            path.display(),
+                            e
+                        );
+                        summary.count_skip("unknown modified time");
+                        continue;
+                    }
+                }
+            }
+
+            // Parse the video file
+            match parse_filename(&path, options) {
+                Ok(mut info) => {
+                    summary.found += 1;
+                    // Rescue a badly named video from its sidecar subtitle, keeping the video's own path
+                    if options.subtitle_fallback
+                        && (info.season_prefix_part.is_none() || info.episode_number_part.is_none())
+                        && let Some(borrowed) =
+                            subtitle_sidecar_info(&path, video_extensions, options)
+                    {
+                        say!(
+                            "Using season/episode from subtitle '{}' for '{}'.",
+                            borrowed.original_filename,
+                            info.original_filename
+                        );
+                        // Only the numbering is borrowed; the rest of the name still describes
+                        // the video. A name without an episode marker was taken whole as the
+                        // show, so the subtitle's show is the better one there.
+                        if info.show_name_part.is_none() || info.episode_number_part.is_none() {
+                            info.show_name_part = borrowed.show_name_part;
+                            info.show_name_source = borrowed.show_name_source;
+                        }
+                        info.season_prefix_part = borrowed.season_prefix_part;
+                        info.episode_number_part = borrowed.episode_number_part;
+                        info.needs_user_input = info.show_name_part.is_none()
+                            || (info.season_prefix_part.is_none() && !options.absolute);
+                    }
+                    // The .nfo's metadata wins over what the name says
+                    if options.read_nfo
+                        && let Some(nfo) = read_nfo(&path)
+                    {
+                        say!(
+                            "Using metadata from the .nfo of '{}'.",
+                            info.original_filename
+                        );
+                        if let Some(show) = nfo.show {
+                            let show = options.ampersand.apply(&show);
+                            info.show_name_part = Some(clean_segment(&show, options.separator));
+                            info.show_name_source =
+                                Some(normalize_separators(&show, options.separator));
+                        }
+                        if let Some(season) = nfo.season {
+                            info.season_prefix_part = Some(format!("S{:02}", season));
+                        }
+                        // A multi-episode name ("E01E02") starting at that episode stays as it is
+                        if let Some(episode) = nfo.episode
+                            && info
+                                .episode_number_part
+                                .as_deref()
+                                .map(episode_numbers)
+                                .and_then(|numbers| numbers.first().copied())
+                                != Some(episode)
+                        {
+                            info.episode_number_part = Some(format!("E{:02}", episode));
+                        }
+                        info.needs_user_input = info.show_name_part.is_none()
+                            || (info.season_prefix_part.is_none() && !options.absolute);
+                    }
+                    let quoted = |part: &Option<String>| {
+                        part.as_ref()
+                            .map_or("-".to_string(), |part| format!("'{}'", part))
+                    };
+                    debug!(
+                        "Parsed '{}': show {}, season {}, episode {}, part {}, remainder {}{}",
+                        info.original_filename,
+                        quoted(&info.show_name_part),
+                        info.season_prefix_part.as_deref().unwrap_or("-"),
+                        info.episode_number_part.as_deref().unwrap_or("-"),
+                        info.part.map_or("-".to_string(), |part| part.to_string()),
+                        quoted(&info.remainder_part),
+                        if info.needs_user_input {
+                            " (needs input)"
+                        } else {
+                            ""
+                        }
+                    );
+                    if let Some(second_tag) = &info.second_tag {
+                        say!(
+                            "Warning: '{}' carries a second season/episode tag ('{}'); leaving it for manual review.",
+                            info.original_filename,
+                            second_tag
+                        );
+                        summary.count_skip("needs manual review");
+                        summary.needs_review.push(info.original_filename.clone());
+                        continue;
+                    }
+                    // Warn if essential SxxExx info seems missing for a video file
+                    if info.needs_user_input
+                        && info.season_prefix_part.is_none()
+                        && info.episode_number_part.is_none()
+                    {
+                        say!(
+                            "Warning: Video file '{}' is missing Season and Episode identifiers (SxxExx).",
+                            info.original_filename
+                        );
+                    }
+                    if info.needs_user_input {
+                        summary.needs_input += 1;
+                    }
+                    parsed_files_info.push(info);
+                }
+                Err(ParseError::NotAFile) => {} // Should not happen due to is_file check
+                Err(e) => {
+                    eprintln!("Warning: Skipping '{}': {}.", path.display(), e);
+                    summary.count_skip(&format!("no {}", e.missing_component()));
+                }
+            }
+        }
+    }
+    progress.finish();
+    if older > 0 {
+        say!(
+            "{} file(s) modified before --newer-than left untouched.",
+            older
+        );
+    }
+
+    // --infer-show: "e05.mkv" among "The.Expanse.S01E0x.mkv" files is The Expanse too
+    if options.infer_show {
+        let common_shows = common_show_names(&parsed_files_info);
+        for info in &mut parsed_files_info {
+            if info.show_name_part.is_none()
+                && let Some((show, source)) = info
+                    .original_path
+                    .parent()
+                    .and_then(|folder| common_shows.get(folder))
+            {
+                debug!(
+                    "Inferred show '{}' for '{}' from its folder.",
+                    source, info.original_filename
+                );
+                info.show_name_part = Some(show.clone());
+                info.show_name_source = Some(source.clone());
+                info.needs_user_input = info.season_prefix_part.is_none() && !options.absolute;
+            }
+        }
+    }
+    // Prompted once per folder
+    let folders_needing_input: BTreeSet<PathBuf> = parsed_files_info
+        .iter()
+        .filter(|info| info.needs_user_input && !options.fills_in(info))
+        .filter_map(|info| info.original_path.parent())
+        .map(Path::to_path_buf)
+        .collect();
+
+    // Existing entries of the destination also count for conflict checking.
+    if destination_dir != target_directory
+        && let Ok(entries) = fs::read_dir(destination_dir)
+    {
+        all_paths_in_dir.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()));
+    }
+    // So do those of existing season folders that --organize moves files into
+    if options.organize {
+        let folders: Vec<PathBuf> = all_paths_in_dir
+            .iter()
+            .filter(|path| path.is_dir())
+            .cloned()
+            .collect();
+        for folder in folders {
+            if let Ok(entries) = fs::read_dir(folder) {
+                all_paths_in_dir.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()));
+            }
+        }
+    }
+
+    Ok(Scan {
+        parsed_files_info,
+        all_paths_in_dir,
+        folders_needing_input,
+    })
+}
+
+/// Pass 2: builds the new name of every parsed file, applying the folder answers to the files
+/// that needed them, and adds their sidecars. Files already correctly named are counted, not
+/// planned.
+fn plan_new_names(
+    scan: &Scan,
+    folder_inputs: &HashMap<PathBuf, FolderInput>,
+    options: &Options,
+    target_directory: &Path,
+    destination_dir: &Path,
+    target_fs: FsType,
+    summary: &mut RunSummary,
+) -> HashMap<PathBuf, PathBuf> {
+    let mut proposed_renames: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut filtered_out = 0; // Files outside the --episodes selection
+    let mut show_names_by_folder: BTreeMap<PathBuf, BTreeSet<String>> = BTreeMap::new();
+    let (season_width, episode_width) = options.pad.widths(&scan.parsed_files_info);
+    // --title-command answers by (show, season, episode), so each episode is asked once
+    let mut fetched_titles: HashMap<(String, u32, u32), Option<String>> = HashMap::new();
+
+    for info in &scan.parsed_files_info {
+        // Start with parsed info, potentially override with global input
+        let mut final_show = info.show_name_part.clone();
+        let mut final_show_source = info.show_name_source.clone();
+        let mut final_season = info.season_prefix_part.clone();
+        let final_episode = info.episode_number_part.clone();
+        let mut final_remainder = if options.keep_remainder_case {
+            info.remainder_source.clone()
+        } else {
+            info.remainder_part.clone()
+        };
+        let mut final_extension = info.extension.clone();
+        if options.lowercase_ext {
+            // A name differing only in extension case is then not already correct, so it's renamed
+            final_extension.make_ascii_lowercase();
+        }
+
+        // --name and --season fill in what the file's name lacks; --force-name replaces its show too
+        if let Some(name) = &options.show_name
+            && (final_show.is_none() || options.force_name)
+        {
+            let name = options.ampersand.apply(name);
+            final_show = Some(clean_segment(&name, options.separator));
+            final_show_source = Some(normalize_separators(&name, options.separator));
+        }
+        if let Some(season) = options.season
+            && final_season.is_none()
+        {
+            final_season = Some(format!("S{:02}", season));
+        }
+
+        // Apply the folder's overrides only if input was needed for this file and successfully provided
+        let folder_input = info
+            .original_path
+            .parent()
+            .and_then(|folder| folder_inputs.get(folder));
+        if info.needs_user_input
+            && let Some(input) = folder_input
+        {
+            if let Some(show) = &input.show_name {
+                final_show = Some(show.clone());
+                final_show_source = input.show_source.clone();
+            }
+            if let Some(season) = &input.season_prefix {
+                final_season = Some(season.clone());
+            }
+
+            // Critical check: Can we form "SxxExx" after potential overrides?
+            if final_season.is_none() || final_episode.is_none() {
+                say!(
+                    "Skipping '{}': Cannot determine final Season/Episode ({} / {}) after prompts.",
+                    info.original_filename,
+                    final_season.as_deref().unwrap_or("Missing"),
+                    final_episode.as_deref().unwrap_or("Missing")
+                );
+                let missing = match (final_season.is_none(), final_episode.is_none()) {
+                    (true, true) => "season and episode",
+                    (true, false) => "season",
+                    _ => "episode",
+                };
+                summary.note_skip(info, missing);
+                continue; // Skip this file if essential parts are missing
+            }
+        }
+
+        // --episodes: leave files outside the selection alone. A multi-episode file matches
+        // when any of its episodes does; absolute-numbered episodes without a season never do.
+        if !options.episode_filters.is_empty()
+            && let Some(episode) = &final_episode
+        {
+            let season = final_season.as_deref().and_then(season_number);
+            let selected = season.is_some_and(|season| {
+                episode_numbers(episode).into_iter().any(|episode| {
+                    options
+                        .episode_filters
+                        .iter()
+                        .any(|filter| filter.matches(season, episode))
+                })
+            });
+            if !selected {
+                debug!(
+                    "Leaving '{}' alone: outside the --episodes selection.",
+                    info.original_filename
+                );
+                filtered_out += 1;
+                continue;
+            }
+        }
+
+        // aliases.toml: every variant of a show converges on one name, cased like any other
+        if let Some(canonical) = final_show
+            .as_deref()
+            .and_then(|show| options.show_aliases.canonical(show))
+        {
+            final_show = Some(clean_segment(canonical, options.separator));
+            final_show_source = Some(normalize_separators(canonical, options.separator));
+        }
+
+        // Collect the template fields piece by piece
+        let mut fields: HashMap<&str, String> = HashMap::new();
+
+        // Every new name needs a show, a season and an episode; --absolute drops the season
+        let required = match (final_show, final_season, final_episode) {
+            (Some(show), _, _) if show.is_empty() => Err(ParseError::EmptyShowName),
+            (None, _, _) => Err(ParseError::MissingShowName),
+            (_, None, _) if !options.absolute => Err(ParseError::MissingSeason),
+            (_, _, None) => Err(ParseError::NoEpisodeMarker),
+            (Some(show), season, Some(episode)) => Ok((show, season, episode)),
+        };
+        let (show, season, episode) = match required {
+            Ok(parts) => parts,
+            Err(e) => {
+                say!("Warning: Skipping '{}': {}.", info.original_filename, e);
+                summary.note_skip(info, e.missing_component());
+                continue;
+            }
+        };
+
+        // 1. Show Name (Title Case unless --case says otherwise)
+        let show =
+            options
+                .case
+                .show_name(&show, final_show_source.as_deref(), &options.title_words);
+        // Names are dot-separated up to here; --separator takes over from the dots now
+        let separator = options.separator.to_string();
+        if let Some(folder) = info.original_path.parent() {
+            show_names_by_folder
+                .entry(folder.to_path_buf())
+                .or_default()
+                .insert(show.clone());
+        }
+        fields.insert("show_spaced", join_words(show.split('.'), " "));
+        fields.insert("show", join_words(show.split('.'), &separator));
+        fields.insert("ids", info.id_tags.join(&separator));
+        fields.insert("group", info.release_group.clone().unwrap_or_default());
+
+        // The fetched title replaces the one in the remainder; its release tags stay
+        if let Some(command) = &options.title_command
+            && let Some(season) = season.as_deref().and_then(season_number)
+            && let Some(&first_episode) = episode_numbers(&episode).first()
+        {
+            let key = (show.clone(), season, first_episode);
+            let title = fetched_titles.entry(key).or_insert_with(|| {
+                let show = show.replace('.', " ");
+                fetch_episode_title(command, &show, season, first_episode, options.separator)
+            });
+            if let Some(title) = title {
+                let tags = final_remainder
+                    .as_deref()
+                    .map_or("", |rem| split_episode_title(rem).1);
+                final_remainder = Some(if tags.is_empty() {
+                    title.clone()
+                } else {
+                    format!("{}.{}", title, tags)
+                });
+            }
+        }
+
+        // 2. Season and Episode (Already formatted Sxx and Exx), padded to the batch's width
+        let season = season.map(|season| pad_tag(&season, season_width));
+        fields.insert("season", season.unwrap_or_default());
+        fields.insert("episode", pad_tag(&episode, episode_width));
+
+        // 3. Part indicator, always in the canonical "Part.N" form
+        if let Some(part) = info.part {
+            fields.insert("part", format!("Part{}{}", separator, part));
+        }
+
+        // 4. Remainder (Keep cleaned/lowercase, apart from known release tags, or as written with
+        // --keep-remainder-case), unless stripped.
+        // Stripped names can collide; the conflict checks below report those.
+        // {title} and {tags} split it at the first release tag (see split_episode_title).
+        if let Some(rem) = final_remainder.filter(|rem| !rem.is_empty() && !options.strip_remainder)
+        {
+            let case_segments = |text: &str| -> Vec<String> {
+                match options.case {
+                    _ if options.keep_remainder_case => {
+                        text.split('.').map(str::to_string).collect()
+                    }
+                    Case::Upper => text.split('.').map(str::to_uppercase).collect(),
+                    Case::Title | Case::Keep if options.canonical_tags => {
+                        canonicalize_release_tags(text)
+                    }
+                    _ => text.split('.').map(str::to_string).collect(),
+                }
+            };
+            let (title, tags) = split_episode_title(&rem);
+            // Spaced between segments only, so tags like "DDP5.1" keep their dot
+            for (field, text) in [
+                ("remainder", rem.as_str()),
+                ("title", title),
+                ("tags", tags),
+            ] {
+                if text.is_empty() {
+                    continue;
+                }
+                let segments = case_segments(text);
+                fields.insert(
+                    match field {
+                        "remainder" => "remainder_spaced",
+                        "title" => "title_spaced",
+                        _ => "tags_spaced",
+                    },
+                    join_words(&segments, " "),
+                );
+                fields.insert(field, join_words(&segments, &separator));
+            }
+        }
+
+        // Fill in the template, appending the original extension bytes untouched
+        let template = options
+            .template
+            .clone()
+            .unwrap_or_else(|| default_template(options.id_position, options.separator));
+        let altered = Cell::new(false);
+        let mut new_filename = render_template(&template, &fields, &final_extension, |text| {
+            let safe = sanitize_filename(text, target_fs, options);
+            altered.set(altered.get() || safe != text);
+            safe
+        });
+        // Windows drops trailing dots and spaces, so a name ending in them isn't the name you get
+        if target_fs == FsType::Windows
+            && let Some(name) = new_filename.to_str()
+            && name.ends_with(['.', ' '])
+        {
+            new_filename = OsString::from(name.trim_end_matches(['.', ' ']));
+            altered.set(true);
+        }
+        if altered.get() {
+            say!(
+                "Note: The new name for '{}' had characters illegal on the target filesystem replaced.",
+                info.original_filename
+            );
+        }
+
+        // Check if the destination actually differs from the original (in-place renames
+        // whose name is unchanged are no-ops; copies/moves to --out-dir always apply).
+        // Without --out-dir every file stays in its own folder, including subfolders.
+        let mut new_dir = match (&options.out_dir, info.original_path.parent()) {
+            (None, Some(folder)) => folder,
+            _ => destination_dir,
+        }
+        .to_path_buf();
+        // --organize: into a "Season NN" folder, unless the file is already in it
+        if options.organize
+            && let Some(season) = fields
+                .get("season")
+                .and_then(|season| season_number(season))
+        {
+            let folder_name = season_folder_name(season, options);
+            if get_dir_name(&new_dir).as_deref() != Some(folder_name.as_str()) {
+                new_dir.push(folder_name);
+            }
+        }
+        let new_path = new_dir.join(new_filename);
+        if already_correct(&info.original_path, &new_path) {
+            debug!(
+                "Leaving '{}' alone: already correctly named.",
+                info.original_filename
+            );
+            summary.correct += 1;
+        } else {
+            proposed_renames.insert(info.original_path.clone(), new_path);
+        }
+    }
+
+    // Files in one folder should all belong to one show; a mix usually means inconsistent
+    // source names rather than two shows sharing a folder.
+    for (folder, show_names) in show_names_by_folder {
+        if show_names.len() > 1 {
+            let folder = relative_name(&folder, target_directory).into_owned();
+            let show_names: Vec<String> = show_names.into_iter().collect();
+            say!(
+                "Warning: Files in '{}' resolve to different show names: {}. Check whether they belong to one show.",
+                folder,
+                show_names.join(", ")
+            );
+            summary.mixed_show_names.push((folder, show_names));
+        }
+    }
+
+    // Companion subtitles and metadata follow their video, unless managed separately
+    if options.rename_sidecars {
+        let sidecars = plan_sidecar_renames(
+            &proposed_renames,
+            &scan.all_paths_in_dir,
+            &sidecar_extensions(options),
+        );
+        proposed_renames.extend(sidecars);
+    }
+
+    if filtered_out > 0 {
+        say!(
+            "{} file(s) outside the --episodes selection left untouched.",
+            filtered_out
+        );
+    }
+    proposed_renames
+}
+
+/// With `--rename-dir`, plans renames for the folders holding the parsed files: a season folder
+/// ("season 1", "S1") becomes "Season 01" (the specials name for season 0) and the show folder
+/// above it, or the file's own folder when that isn't a season folder, takes the show name's
+/// casing with spaces. "Series N" folders keep their name. Only folders strictly inside
+/// `target_directory` are renamed, deepest first, so a season folder goes before its show folder.
+fn plan_folder_renames(
+    scan: &Scan,
+    options: &Options,
+    target_directory: &Path,
+) -> Vec<(PathBuf, PathBuf)> {
+    let inside = |folder: &Path| folder != target_directory && folder.starts_with(target_directory);
+    let folders: BTreeSet<&Path> = scan
+        .parsed_files_info
+        .iter()
+        .filter_map(|info| info.original_path.parent())
+        .collect();
+
+    let mut renames: BTreeMap<PathBuf, PathBuf> = BTreeMap::new();
+    for folder in folders {
+        let mut show_folder = Some(folder);
+        if let Some(season) = season_of_folder(folder, options) {
+            show_folder = folder.parent();
+            if let FolderSeason::Season(season) = season
+                && inside(folder)
+            {
+                let new_name = season_folder_name(season, options);
+                renames.insert(folder.to_path_buf(), folder.with_file_name(new_name));
+            }
+        }
+        if let Some(show_folder) = show_folder.filter(|folder| inside(folder))
+            && let Some(name) = get_dir_name(show_folder)
+        {
+            let source = normalize_separators(&options.ampersand.apply(&name), '.');
+            let show =
+                options
+                    .case
+                    .show_name(&source.to_lowercase(), Some(&source), &options.title_words);
+            renames.insert(
+                show_folder.to_path_buf(),
+                show_folder.with_file_name(join_words(show.split('.'), " ")),
+            );
+        }
+    }
+
+    let mut renames: Vec<(PathBuf, PathBuf)> = renames
+        .into_iter()
+        .filter(|(old, new)| old != new)
+        .collect();
+    renames.sort_by_key(|(old, _)| std::cmp::Reverse(old.components().count()));
+    renames
+}
+
+/// Finds planned targets that are taken: by an existing file that isn't moving away (unless it's
+/// in `overwrites`), or by more than one planned action. Returns a message per conflict and the
+/// conflicting targets.
+fn find_conflicts(
+    proposed_renames: &HashMap<PathBuf, PathBuf>,
+    all_paths_in_dir: &HashSet<PathBuf>,
+    overwrites: &HashSet<PathBuf>,
+    options: &Options,
+    target_directory: &Path,
+) -> (Vec<String>, HashSet<PathBuf>) {
+    let mut potential_conflicts = Vec::new();
+    let mut conflicting_targets: HashSet<PathBuf> = HashSet::new();
+    let target_filenames: HashSet<&PathBuf> = proposed_renames.values().collect(); // Targets being renamed TO
+
+    // Check if a target filename already exists in the directory *and* is not itself being renamed from.
+    // Copies and links keep their sources, so for those modes any existing target is a conflict.
+    for target_path in target_filenames {
+        let vacated = options.mode.removes_source() && proposed_renames.contains_key(target_path);
+        if all_paths_in_dir.contains(target_path) && !vacated && !overwrites.contains(target_path) {
+            conflicting_targets.insert(target_path.clone());
+            potential_conflicts.push(format!(
+                "Target '{}' already exists and is not being renamed.",
+                relative_name(target_path, target_directory)
+            ));
+        }
+    }
+
+    // Check if multiple files are being renamed TO the same target filename
+    let mut target_counts: HashMap<&PathBuf, usize> = HashMap::new();
+    for target_path in proposed_renames.values() {
+        *target_counts.entry(target_path).or_insert(0) += 1;
+    }
+
+    for (target_path, count) in target_counts {
+        if count > 1 {
+            conflicting_targets.insert(target_path.clone());
+            let conflicting_originals: Vec<String> = proposed_renames
+                .iter()
+                .filter(|&(_, new)| new == target_path)
+                .map(|(old, _)| relative_name(old, target_directory).into_owned())
+                .collect();
+            potential_conflicts.push(format!(
+                "Multiple files would be renamed to '{}': {:?}",
+                relative_name(target_path, target_directory),
+                conflicting_originals
+            ));
+        }
+    }
+
+    // Where case is ignored, "Show.S01E02.mkv" and "show.s01e02.MKV" are one file, so targets are
+    // compared case-folded too. A file's own case-only rename is fine.
+    if ignores_case(options, target_directory) {
+        let fold = |path: &Path| path.to_string_lossy().to_lowercase();
+        let mut existing: HashMap<String, Vec<&PathBuf>> = HashMap::new();
+        for path in all_paths_in_dir {
+            existing.entry(fold(path)).or_default().push(path);
+        }
+        let mut by_folded_target: HashMap<String, BTreeSet<&PathBuf>> = HashMap::new();
+        for (old, new) in proposed_renames {
+            by_folded_target.entry(fold(new)).or_default().insert(new);
+            if conflicting_targets.contains(new) || overwrites.contains(new) {
+                continue;
+            }
+            let clash = existing.get(&fold(new)).into_iter().flatten().find(|path| {
+                **path != new
+                    && **path != old
+                    && !(options.mode.removes_source() && proposed_renames.contains_key(**path))
+            });
+            if let Some(clash) = clash {
+                conflicting_targets.insert(new.clone());
+                potential_conflicts.push(format!(
+                    "Target '{}' differs only in case from existing '{}', which is the same file \
+                     on a case-insensitive filesystem.",
+                    relative_name(new, target_directory),
+                    relative_name(clash, target_directory)
+                ));
+            }
+        }
+        let mut clashing: Vec<Vec<String>> = by_folded_target
+            .into_values()
+            .filter(|targets| targets.len() > 1)
+            .map(|targets| {
+                conflicting_targets.extend(targets.iter().map(|path| (*path).clone()));
+                targets
+                    .iter()
+                    .map(|path| relative_name(path, target_directory).into_owned())
+                    .collect()
+            })
+            .collect();
+        clashing.sort();
+        for targets in clashing {
+            potential_conflicts.push(format!(
+                "Targets differ only in case and are the same file on a case-insensitive \
+                 filesystem: {:?}",
+                targets
+            ));
+        }
+    }
+
+    (potential_conflicts, conflicting_targets)
+}
+
+/// Whether names differing only in case name the same file at the destination: with
+/// `--case-insensitive`, on macOS and Windows, and on filesystems with Windows naming rules.
+fn ignores_case(options: &Options, target_directory: &Path) -> bool {
+    let destination = options.out_dir.as_deref().unwrap_or(target_directory);
+    options.case_insensitive
+        || cfg!(any(target_os = "macos", windows))
+        || options.target_fs.resolve(destination) == FsType::Windows
+}
+
+/// With `--force`, the planned renames whose target is an existing file that may be replaced,
+/// sorted. A file that is itself being renamed, copied or moved is never offered, nor is a
+/// target two actions share.
+fn overwrite_candidates(
+    proposed_renames: &HashMap<PathBuf, PathBuf>,
+    all_paths_in_dir: &HashSet<PathBuf>,
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut target_counts: HashMap<&PathBuf, usize> = HashMap::new();
+    for target_path in proposed_renames.values() {
+        *target_counts.entry(target_path).or_insert(0) += 1;
+    }
+    let mut candidates: Vec<(PathBuf, PathBuf)> = proposed_renames
+        .iter()
+        .filter(|&(_, new)| {
+            all_paths_in_dir.contains(new)
+                && !proposed_renames.contains_key(new)
+                && target_counts[new] == 1
+        })
+        .map(|(old, new)| (old.clone(), new.clone()))
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+/// Asks for each of the `overwrite_candidates` whether to replace its target; declined entries
+/// are dropped from the plan, leaving both files alone. `--yes` replaces them all without
+/// asking; a plan-only run asks nothing and only lists them. Returns the targets that may be
+/// replaced.
+fn confirm_overwrites(
+    prompter: &mut Prompter,
+    candidates: Vec<(PathBuf, PathBuf)>,
+    proposed_renames: &mut HashMap<PathBuf, PathBuf>,
+    options: &Options,
+    target_directory: &Path,
+) -> io::Result<HashSet<PathBuf>> {
+    let mut overwrites = HashSet::new();
+    for (old, new) in candidates {
+        let target = relative_name(&new, target_directory);
+        let overwrite = if options.assume_yes {
+            true
+        } else if options.plan_only() {
+            say!(
+                "'{}' already exists; --force would ask to overwrite it.",
+                target
+            );
+            true
+        } else {
+            let answer = prompter.ask(&format!(
+                "'{}' already exists. Overwrite it with '{}'? (y/n) [default: n]: ",
+                target,
+                relative_name(&old, target_directory)
+            ))?;
+            matches!(answer.to_lowercase().as_str(), "y" | "yes")
+        };
+        if overwrite {
+            overwrites.insert(new);
+        } else {
+            say!(
+                "Skipping '{}': kept the existing '{}'.",
+                relative_name(&old, target_directory),
+                target
+            );
+            proposed_renames.remove(&old);
+        }
+    }
+    Ok(overwrites)
+}
+
+/// Plans new names for the video files in `dir` (and below it with `options.recursive`) without
+/// prompting or changing anything. Files that would need a show name or season prompt are left
+/// out, and actions whose target is taken are marked as conflicts. Sorted by original path.
+pub fn plan_renames(dir: &Path, options: &Options) -> io::Result<Vec<ActionRecord>> {
+    let target_directory = fs::canonicalize(dir)?;
+    let destination_dir = options
+        .out_dir
+        .clone()
+        .unwrap_or_else(|| target_directory.clone());
+    let target_fs = options.target_fs.resolve(&destination_dir);
+    let mut summary = RunSummary::default();
+    let scan = scan_episodes(
+        options,
+        &target_directory,
+        &destination_dir,
+        &video_extensions(options),
+        &mut summary,
+    )?;
+    let proposed_renames = plan_new_names(
+        &scan,
+        &HashMap::new(),
+        options,
+        &target_directory,
+        &destination_dir,
+        target_fs,
+        &mut summary,
+    );
+    let (_, conflicting_targets) = find_conflicts(
+        &proposed_renames,
+        &scan.all_paths_in_dir,
+        &HashSet::new(),
+        options,
+        &target_directory,
+    );
+
+    let mut actions: Vec<ActionRecord> = proposed_renames
+        .into_iter()
+        .map(|(from, to)| ActionRecord {
+            conflict: conflicting_targets.contains(&to),
+            overwrite: false,
+            from,
+            to,
+            status: ActionStatus::NotApplied,
+        })
+        .collect();
+    actions.sort_by(|a, b| a.from.cmp(&b.from));
+    Ok(actions)
+}
+
+/// The order to apply renames in so that no target is overwritten before its own action has
+/// moved it away ("B -> C" before "A -> B"), plus the actions that must first move to a temporary
+/// name to break a cycle ("A -> B", "B -> A"). Actions marked as conflicts are left out.
+fn apply_order(actions: &[ActionRecord]) -> (Vec<usize>, HashSet<usize>) {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Visit {
+        New,
+        OnChain,
+        Ordered,
+    }
+    let source_index: HashMap<&Path, usize> = actions
+        .iter()
+        .enumerate()
+        .filter(|(_, action)| !action.conflict)
+        .map(|(index, action)| (action.from.as_path(), index))
+        .collect();
+    let mut visits = vec![Visit::New; actions.len()];
+    let mut order = Vec::new();
+    let mut staged = HashSet::new();
+
+    // A target is the source of at most one other action, so dependencies form chains: follow
+    // each chain to its free end, then apply it back to front.
+    for start in 0..actions.len() {
+        if actions[start].conflict || visits[start] != Visit::New {
+            continue;
+        }
+        let mut chain = Vec::new();
+        let mut index = start;
+        loop {
+            visits[index] = Visit::OnChain;
+            chain.push(index);
+            match source_index.get(actions[index].to.as_path()) {
+                Some(&next) if next != index && visits[next] == Visit::New => index = next,
+                // Back on this chain: a cycle, broken by moving that file aside first
+                Some(&next) if next != index && visits[next] == Visit::OnChain => {
+                    staged.insert(next);
+                    break;
+                }
+                _ => break,
+            }
+        }
+        for &index in chain.iter().rev() {
+            visits[index] = Visit::Ordered;
+            order.push(index);
+        }
+    }
+    (order, staged)
+}
+
+/// `apply_order` for the `--mode` in use. Copies and links keep their sources, so only renames
+/// and moves can depend on each other.
+fn mode_order(actions: &[ActionRecord], options: &Options) -> (Vec<usize>, HashSet<usize>) {
+    if options.mode.removes_source() {
+        apply_order(actions)
+    } else {
+        let order = (0..actions.len())
+            .filter(|&index| !actions[index].conflict)
+            .collect();
+        (order, HashSet::new())
+    }
+}
+
+/// Quotes `text` for a POSIX shell: single quotes, each inner quote written as '\''.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// `--emit-script`: the actions as a POSIX shell script, in the order `apply_renames` would
+/// apply them (cycles go through a temporary name first). Paths inside `target_directory` are
+/// relative to it; the script starts by changing into it. `mv -n` and `cp -n` never clobber a
+/// file, except where `--force` confirmed replacing it.
+fn rename_script(actions: &[ActionRecord], options: &Options, target_directory: &Path) -> String {
+    let path = |path: &Path| {
+        let relative = path.strip_prefix(target_directory).unwrap_or(path);
+        shell_quote(&relative.to_string_lossy())
+    };
+    let command = |action: &ActionRecord| match (options.mode, action.overwrite) {
+        (Mode::Copy, false) => "cp -n",
+        (Mode::Copy, true) => "cp -f",
+        (Mode::Link, false) => "ln",
+        (Mode::Link, true) => "ln -f",
+        (_, false) => "mv -n",
+        (_, true) => "mv -f",
+    };
+    let mut script = format!(
+        "#!/bin/sh\n# {} planned by rename-episodes\nset -e\ncd -- {}\n",
+        if options.mode == Mode::Rename {
+            "Renames"
+        } else {
+            "Actions"
+        },
+        shell_quote(&target_directory.to_string_lossy())
+    );
+    let (order, staged) = mode_order(actions, options);
+    let mut current: Vec<PathBuf> = actions.iter().map(|action| action.from.clone()).collect();
+    for &index in &staged {
+        let temporary = temporary_path(&actions[index].from);
+        script.push_str(&format!(
+            "mv -n -- {} {}\n",
+            path(&current[index]),
+            path(&temporary)
+        ));
+        current[index] = temporary;
+    }
+    let mut folders: HashSet<&Path> = HashSet::new();
+    for index in order {
+        let action = &actions[index];
+        if let Some(folder) = action.to.parent()
+            && action.from.parent() != Some(folder)
+            && folders.insert(folder)
+        {
+            script.push_str(&format!("mkdir -p -- {}\n", path(folder)));
+        }
+        script.push_str(&format!(
+            "{} -- {} {}\n",
+            command(action),
+            path(&current[index]),
+            path(&action.to)
+        ));
+    }
+    script
+}
+
+/// A free name next to `path` to park it under while a cycle of renames is applied.
+fn temporary_path(path: &Path) -> PathBuf {
+    (0..)
+        .map(|attempt| {
+            let mut name = OsString::from(".");
+            name.push(path.file_name().unwrap_or_default());
+            name.push(format!(".rename-episodes-{}.tmp", attempt));
+            path.with_file_name(name)
+        })
+        .find(|candidate| !candidate.exists())
+        .expect("an unused temporary name")
+}
+
+/// Applies each action with the `options.mode` operation, recording its status, and appends the
+/// successful renames and moves to the undo log in `target_directory`. Actions marked as
+/// conflicts are left alone. Renames that take each other's names are ordered, and cycles go
+/// through a temporary name, so no file is overwritten except an action's confirmed
+/// `overwrite` target. Returns how many actions succeeded and
+/// failed; fails only when the output directory can't be created.
+pub fn apply_renames(
+    actions: &mut [ActionRecord],
+    options: &Options,
+    target_directory: &Path,
+) -> io::Result<(usize, usize)> {
+    if let Some(out_dir) = &options.out_dir {
+        fs::create_dir_all(out_dir)?;
+    }
+    let mut success_count = 0;
+    let error_count = Cell::new(0); // Counted by `failure`, and read as the batch goes
+    let mut applied: Vec<usize> = Vec::new(); // In the order they were applied
+    // --atomic --force: files to be overwritten, moved aside until the batch has succeeded
+    let mut replaced: Vec<(usize, PathBuf)> = Vec::new();
+
+    let (order, staged) = mode_order(actions, options);
+    let source_index: HashMap<PathBuf, usize> = order
+        .iter()
+        .map(|&index| (actions[index].from.clone(), index))
+        .collect();
+
+    // Where each action's file currently is, and which sources are out of the way
+    let mut current: Vec<PathBuf> = actions.iter().map(|action| action.from.clone()).collect();
+    let mut vacated: HashSet<usize> = HashSet::new();
+    let failure = |action: &mut ActionRecord, message: String| {
+        eprintln!(
+            "Error {} '{}' to '{}': {}",
+            options.mode.verb(),
+            relative_name(&action.from, target_directory),
+            display_name(&action.to),
+            message
+        );
+        action.status = ActionStatus::Failed(message);
+        error_count.set(error_count.get() + 1);
+    };
+
+    for &index in &staged {
+        let temporary = temporary_path(&actions[index].from);
+        match fs::rename(&actions[index].from, &temporary) {
+            Ok(_) => {
+                say!(
+                    "Moved '{}' aside to '{}' to free its name.",
+                    relative_name(&actions[index].from, target_directory),
+                    display_name(&temporary)
+                );
+                current[index] = temporary;
+                vacated.insert(index);
+            }
+            Err(e) => failure(&mut actions[index], e.to_string()),
+        }
+    }
+
+    let mut progress = Progress::new(options.mode.past_tense(), order.len(), options);
+    for index in order {
+        progress.tick();
+        if options.atomic && error_count.get() > 0 {
+            break; // Everything applied so far is reverted below
+        }
+        if actions[index].status != ActionStatus::NotApplied {
+            continue; // Failed while moving aside
+        }
+        // The target is another action's source: that file must have moved away first
+        if let Some(&blocker) = source_index.get(&actions[index].to)
+            && blocker != index
+            && !vacated.contains(&blocker)
+        {
+            let message = format!(
+                "'{}' could not be moved out of the way",
+                display_name(&actions[blocker].from)
+            );
+            failure(&mut actions[index], message);
+            continue;
+        }
+        let action = &mut actions[index];
+        // --force: clear the confirmed file out of the way, unless another action's source
+        // has turned up there. With --atomic it is only moved aside, so a rollback can bring
+        // it back.
+        if action.overwrite
+            && !source_index.contains_key(&action.to)
+            && fs::symlink_metadata(&action.to).is_ok()
+        {
+            if options.atomic {
+                let temporary = temporary_path(&action.to);
+                if let Err(e) = fs::rename(&action.to, &temporary) {
+                    let message = format!("could not move the existing file aside: {}", e);
+                    failure(action, message);
+                    continue;
+                }
+                say!(
+                    "Moved existing '{}' aside to '{}' to overwrite it.",
+                    relative_name(&action.to, target_directory),
+                    display_name(&temporary)
+                );
+                replaced.push((index, temporary));
+            } else {
+                if let Err(e) = fs::remove_file(&action.to) {
+                    let message = format!("could not remove the existing file: {}", e);
+                    failure(action, message);
+                    continue;
+                }
+                say!(
+                    "Removed existing '{}' to overwrite it.",
+                    relative_name(&action.to, target_directory)
+                );
+            }
+        }
+        if options.organize
+            && let Some(folder) = action.to.parent()
+            && let Err(e) = fs::create_dir_all(folder)
+        {
+            let message = format!("could not create '{}': {}", display_name(folder), e);
+            failure(action, message);
+            continue;
+        }
+        match apply_with_retries(options, &current[index], &action.to) {
+            Ok(_) => {
+                say!(
+                    "{}: '{}' to '{}'",
+                    options.mode.past_tense(),
+                    relative_name(&action.from, target_directory),
+                    display_name(&action.to)
+                );
+                action.status = ActionStatus::Done;
+                success_count += 1;
+                vacated.insert(index);
+                applied.push(index);
+            }
+            Err(e) if current[index] != action.from => {
+                let message = format!(
+                    "{} (the file was left as '{}')",
+                    e,
+                    display_name(&current[index])
+                );
+                failure(action, message);
+            }
+            Err(e) => failure(action, e.to_string()),
+        }
+    }
+    progress.finish();
+    let rolled_back = options.atomic && error_count.get() > 0;
+    if rolled_back {
+        success_count -= roll_back(actions, &applied, &current, options, target_directory);
+    }
+    // The files --force moved aside go once the batch has succeeded, and back after a rollback
+    for (index, temporary) in replaced {
+        let action = &actions[index];
+        if !rolled_back {
+            match fs::remove_file(&temporary) {
+                Ok(_) => say!(
+                    "Removed existing '{}' to overwrite it.",
+                    relative_name(&action.to, target_directory)
+                ),
+                Err(e) => eprintln!(
+                    "Warning: Could not remove the replaced '{}', kept as '{}': {}.",
+                    relative_name(&action.to, target_directory),
+                    display_name(&temporary),
+                    e
+                ),
+            }
+        } else if action.status == ActionStatus::Done {
+            eprintln!(
+                "The replaced '{}' is kept as '{}', as its replacement couldn't be rolled back.",
+                relative_name(&action.to, target_directory),
+                display_name(&temporary)
+            );
+        } else if let Err(e) = fs::rename(&temporary, &action.to) {
+            eprintln!(
+                "Error restoring '{}' from '{}': {}.",
+                relative_name(&action.to, target_directory),
+                display_name(&temporary),
+                e
+            );
+        }
+    }
+    let undo_entries: Vec<(PathBuf, PathBuf)> = applied
+        .iter()
+        .map(|&index| &actions[index])
+        .filter(|action| options.mode.removes_source() && action.status == ActionStatus::Done)
+        .map(|action| (action.from.clone(), action.to.clone()))
+        .collect();
+    if !undo_entries.is_empty()
+        && let Err(e) = append_undo_log(target_directory, &undo_entries)
+    {
+        eprintln!("Warning: Could not write the undo log: {}", e);
+    }
+    Ok((success_count, error_count.get()))
+}
+
+/// `--atomic`: reverts the `applied` actions, last first, each back to where its file was before
+/// (`current`: a temporary name for those moved aside), then returns the files moved aside to
+/// their original names. Copies and links are removed. Returns how many actions were reverted;
+/// any that can't be stay done and are reported.
+fn roll_back(
+    actions: &mut [ActionRecord],
+    applied: &[usize],
+    current: &[PathBuf],
+    options: &Options,
+    target_directory: &Path,
+) -> usize {
+    say!(
+        "Rolling back the {} action(s) already applied...",
+        applied.len()
+    );
+    let mut reverted = 0;
+    for &index in applied.iter().rev() {
+        let action = &mut actions[index];
+        let result = if options.mode.removes_source() {
+            apply_action(Mode::Move, &action.to, &current[index])
+        } else {
+            fs::remove_file(&action.to)
+        };
+        match result {
+            Ok(_) => {
+                action.status = ActionStatus::RolledBack;
+                reverted += 1;
+            }
+            Err(e) => eprintln!(
+                "Error rolling back '{}': {}; it stays as '{}'.",
+                relative_name(&action.from, target_directory),
+                e,
+                display_name(&action.to)
+            ),
+        }
+    }
+    for (index, action) in actions.iter().enumerate() {
+        if current[index] != action.from
+            && action.status != ActionStatus::Done
+            && let Err(e) = fs::rename(&current[index], &action.from)
+        {
+            eprintln!(
+                "Error restoring '{}' from '{}': {}.",
+                relative_name(&action.from, target_directory),
+                display_name(&current[index]),
+                e
+            );
+        }
+    }
+    say!("Rolled back {} of {} action(s).", reverted, applied.len());
+    reverted
+}
+
+/// `--check`: lists the files whose names aren't in their canonical form, without renaming
+/// anything. Files lacking a show or season (that --name and --season don't supply) and files
+/// that couldn't be parsed don't conform either. Returns 0 when everything conforms, else 1.
+fn check_names(
+    scan: &Scan,
+    proposed_renames: &HashMap<PathBuf, PathBuf>,
+    unparsed: usize,
+    options: &Options,
+    target_directory: &Path,
+    summary: &mut RunSummary,
+) -> i32 {
+    let unanswered: Vec<&Path> = scan
+        .parsed_files_info
+        .iter()
+        .filter(|info| info.needs_user_input && !options.fills_in(info))
+        .map(|info| info.original_path.as_path())
+        .collect();
+    let mut mismatches: Vec<(String, String)> = unanswered
+        .iter()
+        .map(|path| {
+            let name = relative_name(path, target_directory).into_owned();
+            (name, "lacks a show name or season".to_string())
+        })
+        .collect();
+    for (old, new) in proposed_renames {
+        if !unanswered.contains(&old.as_path()) {
+            let name = relative_name(old, target_directory).into_owned();
+            let expected = format!("should be '{}'", relative_name(new, target_directory));
+            mismatches.push((name, expected));
+        }
+    }
+    mismatches.sort();
+
+    if mismatches.is_empty() && unparsed == 0 {
+        report!("All {} file(s) are named canonically.", summary.found);
+        summary.outcome = "conforms";
+        return 0;
+    }
+    report!(
+        "{} file(s) are not named canonically:",
+        mismatches.len() + unparsed
+    );
+    for (name, problem) in &mismatches {
+        report!("- '{}' {}", name, problem);
+    }
+    if unparsed > 0 {
+        report!(
+            "- {} file(s) could not be parsed (see the warnings above)",
+            unparsed
+        );
+    }
+    summary.outcome = "nonconforming";
+    1
+}
+
+/// Runs the scan/plan/apply flow, recording what happened in `summary`. Returns the exit code:
+/// 0 on success (including nothing to do and dry runs without conflicts), 1 for conflicts and
+/// errors, `EXIT_NEEDS_INPUT`, `EXIT_PARTIAL_FAILURE` and `EXIT_CANCELLED`.
+pub fn run(
+    options: &Options,
+    summary: &mut RunSummary,
+    prompter: &mut Prompter,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let mut phase_start = Instant::now();
+    let target_directory = match &options.directory {
+        // Canonicalized so parent/grandparent defaults work for relative paths like "."
+        Some(dir) => match fs::canonicalize(dir) {
+            Ok(path) if path.is_dir() => path,
+            Ok(_) => {
+                eprintln!("Error: '{}' is not a directory.", dir.display());
+                summary.outcome = "error";
+                return Ok(1);
+            }
+            Err(e) => {
+                eprintln!("Error: Cannot access directory '{}': {}", dir.display(), e);
+                summary.outcome = "error";
+                return Ok(1);
+            }
+        },
+        None => env::current_dir()?,
+    };
+    summary.target_directory = Some(target_directory.clone());
+    say!("Scanning directory: {}", target_directory.display());
+    if options.undo {
+        return undo_last_run(options, &target_directory, summary, prompter);
+    }
+    if options.nfc_only {
+        return renormalize_to_nfc(options, &target_directory, summary, prompter);
+    }
+    if let Some(plan) = &options.plan {
+        return run_plan(plan, options, &target_directory, summary, prompter);
+    }
+
+    // Copies, moves and links land in --out-dir when given, otherwise next to the originals.
+    let destination_dir = options
+        .out_dir
+        .clone()
+        .unwrap_or_else(|| target_directory.clone());
+    if destination_dir.exists() && !destination_dir.is_dir() {
+        eprintln!(
+            "Error: Output path '{}' exists and is not a directory.",
+            destination_dir.display()
+        );
+        summary.outcome = "error";
+        return Ok(1);
+    }
+
+    let target_fs = options.target_fs.resolve(&destination_dir);
+
+    let video_extensions = video_extensions(options);
+
+    if options.skip_processed
+        && let Some(processed_at) = current_processed_marker(&target_directory, &video_extensions)
+    {
+        say!(
+            "Skipping '{}': processed at {} (Unix time) and unchanged since.",
+            target_directory.display(),
+            processed_at
+        );
+        summary.outcome = "already_processed";
+        return Ok(0);
+    }
+
+    let scan = scan_episodes(
+        options,
+        &target_directory,
+        &destination_dir,
+        &video_extensions,
+        summary,
+    )?;
+
+    summary.timings.push(("scan", phase_start.elapsed()));
+    phase_start = Instant::now();
+
+    if scan.parsed_files_info.is_empty() {
+        say!("No eligible video files found to process in this directory.");
+        summary.outcome = "no_files";
+        return Ok(0);
+    }
+
+    if options.group_episodes_into_ranges {
+        print_episode_ranges(&scan.parsed_files_info, options);
+        summary.outcome = "report";
+        return Ok(0);
+    }
+    if options.gaps {
+        print_gaps(&scan.parsed_files_info, options);
+    }
+    if options.report_duplicates {
+        print_duplicates(&scan.parsed_files_info, options, &target_directory);
+        summary.outcome = "report";
+        return Ok(0);
+    }
+
+    if options.check {
+        // Files the scan already skipped, before planning skips those lacking a show or season
+        let unparsed = summary.skipped;
+        let proposed_renames = plan_new_names(
+            &scan,
+            &HashMap::new(),
+            options,
+            &target_directory,
+            &destination_dir,
+            target_fs,
+            summary,
+        );
+        return Ok(check_names(
+            &scan,
+            &proposed_renames,
+            unparsed,
+            options,
+            &target_directory,
+            summary,
+        ));
+    }
+
+    // Nobody is there to answer the prompts; fail instead of hanging on stdin.
+    // Files that --name and --season fill in need no answers.
+    if options.assume_yes && !scan.folders_needing_input.is_empty() {
+        let unanswered: Vec<&ParsedInfo> = scan
+            .parsed_files_info
+            .iter()
+            .filter(|info| info.needs_user_input && !options.fills_in(info))
+            .collect();
+        eprintln!(
+            "Error: {} file(s) lack Show Name or Season info and --yes can't prompt for them \
+             (--name and --season can supply them):",
+            unanswered.len()
+        );
+        for info in unanswered {
+            eprintln!(
+                "- {}",
+                relative_name(&info.original_path, &target_directory)
+            );
+        }
+        summary.outcome = "needs_input";
+        return Ok(EXIT_NEEDS_INPUT);
+    }
+
+    // User Input Phase: Get Show/Season once for each folder holding files that required it.
+    // Defaults come from that folder's own name (season) and its parent's name (show).
+    // A show typed for one folder is the default for its sibling folders ("Show/Season 1",
+    // "Show/Season 2"), so one show is only typed once.
+    // Before either, the show most of the folder's own files name is offered.
+    // --show-from-level and --season-from-level pick other folder levels ("Show/2019/Season 1").
+    let mut folder_inputs: HashMap<PathBuf, FolderInput> = HashMap::new();
+    let mut show_answers: HashMap<PathBuf, String> = HashMap::new();
+    let common_shows = common_show_names(&scan.parsed_files_info);
+
+    for folder in &scan.folders_needing_input {
+        if folder == &target_directory {
+            say!("\nSome video files lack Show Name or Season info (Sxx) in the filename.");
+        } else {
+            say!(
+                "\nSome video files in '{}' lack Show Name or Season info (Sxx) in the filename.",
+                relative_name(folder, &target_directory)
+            );
+        }
+        let level = |level: usize| folder.ancestors().nth(level - 1);
+        let show_folder = level(options.show_from_level);
+        let default_season_dir_name = level(options.season_from_level).and_then(get_dir_name);
+        let default_show_dir_name = common_shows
+            .get(folder)
+            .map(|(_, source)| join_words(source.split('.'), " "))
+            .or_else(|| {
+                show_folder.and_then(|show_folder| {
+                    show_answers
+                        .get(show_folder)
+                        .cloned()
+                        .or_else(|| get_dir_name(show_folder))
+                })
+            });
+
+        // --name and --season are filled in while planning; only ask for what they leave out
+        let user_show_name = match options.show_name {
+            Some(_) => String::new(),
+            None => prompt_user(
+                prompter,
+                "Enter Show Name for these files",
+                default_show_dir_name.as_deref(),
+            )?,
+        };
+        if !user_show_name.is_empty()
+            && let Some(show_folder) = show_folder
+        {
+            show_answers.insert(show_folder.to_path_buf(), user_show_name.clone());
+        }
+        let user_show_name = options.ampersand.apply(&user_show_name);
+        let (show_name, show_source) = if options.show_name.is_some() {
+            (None, None)
+        } else if !user_show_name.is_empty() {
+            (
+                Some(clean_segment(&user_show_name, options.separator)), // Clean the input
+                Some(normalize_separators(&user_show_name, options.separator)),
+            )
+        } else {
+            say!("No Show Name provided, files needing it might be skipped or use partial names.");
+            (None, None)
+        };
+
+        if options.season.is_some() {
+            folder_inputs.insert(
+                folder.clone(),
+                FolderInput {
+                    show_name,
+                    show_source,
+                    season_prefix: None,
+                },
+            );
+            continue;
+        }
+        let user_season_str = prompt_user(
+            prompter,
+            "Enter Season Number (e.g., 1, 02, 15) for these files",
+            default_season_dir_name.as_deref(),
+        )?;
+
+        // A bad season only skips the files that lack one; the show still applies to the rest
+        let season_prefix = match parse_season_input(&user_season_str, options) {
+            Ok(season_prefix) => Some(season_prefix),
+            Err(e) => {
+                say!("Warning: {}; files needing it will be skipped.", e);
+                None
+            }
+        };
+        folder_inputs.insert(
+            folder.clone(),
+            FolderInput {
+                show_name,
+                show_source,
+                season_prefix,
+            },
+        );
+    }
+
+    let proposed_renames = plan_new_names(
+        &scan,
+        &folder_inputs,
+        options,
+        &target_directory,
+        &destination_dir,
+        target_fs,
+        summary,
+    );
+
+    // Display proposed changes
+    summary.timings.push(("plan", phase_start.elapsed()));
+    if summary.correct > 0 {
+        say!(
+            "{} file(s) already correctly named; leaving them alone.",
+            summary.correct
+        );
+    }
+
+    let folder_renames = if options.rename_dirs {
+        plan_folder_renames(&scan, options, &target_directory)
+    } else {
+        Vec::new()
+    };
+
+    execute_renames(
+        options,
+        summary,
+        prompter,
+        &scan,
+        proposed_renames,
+        &folder_renames,
+        &target_directory,
+    )
+}
+
+/// Shows the planned actions, lets the user review them, checks them for conflicts and applies
+/// them once confirmed. Folder renames are applied after the files in them. Returns the exit
+/// code.
+fn execute_renames(
+    options: &Options,
+    summary: &mut RunSummary,
+    prompter: &mut Prompter,
+    scan: &Scan,
+    mut proposed_renames: HashMap<PathBuf, PathBuf>,
+    folder_renames: &[(PathBuf, PathBuf)],
+    target_directory: &Path,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    if proposed_renames.is_empty() && folder_renames.is_empty() {
+        say!("\nNo files need renaming based on the current rules and inputs.");
+        summary.outcome = "nothing_to_do";
+        mark_if_requested(options, target_directory, &video_extensions(options));
+        return Ok(0);
+    }
+
+    let mut table = format!(
+        "\nProposed {}:\n",
+        if options.mode == Mode::Rename {
+            "renames"
+        } else {
+            "actions"
+        }
+    );
+    table.push_str("--------------------\n");
+    let max_len_old = proposed_renames
+        .keys()
+        .chain(folder_renames.iter().map(|(old, _)| old))
+        .map(|p| relative_name(p, target_directory).chars().count())
+        .max()
+        .unwrap_or(0);
+
+    // Sort for consistent display order
+    let mut sorted_renames: Vec<_> = proposed_renames.iter().collect();
+    options.sort.sort(&mut sorted_renames);
+
+    let color = options.color.enabled();
+    for (index, (old, new)) in sorted_renames.iter().enumerate() {
+        // Borrow here for display
+        if options.dry_run_interactive {
+            table.push_str(&format!("{:>4}. ", index + 1));
+        }
+        let old_name = relative_name(old, target_directory);
+        let new_name = if new.parent() == old.parent() {
+            display_name(new)
+        } else {
+            new.to_string_lossy() // Outside the source directory: show where it goes
+        };
+        // Padded by hand: the color codes take no room on screen
+        let padding = " ".repeat(max_len_old.saturating_sub(old_name.chars().count()));
+        let (old_name, new_name) = if color {
+            highlight_changes(&old_name, &new_name)
+        } else {
+            (old_name.into_owned(), new_name.into_owned())
+        };
+        table.push_str(&format!("{}{} -> {}\n", old_name, padding, new_name));
+    }
+    // Folders are renamed after the files in them, so they come last
+    for (old, new) in folder_renames {
+        table.push_str(&format!(
+            "{:<width$} -> {}/\n",
+            format!("{}/", relative_name(old, target_directory)),
+            display_name(new),
+            width = max_len_old
+        ));
+    }
+    table.push_str("--------------------\n");
+
+    // Long plans go through a pager so they can be scrolled before the confirmation prompt.
+    let overflows = || terminal_height().is_some_and(|rows| table.lines().count() >= rows);
+    // --quiet only shows the plan when there is a prompt to answer about it.
+    let answering = options.interactive
+        || options.dry_run_interactive
+        || !(options.assume_yes || options.plan_only());
+    let hidden = options.json || options.emit_script // The plan goes out as JSON or a script instead
+        || (options.verbosity == Verbosity::Quiet && !answering);
+    let paged = !hidden
+        && options.preview_open
+        && io::stdout().is_terminal()
+        && overflows()
+        && show_in_pager(&table).is_ok();
+    if !hidden && !paged {
+        print!("{}", table);
+    }
+
+    // Interactive review: drop deselected entries; the rest goes through conflict checks below.
+    let mut deselected_count = 0;
+    if options.interactive {
+        let planned = proposed_renames.len();
+        proposed_renames = review_each_entry(prompter, &sorted_renames, target_directory)?;
+        deselected_count = planned - proposed_renames.len();
+        if proposed_renames.is_empty() && folder_renames.is_empty() {
+            say!("All entries skipped; nothing to do.");
+            summary.outcome = "nothing_to_do";
+            return Ok(0);
+        }
+    } else if options.dry_run_interactive {
+        let deselected = select_entries_to_skip(prompter, &sorted_renames)?;
+        deselected_count = deselected.len();
+        for old in &deselected {
+            proposed_renames.remove(old);
+        }
+        if proposed_renames.is_empty() && folder_renames.is_empty() {
+            say!("All entries deselected; nothing to do.");
+            summary.outcome = "nothing_to_do";
+            return Ok(0);
+        }
+        if deselected_count > 0 {
+            say!(
+                "{} entr{} deselected, {} remaining.",
+                deselected_count,
+                if deselected_count == 1 { "y" } else { "ies" },
+                proposed_renames.len()
+            );
+        }
+    }
+    // --force: existing files in the way may be replaced where the user agrees, one by one. They
+    // are left out of the conflict check, so the questions only come for an otherwise sound plan;
+    // the check runs again after them, as a declined entry keeps its source where it is.
+    let candidates = if options.force {
+        overwrite_candidates(&proposed_renames, &scan.all_paths_in_dir)
+    } else {
+        Vec::new()
+    };
+    let mut overwrites: HashSet<PathBuf> = candidates.iter().map(|(_, new)| new.clone()).collect();
+    let mut pending_questions = Some(candidates).filter(|candidates| !candidates.is_empty());
+    let all_renames = loop {
+        let mut sorted_renames: Vec<_> = proposed_renames.iter().collect();
+        options.sort.sort(&mut sorted_renames);
+
+        summary.actions = sorted_renames
+            .iter()
+            .map(|(old, new)| ActionRecord {
+                from: (*old).clone(),
+                to: (*new).clone(),
+                conflict: false,
+                overwrite: overwrites.contains(*new),
+                status: ActionStatus::NotApplied,
+            })
+            .collect();
+        // Folder renames go last: the file actions still refer to the folders' current names
+        summary
+            .actions
+            .extend(folder_renames.iter().map(|(old, new)| ActionRecord {
+                from: old.clone(),
+                to: new.clone(),
+                conflict: false,
+                overwrite: false,
+                status: ActionStatus::NotApplied,
+            }));
+        let mut all_renames = proposed_renames.clone();
+        all_renames.extend(folder_renames.iter().cloned());
+
+        // Conflict Checking
+        let (potential_conflicts, conflicting_targets) = find_conflicts(
+            &all_renames,
+            &scan.all_paths_in_dir,
+            &overwrites,
+            options,
+            target_directory,
+        );
+
+        if !potential_conflicts.is_empty() {
+            let potential_conflicts_count = potential_conflicts.len();
+            eprintln!("\nWarning: Potential conflicts detected!");
+            for conflict in potential_conflicts {
+                eprintln!("- {}", conflict);
+            }
+            eprintln!("Please resolve conflicts before proceeding.");
+            summary.conflicts = potential_conflicts_count;
+            for action in &mut summary.actions {
+                action.conflict = conflicting_targets.contains(&action.to);
+            }
+            summary.outcome = "conflicts";
+            return Ok(1); // Abort due to conflicts
+        }
+
+        let Some(candidates) = pending_questions.take() else {
+            break all_renames;
+        };
+        let asked = candidates.len();
+        overwrites = confirm_overwrites(
+            prompter,
+            candidates,
+            &mut proposed_renames,
+            options,
+            target_directory,
+        )?;
+        if proposed_renames.is_empty() && folder_renames.is_empty() {
+            say!("All entries skipped; nothing to do.");
+            summary.outcome = "nothing_to_do";
+            return Ok(0);
+        }
+        if overwrites.len() == asked {
+            break all_renames; // Nothing was dropped, so the plan just checked is the final one
+        }
+    };
+
+    if options.emit_script {
+        print!(
+            "{}",
+            rename_script(&summary.actions, options, target_directory)
+        );
+        summary.outcome = "script";
+        return Ok(0);
+    }
+    if options.plan_only() {
+        say!("\nDry run: no files were changed.");
+        summary.outcome = "dry_run";
+        return Ok(0);
+    }
+
+    // Confirmation and Renaming
+    if confirm_batch(prompter, all_renames.len(), options)? {
+        let phase_start = Instant::now();
+        say!("\n{} files...", sentence_case(options.mode.verb()));
+        let (success_count, error_count) =
+            match apply_renames(&mut summary.actions, options, target_directory) {
+                Ok(counts) => counts,
+                Err(e) => {
+                    eprintln!(
+                        "Error creating output directory '{}': {}",
+                        options
+                            .out_dir
+                            .as_deref()
+                            .unwrap_or(target_directory)
+                            .display(),
+                        e
+                    );
+                    summary.outcome = "error";
+                    return Ok(1);
+                }
+            };
+        say!("--------------------");
+        say!(
+            "{} complete. {} succeeded, {} failed.",
+            sentence_case(options.mode.verb()),
+            success_count,
+            error_count
+        );
+        if deselected_count > 0 {
+            say!(
+                "Applied {} of {} planned entries; {} deselected entr{} left untouched.",
+                success_count + error_count,
+                success_count + error_count + deselected_count,
+                deselected_count,
+                if deselected_count == 1 {
+                    "y was"
+                } else {
+                    "ies were"
+                }
+            );
+        }
+        summary.renamed = success_count;
+        summary.failed = error_count;
+        summary.outcome = "completed";
+        summary.timings.push(("apply", phase_start.elapsed()));
+        if options.atomic && error_count > 0 && success_count == 0 {
+            summary.outcome = "rolled_back";
+            return Ok(1); // Nothing stayed applied
+        }
+        if error_count > 0 {
+            return Ok(EXIT_PARTIAL_FAILURE);
+        }
+        mark_if_requested(options, target_directory, &video_extensions(options));
+    } else {
+        say!("{} cancelled.", sentence_case(options.mode.verb()));
+        summary.outcome = "cancelled";
+        return Ok(EXIT_CANCELLED);
+    }
+
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// A scratch directory under the system temp dir, removed again on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> TempDir {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let dir = env::temp_dir().join(format!(
+                "rename-episodes-test-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(fs::canonicalize(&dir).unwrap())
+        }
+
+        /// Creates an empty file (and its folders) at `name`, relative to the directory.
+        fn touch(&self, name: &str) -> PathBuf {
+            let path = self.0.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, b"").unwrap();
+            path
+        }
+
+        /// Every file below the directory, relative and sorted.
+        fn files(&self) -> Vec<String> {
+            let mut files: Vec<String> = scan_tree(&self.0, usize::MAX, 1)
+                .unwrap()
+                .into_iter()
+                .filter(|entry| entry.is_file)
+                .map(|entry| relative_name(&entry.path, &self.0).into_owned())
+                .filter(|name| name != UNDO_LOG)
+                .collect();
+            files.sort();
+            files
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A prompter answering from `input`, one line per prompt, with empty answers once it runs out.
+    fn scripted(input: &str) -> Prompter {
+        Prompter::new(
+            Box::new(io::Cursor::new(input.as_bytes().to_vec())),
+            Box::new(io::sink()),
+        )
+    }
+
+    fn parse_with(name: &str, options: &Options) -> ParsedInfo {
+        let path = Path::new(name);
+        let stem = path.file_stem().and_then(OsStr::to_str).unwrap();
+        parse_stem(stem, path.extension().unwrap_or_default(), None, options)
+    }
+
+    fn parse(name: &str) -> ParsedInfo {
+        parse_with(name, &Options::default())
+    }
+
+    fn test_options(dir: &TempDir) -> Options {
+        Options {
+            directory: Some(dir.0.clone()),
+            target_fs: FsType::Posix,
+            ..Options::default()
+        }
+    }
+
+    /// Runs with `options`, answering prompts from `input`.
+    fn run_with(options: &Options, input: &str) -> (i32, RunSummary) {
+        let mut summary = RunSummary::default();
+        let code = run(options, &mut summary, &mut scripted(input)).unwrap();
+        (code, summary)
+    }
+
+    /// Dry-runs over files named `names` and returns the planned renames as relative names.
+    fn plan_with(names: &[&str], options: Options) -> Vec<(String, String)> {
+        let dir = TempDir::new();
+        for name in names {
+            dir.touch(name);
+        }
+        let options = Options {
+            dry_run: true,
+            ..Options {
+                directory: Some(dir.0.clone()),
+                target_fs: FsType::Posix,
+                ..options
+            }
+        };
+        let (_, summary) = run_with(&options, "");
+        summary
+            .actions
+            .iter()
+            .map(|action| {
+                (
+                    relative_name(&action.from, &dir.0).into_owned(),
+                    relative_name(&action.to, &dir.0).into_owned(),
+                )
+            })
+            .collect()
+    }
+
+    fn plan(names: &[&str]) -> Vec<(String, String)> {
+        plan_with(names, Options::default())
+    }
+
+    /// The new name planned for a single file, or the old one when it is left alone.
+    fn renamed_with(name: &str, options: Options) -> String {
+        plan_with(&[name], options)
+            .into_iter()
+            .next()
+            .map_or(name.to_string(), |(_, to)| to)
+    }
+
+    fn renamed(name: &str) -> String {
+        renamed_with(name, Options::default())
+    }
+
+    // Parsing
+
+    #[test]
+    fn parses_sxxexx_tag() {
+        let info = parse("Show.Name.S01E02.720p.mkv");
+        assert_eq!(info.show_name_part.as_deref(), Some("show.name"));
+        assert_eq!(info.show_name_source.as_deref(), Some("Show.Name"));
+        assert_eq!(info.season_prefix_part.as_deref(), Some("S01"));
+        assert_eq!(info.episode_number_part.as_deref(), Some("E02"));
+        assert_eq!(info.remainder_part.as_deref(), Some("720p"));
+        assert_eq!(info.extension, OsString::from("mkv"));
+        assert!(!info.needs_user_input);
+    }
+
+    #[test]
+    fn parses_separated_tags() {
+        for name in ["Show.S01.E03.mkv", "Show S01 E03.mkv", "Show_S01_E03.mkv"] {
+            let info = parse(name);
+            assert_eq!(info.season_prefix_part.as_deref(), Some("S01"), "{}", name);
+            assert_eq!(info.episode_number_part.as_deref(), Some("E03"), "{}", name);
+        }
+    }
+
+    #[test]
+    fn lone_episode_tag_needs_a_season() {
+        let info = parse("Show.E05.mkv");
+        assert_eq!(info.show_name_part.as_deref(), Some("show"));
+        assert_eq!(info.season_prefix_part, None);
+        assert_eq!(info.episode_number_part.as_deref(), Some("E05"));
+        assert!(info.needs_user_input);
+    }
+
+    #[test]
+    fn strips_group_suffix() {
+        let info = parse("Show.S01E02.720p-GROUP[rarbg].mkv");
+        assert_eq!(info.remainder_part.as_deref(), Some("720p"));
+    }
+
+    #[test]
+    fn missing_show_name_needs_input() {
+        let info = parse("S01E02.mkv");
+        assert_eq!(info.show_name_part, None);
+        assert_eq!(info.season_prefix_part.as_deref(), Some("S01"));
+        assert!(info.needs_user_input);
+    }
+
+    #[test]
+    fn collapses_doubled_dots() {
+        let info = parse("Show..Name...S01E02..720p.mkv");
+        assert_eq!(info.show_name_part.as_deref(), Some("show.name"));
+        assert_eq!(info.remainder_part.as_deref(), Some("720p"));
+    }
+
+    #[test]
+    fn pads_short_and_long_tags() {
+        let info = parse("Show.S1E1.mkv");
+        assert_eq!(info.season_prefix_part.as_deref(), Some("S01"));
+        assert_eq!(info.episode_number_part.as_deref(), Some("E01"));
+        let info = parse("Show.S001E003.mkv");
+        assert_eq!(info.season_prefix_part.as_deref(), Some("S01"));
+        assert_eq!(info.episode_number_part.as_deref(), Some("E03"));
+    }
+
+    #[test]
+    fn parses_season_x_notation() {
+        let info = parse("Show.Name.1x02.mkv");
+        assert_eq!(info.season_prefix_part.as_deref(), Some("S01"));
+        assert_eq!(info.episode_number_part.as_deref(), Some("E02"));
+        assert!(!info.needs_user_input);
+        assert_eq!(
+            parse("Show.01x02.mkv").season_prefix_part.as_deref(),
+            Some("S01")
+        );
+        // A resolution is not a season/episode tag
+        assert_eq!(parse("Show.1920x1080.mkv").season_prefix_part, None);
+    }
+
+    #[test]
+    fn keeps_every_episode_of_multi_episode_files() {
+        assert_eq!(
+            parse("Show.S01E01E02.mkv").episode_number_part.as_deref(),
+            Some("E01E02")
+        );
+        assert_eq!(
+            parse("show.s01e1e2.mkv").episode_number_part.as_deref(),
+            Some("E01E02")
+        );
+        assert_eq!(
+            parse("Show.S01E01-E03.mkv").episode_number_part.as_deref(),
+            Some("E01-E03")
+        );
+        assert_eq!(
+            parse("Show.S01E01.mkv").episode_number_part.as_deref(),
+            Some("E01")
+        );
+    }
+
+    #[test]
+    fn detects_a_second_tag() {
+        assert_eq!(
+            parse("Show.S01E01.S01E02.mkv").second_tag.as_deref(),
+            Some("S01E02")
+        );
+        assert_eq!(parse("Show.S01E01E02.mkv").second_tag, None);
+    }
+
+    #[test]
+    fn lifts_part_indicators() {
+        let info = parse("Show.S01E05.Part.Two.720p.mkv");
+        assert_eq!(info.part, Some(2));
+        assert_eq!(info.remainder_part.as_deref(), Some("720p"));
+        assert_eq!(parse("Show.S01E05.Pt.1.mkv").part, Some(1));
+        assert_eq!(parse("Show.S01E05.Part II.mkv").part, Some(2));
+        assert_eq!(parse("Show.S01E05.Partridge.mkv").part, None);
+        assert_eq!(parse("Show.S01E05 (2).mkv").part, Some(2));
+        assert_eq!(parse("Show.S01E05 (2019).mkv").part, None);
+    }
+
+    #[test]
+    fn parts_of_one_episode_keep_distinct_names() {
+        assert_eq!(
+            plan(&[
+                "show.s01e01.part.1.mkv",
+                "show.s01e01.pt2.mkv",
+                "show.s01e01 (3).mkv"
+            ]),
+            vec![
+                (
+                    "show.s01e01 (3).mkv".to_string(),
+                    "Show.S01E01.Part.3.mkv".to_string()
+                ),
+                (
+                    "show.s01e01.part.1.mkv".to_string(),
+                    "Show.S01E01.Part.1.mkv".to_string()
+                ),
+                (
+                    "show.s01e01.pt2.mkv".to_string(),
+                    "Show.S01E01.Part.2.mkv".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn part_numbers() {
+        assert_eq!(part_number("3"), Some(3));
+        assert_eq!(part_number("Three"), Some(3));
+        assert_eq!(part_number("third"), Some(3));
+        assert_eq!(part_number("iv"), Some(4));
+        assert_eq!(part_number("many"), None);
+    }
+
+    #[test]
+    fn keeps_id_tags_only_when_asked() {
+        let options = Options {
+            keep_ids: true,
+            ..Options::default()
+        };
+        let info = parse_with("Show [TMDBID-1396].S01E01.mkv", &options);
+        assert_eq!(info.id_tags, vec!["[tmdbid-1396]".to_string()]);
+        assert_eq!(info.show_name_part.as_deref(), Some("show"));
+        assert!(parse("Show [tmdbid-1396].S01E01.mkv").id_tags.is_empty());
+    }
+
+    #[test]
+    fn parses_absolute_numbers() {
+        let options = Options {
+            absolute: true,
+            ..Options::default()
+        };
+        let info = parse_with("[SubsPlease] Frieren - 135 [1080p].mkv", &options);
+        assert_eq!(info.show_name_part.as_deref(), Some("frieren"));
+        assert_eq!(info.season_prefix_part, None);
+        assert_eq!(info.episode_number_part.as_deref(), Some("E135"));
+        assert_eq!(info.remainder_part.as_deref(), Some("1080p"));
+        assert!(!info.needs_user_input);
+
+        let options = Options {
+            absolute_season: Some(2),
+            ..options
+        };
+        let info = parse_with("Show - 07v2 (720p).mkv", &options);
+        assert_eq!(info.season_prefix_part.as_deref(), Some("S02"));
+        assert_eq!(info.episode_number_part.as_deref(), Some("E07"));
+    }
+
+    #[test]
+    fn episode_tags_start_a_word_and_are_not_resolutions() {
+        assert_eq!(parse("show.1080p.mkv").episode_number_part, None);
+        assert_eq!(parse("show.Live720p.mkv").episode_number_part, None);
+        assert_eq!(parse("show.e1080p.mkv").episode_number_part, None);
+        assert_eq!(parse("Three.Pines.mkv").episode_number_part, None);
+        let info = parse("show.e05.mkv");
+        assert_eq!(info.episode_number_part.as_deref(), Some("E05"));
+        assert_eq!(info.show_name_part.as_deref(), Some("show"));
+        let info = parse("show.e05.720p.mkv");
+        assert_eq!(info.episode_number_part.as_deref(), Some("E05"));
+        assert_eq!(info.remainder_part.as_deref(), Some("720p"));
+    }
+
+    #[test]
+    fn strips_a_leading_release_group() {
+        let info = parse("[SubsPlease] Show.S01E02.mkv");
+        assert_eq!(info.release_group.as_deref(), Some("SubsPlease"));
+        assert_eq!(info.show_name_source.as_deref(), Some("Show"));
+        let info = parse("{Group}Show.S01E02.mkv");
+        assert_eq!(info.release_group.as_deref(), Some("Group"));
+        assert_eq!(info.show_name_source.as_deref(), Some("Show"));
+        // Brackets later in the name are part of it
+        let info = parse("Show [US].S01E02.mkv");
+        assert_eq!(info.release_group, None);
+        assert_eq!(parse("[S01E02] Show.mkv").release_group, None);
+
+        let options = Options {
+            template: Some("{show} - {season}{episode} [{group}].{ext}".to_string()),
+            ..Options::default()
+        };
+        assert_eq!(
+            plan_with(&["[SubsPlease] show.s01e02.mkv"], options),
+            vec![(
+                "[SubsPlease] show.s01e02.mkv".to_string(),
+                "Show - S01E02 [SubsPlease].mkv".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn ignores_episode_markers_in_brackets() {
+        let info = parse("[E1-Raws] Show - 07 (720p).mkv");
+        assert_eq!(info.episode_number_part, None);
+        assert!(in_brackets("[E1] x", 1));
+        assert!(!in_brackets("[E1] x", 5));
+        assert!(in_brackets("a (b [c] d)", 9));
+    }
+
+    #[test]
+    fn takes_the_season_from_a_series_folder() {
+        let options = Options::default();
+        let series = Some(FolderSeason::Series(2));
+        let info = parse_stem("Show - 14", OsStr::new("mkv"), series, &options);
+        assert_eq!(info.season_prefix_part.as_deref(), Some("S02"));
+        assert_eq!(info.episode_number_part.as_deref(), Some("E14"));
+        assert_eq!(info.show_name_part.as_deref(), Some("show"));
+        assert!(!info.needs_user_input);
+
+        // Two standalone numbers are ambiguous
+        let info = parse_stem("Show 3 - 14", OsStr::new("mkv"), series, &options);
+        assert!(info.needs_user_input);
+        // Only a Series folder makes a bare number an episode
+        let info = parse_stem(
+            "Show - 14",
+            OsStr::new("mkv"),
+            Some(FolderSeason::Season(2)),
+            &options,
+        );
+        assert!(info.needs_user_input);
+        assert_eq!(
+            folder_season(Path::new("Series 2/a.mkv"), &options),
+            Some(FolderSeason::Series(2))
+        );
+        assert_eq!(folder_season(Path::new("Series 1-2/a.mkv"), &options), None);
+    }
+
+    #[test]
+    fn parses_with_a_custom_pattern() {
+        let pattern =
+            r"^\[(?P<show>[^\]]+)\]_\[s(?P<season>\d+)\]_\[e(?P<episode>\d+)\](?P<remainder>.*)$";
+        let options = Options {
+            pattern: Some(parse_pattern(pattern).unwrap()),
+            ..Options::default()
+        };
+        let info = parse_with("[The Show]_[s01]_[e02] 720p.mkv", &options);
+        assert_eq!(info.show_name_source.as_deref(), Some("The.Show"));
+        assert_eq!(info.season_prefix_part.as_deref(), Some("S01"));
+        assert_eq!(info.episode_number_part.as_deref(), Some("E02"));
+        assert_eq!(info.remainder_part.as_deref(), Some("720p"));
+        assert!(!info.needs_user_input);
+        // Names the pattern doesn't match use the built-in rules
+        let info = parse_with("Show.S03E04.mkv", &options);
+        assert_eq!(info.episode_number_part.as_deref(), Some("E04"));
+
+        assert!(parse_pattern(r"(?P<show>.+) - (?P<season>\d+)").is_err());
+        assert!(parse_pattern(r"(?P<ep>\d+)").is_err());
+        assert!(parse_pattern(r"(?P<episode>\d+").is_err());
+    }
+
+    #[test]
+    fn takes_the_season_from_a_season_folder() {
+        let options = Options::default();
+        for (folder, season) in [
+            ("Season 3", Some(FolderSeason::Season(3))),
+            ("season.03", Some(FolderSeason::Season(3))),
+            ("S02", Some(FolderSeason::Season(2))),
+            ("Specials", Some(FolderSeason::Season(0))),
+            ("Season 2 Extras", None),
+            ("Show", None),
+        ] {
+            let path = Path::new(folder).join("a.mkv");
+            assert_eq!(folder_season(&path, &options), season, "{folder}");
+        }
+
+        let season = Some(FolderSeason::Season(3));
+        let info = parse_stem("Show.E05", OsStr::new("mkv"), season, &options);
+        assert_eq!(info.season_prefix_part.as_deref(), Some("S03"));
+        assert_eq!(info.episode_number_part.as_deref(), Some("E05"));
+        assert!(!info.needs_user_input);
+        // Without a folder season it still asks
+        let info = parse_stem("Show.E05", OsStr::new("mkv"), None, &options);
+        assert!(info.needs_user_input);
+    }
+
+    #[test]
+    fn rewrites_ampersands() {
+        for (ampersand, expected) in [
+            (Ampersand::Keep, "tom.&.jerry"),
+            (Ampersand::And, "tom.and.jerry"),
+            (Ampersand::Plus, "tom.+.jerry"),
+        ] {
+            let options = Options {
+                ampersand,
+                ..Options::default()
+            };
+            for name in ["Tom & Jerry.S01E01.mkv", "Tom&Jerry.S01E01.mkv"] {
+                let info = parse_with(name, &options);
+                assert_eq!(info.show_name_part.as_deref(), Some(expected), "{}", name);
+            }
+        }
+        assert_eq!(Ampersand::parse("AND"), Ok(Ampersand::And));
+        assert!(Ampersand::parse("both").is_err());
+    }
+
+    #[test]
+    fn parses_names_in_the_chosen_separator() {
+        let options = Options {
+            separator: '_',
+            ..Options::default()
+        };
+        let info = parse_with("Breaking_Bad_S01E03_720p.mkv", &options);
+        assert_eq!(info.show_name_part.as_deref(), Some("breaking.bad"));
+        assert_eq!(info.remainder_part.as_deref(), Some("720p"));
+    }
+
+    #[test]
+    fn keeps_the_extension_bytes() {
+        let info = parse("Show.S01E01.MKV");
+        assert_eq!(info.extension, OsString::from("MKV"));
+        assert_eq!(info.original_filename, "Show.S01E01.MKV");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_non_utf8_names() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = TempDir::new();
+        let path = dir.0.join(OsStr::from_bytes(b"Show.S01E01.\xff.mkv"));
+        fs::write(&path, b"").unwrap();
+        assert_eq!(
+            parse_filename(&path, &Options::default()).unwrap_err(),
+            ParseError::NonUtf8Name
+        );
+    }
+
+    #[test]
+    fn parse_filename_needs_a_file() {
+        let dir = TempDir::new();
+        assert_eq!(
+            parse_filename(&dir.0, &Options::default()).unwrap_err(),
+            ParseError::NotAFile
+        );
+        let path = dir.touch("Series 3/Show - 04.mkv");
+        let info = parse_filename(&path, &Options::default()).unwrap();
+        assert_eq!(info.original_path, path);
+        assert_eq!(info.season_prefix_part.as_deref(), Some("S03"));
+    }
+
+    // Cleaning and casing
+
+    #[test]
+    fn cleans_segments() {
+        assert_eq!(clean_segment(" Some  Show ", '.'), "some.show");
+        assert_eq!(normalize_separators("Some  Show.", '.'), "Some.Show");
+        assert_eq!(normalize_separators("..", '.'), "");
+        assert_eq!(normalize_separators("Some_Show", '_'), "Some.Show");
+    }
+
+    #[test]
+    fn title_cases_show_names() {
+        let words = TitleWords::default();
+        assert_eq!(
+            capitalize_title_case("the.lord.of.the.rings", &words),
+            "The.Lord.of.the.Rings"
+        );
+        assert_eq!(capitalize_title_case("csi.miami", &words), "CSI.Miami");
+        assert_eq!(capitalize_title_case("s.w.a.t", &words), "S.W.A.T");
+        assert_eq!(capitalize_title_case("ncis", &words), "NCIS");
+    }
+
+    #[test]
+    fn restores_acronyms_from_the_source() {
+        assert_eq!(
+            restore_source_acronyms("The.Office.Us", Some("The.Office.US")),
+            "The.Office.US"
+        );
+        // All capitals carry no signal
+        assert_eq!(
+            restore_source_acronyms("Breaking.Bad", Some("BREAKING.BAD")),
+            "Breaking.Bad"
+        );
+        assert_eq!(restore_source_acronyms("Show", None), "Show");
+    }
+
+    #[test]
+    fn title_cases_all_caps_sources() {
+        assert_eq!(
+            renamed("BREAKING.BAD.S01E01.mkv"),
+            "Breaking.Bad.S01E01.mkv"
+        );
+        assert_eq!(renamed("CSI.MIAMI.S01E01.mkv"), "CSI.Miami.S01E01.mkv");
+        assert_eq!(
+            renamed("the.office.US.s01e01.mkv"),
+            "The.Office.US.S01E01.mkv"
+        );
+    }
+
+    #[test]
+    fn title_cases_accented_and_non_latin_names() {
+        assert_eq!(
+            renamed("détective.conan.s01e02.mkv"),
+            "Détective.Conan.S01E02.mkv"
+        );
+        assert_eq!(renamed("ДОКТОР.КТО.S01E01.mkv"), "Доктор.Кто.S01E01.mkv");
+        assert_eq!(renamed("東京.s01e01.mkv"), "東京.S01E01.mkv");
+        // A decomposed capital is still a capital
+        assert_eq!(
+            restore_source_acronyms("Élite.Ue", Some("E\u{301}lite.UE")),
+            "Élite.UE"
+        );
+        assert_eq!(
+            capitalize_title_case("e\u{301}lite", &TitleWords::default()),
+            "E\u{301}lite"
+        );
+    }
+
+    #[test]
+    fn matches_words_regardless_of_normalization() {
+        let words = TitleWords::parse("lowercase = [\"de\u{301}\"]").unwrap();
+        assert_eq!(capitalize_title_case("café.dé", &words), "Café.dé");
+        assert_eq!(
+            capitalize_title_case("café.de\u{301}", &words),
+            "Café.de\u{301}"
+        );
+    }
+
+    #[test]
+    fn only_ascii_digits_are_numbers() {
+        let info = parse("Show.S\u{661}E\u{662}.mkv");
+        assert_eq!(info.season_prefix_part, None);
+        assert_eq!(info.episode_number_part, None);
+        // Letters of any script keep "1x02" from being a standalone tag
+        assert_eq!(parse("Привет1x02.mkv").season_prefix_part, None);
+        assert_eq!(
+            parse("Привет.1x02.mkv").season_prefix_part.as_deref(),
+            Some("S01")
+        );
+    }
+
+    #[test]
+    fn reads_words_files() {
+        let words = TitleWords::parse("# comment\nlowercase = [\"de\", \"La\"]\n").unwrap();
+        assert!(words.lowercase.contains("de") && words.lowercase.contains("la"));
+        assert!(!words.lowercase.contains("the"));
+        assert_eq!(words.acronyms, TitleWords::default().acronyms);
+
+        let words = TitleWords::parse("acronyms = [\n  \"BBC\",\n  \"U.S.A\"\n]\n").unwrap();
+        assert_eq!(
+            capitalize_title_case("the.bbc.u.s.a", &words),
+            "The.BBC.U.S.A"
+        );
+        assert_eq!(capitalize_title_case("csi", &words), "Csi");
+        assert!(TitleWords::parse("lowercase = [\"unterminated]").is_err());
+    }
+
+    #[test]
+    fn cases_show_names_on_request() {
+        let words = TitleWords::default();
+        let source = Some("the.OFFICE.us");
+        assert_eq!(
+            Case::Title.show_name("the.office.us", source, &words),
+            "The.OFFICE.Us"
+        );
+        assert_eq!(
+            Case::Lower.show_name("the.office.us", source, &words),
+            "the.office.us"
+        );
+        assert_eq!(
+            Case::Upper.show_name("the.office.us", source, &words),
+            "THE.OFFICE.US"
+        );
+        assert_eq!(
+            Case::Keep.show_name("the.office.us", source, &words),
+            "the.OFFICE.us"
+        );
+        assert_eq!(Case::parse("Keep"), Ok(Case::Keep));
+        assert!(Case::parse("camel").is_err());
+    }
+
+    #[test]
+    fn sentence_cases_messages() {
+        assert_eq!(sentence_case("rename"), "Rename");
+        assert_eq!(sentence_case(""), "");
+    }
+
+    #[test]
+    fn canonicalizes_release_tags() {
+        assert_eq!(
+            canonicalize_release_tags("1080p.web-dl.ddp5.1.x265"),
+            vec!["1080p", "WEB-DL", "DDP5.1", "x265"]
+        );
+        assert_eq!(
+            canonicalize_release_tags("h.264.some.title"),
+            vec!["H.264", "some", "title"]
+        );
+        // Words that are also tags stay words unless a real tag is next to them
+        assert_eq!(
+            canonicalize_release_tags("web.of.lies.1080p"),
+            vec!["web", "of", "lies", "1080p"]
+        );
+        assert_eq!(
+            canonicalize_release_tags("magnum.opus.proper.720p.nf.web"),
+            vec!["magnum", "Opus", "PROPER", "720p", "NF", "WEB"]
+        );
+        assert_eq!(
+            canonicalize_release_tags("opus.magnum.4k"),
+            vec!["opus", "magnum", "4k"]
+        );
+        assert_eq!(
+            canonicalize_release_tags("2160p.dv.hdr10+.atmos.truehd.dts-hd.ma.5.1"),
+            vec![
+                "2160p",
+                "DV",
+                "HDR10+",
+                "Atmos",
+                "TrueHD",
+                "DTS-HD.MA",
+                "5",
+                "1"
+            ]
+        );
+        assert_eq!(
+            canonicalize_release_tags("1080p.dts.hd.ma.x264"),
+            vec!["1080p", "DTS-HD.MA", "x264"]
+        );
+        assert_eq!(
+            canonicalize_release_tags("ddp7.1.h265"),
+            vec!["DDP7.1", "H265"]
+        );
+        assert_eq!(
+            canonicalize_release_tags("hdr10+.hevc"),
+            vec!["HDR10+", "HEVC"]
+        );
+        // Canonical output, lowercased again by cleaning, comes back the same
+        let once = canonicalize_release_tags("web.dl.hevc").join(".");
+        assert_eq!(
+            canonicalize_release_tags(&once.to_lowercase()).join("."),
+            once
+        );
+    }
+
+    #[test]
+    fn sanitizes_for_the_target_filesystem() {
+        let options = Options::default();
+        assert_eq!(
+            sanitize_filename("Show: Name?", FsType::Windows, &options),
+            "Show Name"
+        );
+        assert_eq!(
+            sanitize_filename("Show: Name?", FsType::Posix, &options),
+            "Show: Name?"
+        );
+        let options = Options {
+            illegal_map: parse_char_map(":=-").unwrap(),
+            illegal_replacement: "_".to_string(),
+            ..Options::default()
+        };
+        assert_eq!(
+            sanitize_filename("Show: Name?", FsType::Windows, &options),
+            "Show- Name_"
+        );
+        // Removals never leave doubled or dangling dots
+        assert_eq!(
+            sanitize_filename("Show.?.Name.?", FsType::Windows, &Options::default()),
+            "Show.Name"
+        );
+    }
+
+    #[test]
+    fn makes_new_names_safe_for_windows() {
+        let dir = TempDir::new();
+        dir.touch("show: the return*?.s01e02.mkv");
+        let options = Options {
+            target_fs: FsType::Windows,
+            // A trailing literal would leave a name Windows silently shortens
+            template: Some("{show}.{season}{episode}.{ext}. ".to_string()),
+            dry_run: true,
+            ..test_options(&dir)
+        };
+        let (code, summary) = run_with(&options, "");
+        assert_eq!(code, 0);
+        assert_eq!(
+            summary.actions[0].to.file_name().unwrap(),
+            "Show.the.Return.S01E02.mkv"
+        );
+    }
+
+    // Episodes and selections
+
+    #[test]
+    fn expands_and_groups_episodes() {
+        assert_eq!(episode_numbers("E01-E03"), vec![1, 2, 3]);
+        assert_eq!(episode_numbers("E01E02"), vec![1, 2]);
+        assert_eq!(group_into_ranges(&[5, 1, 2, 3]), vec![(1, 3), (5, 5)]);
+        assert_eq!(
+            format_episode_ranges(&group_into_ranges(&[1, 2, 3, 5])),
+            "E01-E03, E05"
+        );
+        assert_eq!(episode_run(1, "E02.720p"), ("E01E02".to_string(), 3));
+        assert_eq!(episode_run(1, ".720p"), ("E01".to_string(), 0));
+    }
+
+    #[test]
+    fn finds_episodes_present_in_several_files() {
+        let infos = [
+            parse("show.s01e02.720p.mkv"),
+            parse("Show.S01E02.1080p.mkv"),
+            parse("show.s01e02e03.mkv"),
+            parse("show.s01e04.mkv"),
+            parse("other.s01e04.mkv"),
+        ];
+        let duplicates = find_duplicates(&infos, &Options::default());
+        let found: Vec<_> = duplicates
+            .iter()
+            .map(|(key, files)| (key.clone(), files.len()))
+            .collect();
+        assert_eq!(found, vec![(("Show".to_string(), 1, 2), 3)]);
+    }
+
+    #[test]
+    fn sorts_renames_by_episode_number() {
+        let names = ["b.s01e10.mkv", "a.s01e02.mkv", "c.e05.mkv", "d.s02e01.mkv"];
+        let options = Options {
+            sort: SortOrder::Episode,
+            season: Some(3),
+            ..Options::default()
+        };
+        let order: Vec<String> = plan_with(&names, options)
+            .into_iter()
+            .map(|(_, new)| new)
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                "A.S01E02.mkv",
+                "B.S01E10.mkv",
+                "D.S02E01.mkv",
+                "C.S03E05.mkv"
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_episode_filters() {
+        let filters = parse_episode_filters("S01E05, S01E07-S02E02,S03").unwrap();
+        let selected = |season, episode| filters.iter().any(|f| f.matches(season, episode));
+        assert!(selected(1, 5));
+        assert!(!selected(1, 6));
+        assert!(selected(1, 9));
+        assert!(selected(2, 2));
+        assert!(!selected(2, 3));
+        assert!(selected(3, 40));
+        assert!(parse_episode_filters("E05").is_err());
+        assert!(parse_episode_filters("S01E08-S01E05").is_err());
+    }
+
+    #[test]
+    fn parses_selections() {
+        assert_eq!(
+            parse_selection("2 5-7, 9", 9),
+            Ok([1, 4, 5, 6, 8].into_iter().collect())
+        );
+        assert!(parse_selection("10", 9).is_err());
+        assert!(parse_selection("x", 9).is_err());
+    }
+
+    #[test]
+    fn names_season_zero_folders() {
+        let options = Options::default();
+        assert_eq!(season_folder_name(0, &options), "Specials");
+        assert_eq!(season_folder_name(2, &options), "Season 02");
+        let options = Options {
+            specials_folder_name: "Season 00".to_string(),
+            ..Options::default()
+        };
+        assert_eq!(season_folder_name(0, &options), "Season 00");
+        // File names keep the S00 tag whatever the folder is called
+        assert_eq!(
+            parse("Show.S00E01.mkv").season_prefix_part.as_deref(),
+            Some("S00")
+        );
+    }
+
+    #[test]
+    fn parses_prompted_seasons() {
+        let options = Options::default();
+        assert_eq!(parse_season_input("1", &options), Ok("S01".to_string()));
+        assert_eq!(
+            parse_season_input("Season 3", &options),
+            Ok("S03".to_string())
+        );
+        assert_eq!(
+            parse_season_input("Season 00", &options),
+            Ok("S00".to_string())
+        );
+        assert_eq!(
+            parse_season_input("Specials", &options),
+            Ok("S00".to_string())
+        );
+        assert_eq!(
+            parse_season_input("extras", &options),
+            Ok("S00".to_string())
+        );
+        assert_eq!(
+            parse_season_input("abc", &options),
+            Err(ParseError::UnparseableSeason("abc".to_string()))
+        );
+        let options = Options {
+            specials_folder_name: "Bonus".to_string(),
+            ..Options::default()
+        };
+        assert!(is_specials_folder("bonus", &options));
+    }
+
+    #[test]
+    fn describes_parse_errors() {
+        assert_eq!(
+            ParseError::UnparseableSeason("x".to_string()).to_string(),
+            "could not parse season number 'x'"
+        );
+        assert_eq!(
+            ParseError::NoEpisodeMarker.to_string(),
+            "no episode marker (Exx) was found"
+        );
+        assert_eq!(ParseError::MissingSeason.missing_component(), "season");
+        assert_eq!(
+            ParseError::UnparseableSeason(String::new()).missing_component(),
+            "season"
+        );
+        assert_eq!(
+            ParseError::NonUtf8Name.missing_component(),
+            "usable file name"
+        );
+    }
+
+    // Templates
+
+    #[test]
+    fn renders_templates() {
+        let mut fields: HashMap<&str, String> = HashMap::new();
+        fields.insert("show", "Show".to_string());
+        fields.insert("season", "S01".to_string());
+        fields.insert("episode", "E02".to_string());
+        let render = |template: &str, fields: &HashMap<&str, String>| {
+            render_template(template, fields, OsStr::new("mkv"), str::to_string)
+        };
+        let template = default_template(IdPosition::AfterShow, '.');
+        assert_eq!(render(&template, &fields), "Show.S01E02.mkv");
+
+        fields.insert("remainder", "720p".to_string());
+        assert_eq!(render(&template, &fields), "Show.S01E02.720p.mkv");
+        assert_eq!(
+            render("{show} - {season}{episode} - {part}.{ext}", &fields),
+            "Show - S01E02.mkv"
+        );
+
+        // An empty season hands its separator to the episode
+        fields.remove("season");
+        assert_eq!(render(&template, &fields), "Show.E02.720p.mkv");
+        assert_eq!(
+            default_template(IdPosition::End, ' '),
+            "{show} {season}{episode} {part} {remainder} {ids}.{ext}"
+        );
+    }
+
+    #[test]
+    fn validates_templates() {
+        assert!(validate_template("{show}.{season}{episode}.{ext}").is_ok());
+        assert!(validate_template("{show}.{season}{episode}.{title}.{ext}").is_ok());
+        assert!(validate_template("{show}.{year}.{ext}").is_err());
+        assert!(validate_template("{show}.{season}{episode}").is_err());
+    }
+
+    // Options
+
+    #[test]
+    fn parses_extension_lists() {
+        assert_eq!(
+            ExtensionList::parse("mkv, .MP4"),
+            Ok(ExtensionList {
+                extend_defaults: false,
+                extensions: vec!["mkv".to_string(), "mp4".to_string()],
+            })
+        );
+        assert!(ExtensionList::parse("+m4v").unwrap().extend_defaults);
+        assert!(ExtensionList::parse("").is_err());
+        assert!(ExtensionList::parse("+").is_err());
+    }
+
+    #[test]
+    fn plans_only_for_dry_runs_and_unconfirmed_json() {
+        assert!(!Options::default().plan_only());
+        let json = Options {
+            json: true,
+            ..Options::default()
+        };
+        assert!(json.plan_only());
+        assert!(
+            !Options {
+                assume_yes: true,
+                ..json
+            }
+            .plan_only()
+        );
+    }
+
+    // Prompts
+
+    #[test]
+    fn confirms_batches() {
+        let options = Options::default();
+        assert!(confirm_batch(&mut scripted("y\n"), 2, &options).unwrap());
+        assert!(confirm_batch(&mut scripted("YES\n"), 2, &options).unwrap());
+        assert!(!confirm_batch(&mut scripted("\n"), 2, &options).unwrap());
+
+        let options = Options {
+            confirm_threshold: 1,
+            ..Options::default()
+        };
+        assert!(!confirm_batch(&mut scripted("y\n"), 2, &options).unwrap());
+        assert!(confirm_batch(&mut scripted("RENAME\n"), 2, &options).unwrap());
+        let options = Options {
+            confirm_word: Some("GO".to_string()),
+            ..options
+        };
+        assert!(confirm_batch(&mut scripted("GO\n"), 2, &options).unwrap());
+
+        // --yes never reads an answer
+        let options = Options {
+            assume_yes: true,
+            ..Options::default()
+        };
+        assert!(confirm_batch(&mut scripted(""), 500, &options).unwrap());
+    }
+
+    #[test]
+    fn prompts_fall_back_to_defaults() {
+        let mut prompter = scripted("typed\n\n");
+        assert_eq!(prompt_user(&mut prompter, "Q", Some("d")).unwrap(), "typed");
+        assert_eq!(prompt_user(&mut prompter, "Q", Some("d")).unwrap(), "d");
+        assert_eq!(prompt_user(&mut prompter, "Q", None).unwrap(), "");
+    }
+
+    #[test]
+    fn reviews_each_entry() {
+        let (a, b, c) = (
+            PathBuf::from("/x/a"),
+            PathBuf::from("/x/b"),
+            PathBuf::from("/x/c"),
+        );
+        let (new_a, new_b, new_c) = (
+            PathBuf::from("/x/A"),
+            PathBuf::from("/x/B"),
+            PathBuf::from("/x/C"),
+        );
+        let entries = [(&a, &new_a), (&b, &new_b), (&c, &new_c)];
+        let kept = review_each_entry(
+            &mut scripted("a\nq\ne\nx/y\ne\nBee\ns\n"),
+            &entries,
+            Path::new("/x"),
+        )
+        .unwrap();
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[&a], new_a);
+        assert_eq!(kept[&b], PathBuf::from("/x/Bee"));
+    }
+
+    // JSON
+
+    #[test]
+    fn round_trips_json_strings() {
+        let text = "a \"quoted\" \\ path\twith\nnewline and \u{1} ctrl, é";
+        let encoded = json_string(text);
+        assert_eq!(json_strings(&encoded), Ok(vec![text.to_string()]));
+        assert!(json_strings("\"unterminated").is_err());
+    }
+
+    #[test]
+    fn parses_undo_entries() {
+        let line = r#"{"time": 1, "renames": [{"from": "/a/x.mkv", "to": "/a/X.mkv"}]}"#;
+        assert_eq!(
+            parse_undo_entry(line),
+            Ok(vec![(PathBuf::from("/a/x.mkv"), PathBuf::from("/a/X.mkv"))])
+        );
+        assert!(parse_undo_entry(r#"{"time": 1}"#).is_err());
+        assert!(parse_undo_entry(r#"{"renames": [{"from": "/a"}]}"#).is_err());
+    }
+
+    #[test]
+    fn writes_actions_as_json() {
+        let mut summary = RunSummary::default();
+        assert_eq!(summary.actions_json(), "[]\n");
+        summary.actions.push(ActionRecord {
+            from: PathBuf::from("/a/x.mkv"),
+            to: PathBuf::from("/a/X.mkv"),
+            conflict: true,
+            overwrite: false,
+            status: ActionStatus::Failed("busy".to_string()),
+        });
+        assert_eq!(
+            summary.actions_json(),
+            "[\n  {\"from\": \"/a/x.mkv\", \"to\": \"/a/X.mkv\", \"conflict\": true, \"status\": \"failed\", \"error\": \"busy\"}\n]\n"
+        );
+        assert!(!summary.actions[0].to_json(false).contains("conflict"));
+    }
+
+    #[test]
+    fn counts_skips_by_reason() {
+        let mut summary = RunSummary::default();
+        summary.count_skip("no season");
+        summary.count_skip("no season");
+        summary.note_skip(&parse("Show.E01.mkv"), "season");
+        summary.note_skip(&parse("Show.mkv"), "episode");
+        assert_eq!(summary.skipped, 4);
+        assert_eq!(summary.skip_reasons["no season"], 3);
+        // Only files with a tag are near misses
+        assert_eq!(
+            summary.unrenamable,
+            vec![("Show.E01.mkv".to_string(), "season".to_string())]
+        );
+    }
+
+    // Files
+
+    #[test]
+    fn applies_each_mode() {
+        for (mode, keeps_source) in [
+            (Mode::Rename, false),
+            (Mode::Copy, true),
+            (Mode::Move, false),
+            (Mode::Link, true),
+        ] {
+            let dir = TempDir::new();
+            let old = dir.0.join("show.s01e01.mkv");
+            fs::write(&old, b"episode").unwrap();
+            let new = dir.0.join("out").join("Show.S01E01.mkv");
+            fs::create_dir(dir.0.join("out")).unwrap();
+
+            apply_action(mode, &old, &new).unwrap();
+            assert_eq!(fs::read(&new).unwrap(), b"episode", "{:?}", mode);
+            assert_eq!(old.exists(), keeps_source, "{:?}", mode);
+            #[cfg(unix)]
+            if keeps_source {
+                use std::os::unix::fs::MetadataExt;
+                let inode = |path: &Path| fs::metadata(path).unwrap().ino();
+                assert_eq!(inode(&old) == inode(&new), mode == Mode::Link, "{:?}", mode);
+            }
+        }
+    }
+
+    #[test]
+    fn spots_already_correct_names() {
+        assert!(already_correct(
+            Path::new("/a/X.mkv"),
+            Path::new("/a/X.mkv")
+        ));
+        assert!(already_correct(
+            Path::new("/a/Ame\u{301}lie.mkv"),
+            Path::new("/a/Am\u{e9}lie.mkv")
+        ));
+        assert!(!already_correct(
+            Path::new("/a/x.mkv"),
+            Path::new("/a/X.mkv")
+        ));
+        assert!(!already_correct(
+            Path::new("/a/X.mkv"),
+            Path::new("/b/X.mkv")
+        ));
+    }
+
+    #[test]
+    fn plans_sidecar_renames() {
+        let video = PathBuf::from("/tv/show.s01e02.mkv");
+        let longer = PathBuf::from("/tv/show.s01e02.extended.mkv");
+        let videos: HashMap<PathBuf, PathBuf> = [
+            (video.clone(), PathBuf::from("/tv/Show.S01E02.mkv")),
+            (
+                longer.clone(),
+                PathBuf::from("/tv/Show.S01E02.Extended.mkv"),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let known: HashSet<PathBuf> = [
+            "/tv/show.s01e02.mkv",
+            "/tv/show.s01e02.extended.mkv",
+            "/tv/show.s01e02.en.srt",
+            "/tv/show.s01e02.extended.srt",
+            "/tv/show.s01e02.txt",
+            "/tv/show.s01e02.nfo",
+            "/other/show.s01e02.srt",
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+        let subtitles = sidecar_extensions(&Options::default());
+        let mut sidecars = plan_sidecar_renames(&videos, &known, &subtitles);
+        sidecars.sort();
+        assert_eq!(
+            sidecars,
+            vec![
+                (
+                    PathBuf::from("/tv/show.s01e02.en.srt"),
+                    PathBuf::from("/tv/Show.S01E02.en.srt")
+                ),
+                (
+                    PathBuf::from("/tv/show.s01e02.extended.srt"),
+                    PathBuf::from("/tv/Show.S01E02.Extended.srt")
+                ),
+            ]
+        );
+
+        // Only the listed extensions follow, still keyed on the original video stem
+        let options = Options {
+            sidecar_extensions: Some(ExtensionList::parse("nfo").unwrap()),
+            ..Options::default()
+        };
+        let only_nfo = plan_sidecar_renames(&videos, &known, &sidecar_extensions(&options));
+        assert_eq!(
+            only_nfo,
+            vec![(
+                PathBuf::from("/tv/show.s01e02.nfo"),
+                PathBuf::from("/tv/Show.S01E02.nfo")
+            )]
+        );
+    }
+
+    #[test]
+    fn parallel_map_keeps_order() {
+        let items: Vec<u32> = (0..50).collect();
+        let doubled: Vec<u32> = items.iter().map(|n| n * 2).collect();
+        assert_eq!(parallel_map(&items, 1, |n| n * 2), doubled);
+        assert_eq!(parallel_map(&items, 4, |n| n * 2), doubled);
+        assert_eq!(parallel_map(&items, 64, |n| n * 2), doubled);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scan_tree_does_not_follow_symlinked_folders() {
+        let dir = TempDir::new();
+        dir.touch("real/a.mkv");
+        std::os::unix::fs::symlink(dir.0.join("real"), dir.0.join("link")).unwrap();
+        let mut found: Vec<String> = scan_tree(&dir.0, usize::MAX, 2)
+            .unwrap()
+            .into_iter()
+            .map(|entry| relative_name(&entry.path, &dir.0).into_owned())
+            .collect();
+        found.sort();
+        assert_eq!(found, vec!["link", "real", "real/a.mkv"]);
+        assert_eq!(scan_tree(&dir.0, 1, 1).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn max_depth_bounds_the_scan() {
+        let dir = TempDir::new();
+        dir.touch("show/season 1/show.s01e01.mkv");
+        let found = |depth| scan_tree(&dir.0, depth, 1).unwrap().len();
+        assert_eq!((found(1), found(2), found(3)), (1, 2, 3));
+        let options = Options {
+            recursive: true,
+            max_depth: Some(2),
+            ..Options::default()
+        };
+        assert_eq!(options.scan_depth(), 2);
+        assert_eq!(Options::default().scan_depth(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_inaccessible_entries_with_a_warning() {
+        let dir = TempDir::new();
+        dir.touch("show.s01e01.mkv");
+        let dangling = dir.0.join("show.s01e02.mkv");
+        std::os::unix::fs::symlink(dir.0.join("missing.mkv"), &dangling).unwrap();
+        assert!(matches!(
+            parse_filename(&dangling, &Options::default()),
+            Err(ParseError::Inaccessible(_))
+        ));
+        let (code, summary) = run_with(&test_options(&dir), "y\n");
+        assert_eq!((code, summary.found, summary.renamed), (0, 1, 1));
+    }
+
+    #[test]
+    fn borrows_season_and_episode_from_a_subtitle() {
+        let dir = TempDir::new();
+        let video = dir.touch("badname.mkv");
+        dir.touch("Show.S01E05.srt");
+        let extensions: HashSet<String> = ["mkv".to_string()].into_iter().collect();
+        let info = subtitle_sidecar_info(&video, &extensions, &Options::default()).unwrap();
+        assert_eq!(info.season_prefix_part.as_deref(), Some("S01"));
+        assert_eq!(info.episode_number_part.as_deref(), Some("E05"));
+
+        let options = Options {
+            subtitle_fallback: true,
+            ..test_options(&dir)
+        };
+        let (code, _) = run_with(&options, "y\n");
+        assert_eq!(code, 0);
+        assert_eq!(dir.files(), vec!["Show.S01E05.mkv", "Show.S01E05.srt"]);
+    }
+
+    #[test]
+    fn borrowing_keeps_the_rest_of_the_video_name() {
+        let options = || Options {
+            subtitle_fallback: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            plan_with(&["Show - 05.mkv", "Show.S01E05.en.srt"], options()),
+            vec![("Show - 05.mkv".to_string(), "Show.S01E05.mkv".to_string())]
+        );
+        assert_eq!(
+            plan_with(&["The.Show.E05.720p.mkv", "show.s01e05.en.srt"], options()),
+            vec![(
+                "The.Show.E05.720p.mkv".to_string(),
+                "The.Show.S01E05.720p.mkv".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn nfo_metadata_overrides_the_name() {
+        let dir = TempDir::new();
+        let video = dir.touch("show.s01e09.mkv");
+        fs::write(
+            dir.0.join("show.s01e09.nfo"),
+            "<episodedetails>\n  <title>The Pilot</title>\n  <showtitle>Law &amp; Order</showtitle>\n  \
+             <season>2</season>\n  <episode>1</episode>\n</episodedetails>\n",
+        )
+        .unwrap();
+        let nfo = read_nfo(&video).unwrap();
+        assert_eq!(nfo.show.as_deref(), Some("Law & Order"));
+        assert_eq!((nfo.season, nfo.episode), (Some(2), Some(1)));
+
+        let options = Options {
+            sidecar_extensions: Some(ExtensionList::parse("+nfo").unwrap()),
+            ..test_options(&dir)
+        };
+        let (code, _) = run_with(&options, "y\n");
+        assert_eq!(code, 0);
+        assert_eq!(
+            dir.files(),
+            vec!["Law.&.Order.S02E01.mkv", "Law.&.Order.S02E01.nfo"]
+        );
+
+        let options = Options {
+            read_nfo: false,
+            ..test_options(&dir)
+        };
+        fs::write(dir.0.join("Law.&.Order.S02E01.nfo"), "<season>3</season>").unwrap();
+        assert_eq!(run_with(&options, "y\n").0, 0);
+        assert_eq!(
+            dir.files(),
+            vec!["Law.&.Order.S02E01.mkv", "Law.&.Order.S02E01.nfo"]
+        );
+    }
+
+    #[test]
+    fn finds_missing_episodes() {
+        let infos: Vec<ParsedInfo> = [
+            "Show.S01E01.mkv",
+            "Show.S01E02E03.mkv",
+            "Show.S01E05.mkv",
+            "Show.S01E08.mkv",
+            "Show.S02E01.mkv",
+            "Show.S02E02.mkv",
+        ]
+        .iter()
+        .map(|name| parse(name))
+        .collect();
+        assert_eq!(
+            find_gaps(&infos, &Options::default()),
+            vec![(("Show".to_string(), 1), vec![4, 6, 7])]
+        );
+    }
+
+    #[test]
+    fn emits_a_shell_script() {
+        let dir = TempDir::new();
+        let action = |from: &str, to: &str| ActionRecord {
+            from: dir.0.join(from),
+            to: dir.0.join(to),
+            conflict: false,
+            overwrite: false,
+            status: ActionStatus::NotApplied,
+        };
+        let actions = [
+            action("a.mkv", "b.mkv"),
+            action("b.mkv", "a.mkv"),
+            action("it's -here.mkv", "Season 01/It's.mkv"),
+        ];
+        let script = rename_script(&actions, &Options::default(), &dir.0);
+        assert!(script.starts_with("#!/bin/sh\n"));
+        let lines: Vec<&str> = script.lines().skip(4).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "mv -n -- 'a.mkv' '.a.mkv.rename-episodes-0.tmp'",
+                "mv -n -- 'b.mkv' 'a.mkv'",
+                "mv -n -- '.a.mkv.rename-episodes-0.tmp' 'b.mkv'",
+                "mkdir -p -- 'Season 01'",
+                "mv -n -- 'it'\\''s -here.mkv' 'Season 01/It'\\''s.mkv'",
+            ]
+        );
+    }
+
+    #[test]
+    fn highlights_changed_words() {
+        let (old, new) =
+            highlight_changes("show.name.s01e02.720p.mkv", "Show.Name.S01E02.720p.mkv");
+        assert_eq!(
+            old,
+            "\x1b[31mshow\x1b[0m.\x1b[31mname\x1b[0m.\x1b[31ms01e02\x1b[0m.720p.mkv"
+        );
+        assert_eq!(
+            new,
+            "\x1b[32mShow\x1b[0m.\x1b[32mName\x1b[0m.\x1b[32mS01E02\x1b[0m.720p.mkv"
+        );
+        let (old, new) = highlight_changes("Show.S01E02.mkv", "Show.S01E02.mkv");
+        assert_eq!(
+            (old.as_str(), new.as_str()),
+            ("Show.S01E02.mkv", "Show.S01E02.mkv")
+        );
+        assert_eq!(ColorChoice::parse("ALWAYS"), Ok(ColorChoice::Always));
+        assert!(ColorChoice::parse("sometimes").is_err());
+    }
+
+    #[test]
+    fn show_aliases_converge_on_one_name() {
+        let aliases = ShowAliases::parse(
+            "# Variants of one show\n\"theoffice\" = \"The Office US\"\n\
+             \"/^(the\\\\.)?office(\\\\.us)?$/\" = \"The Office US\"\n",
+        )
+        .unwrap();
+        let options = || Options {
+            show_aliases: aliases.clone(),
+            ..Options::default()
+        };
+        for name in [
+            "theoffice.s01e01.mkv",
+            "the.office.us.s01e01.mkv",
+            "Office S01E01.mkv",
+        ] {
+            assert_eq!(renamed_with(name, options()), "The.Office.US.S01E01.mkv");
+        }
+        assert_eq!(
+            renamed_with("the.office.uk.s01e01.mkv", options()),
+            "The.Office.Uk.S01E01.mkv"
+        );
+        assert!(ShowAliases::parse("theoffice = The Office").is_err());
+        assert!(ShowAliases::parse("\"/(/\" = \"Show\"").is_err());
+    }
+
+    #[test]
+    fn newer_than_skips_older_files() {
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("90M"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2"), None);
+        assert_eq!(parse_duration("h"), None);
+
+        let dir = TempDir::new();
+        let old = dir.touch("show.s01e01.mkv");
+        dir.touch("show.s01e02.mkv");
+        let day_ago = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+        fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(day_ago)
+            .unwrap();
+        let options = Options {
+            newer_than: Some(Duration::from_secs(60 * 60)),
+            ..test_options(&dir)
+        };
+        assert_eq!(run_with(&options, "y\n").0, 0);
+        assert_eq!(dir.files(), vec!["Show.S01E02.mkv", "show.s01e01.mkv"]);
+    }
+
+    // Whole runs
+
+    #[test]
+    fn keeps_apostrophes_hyphens_and_initialisms() {
+        assert_eq!(
+            renamed("Marvel's Agents of S.H.I.E.L.D. S01E02.mkv"),
+            "Marvel's.Agents.of.S.H.I.E.L.D.S01E02.mkv"
+        );
+        assert_eq!(renamed("spider-man s01e01.mkv"), "Spider-Man.S01E01.mkv");
+        let spaced = || Options {
+            separator: ' ',
+            ..Options::default()
+        };
+        assert_eq!(
+            renamed_with("marvels.agents.of.s.h.i.e.l.d.s01e03.mkv", spaced()),
+            "Marvels Agents of S.H.I.E.L.D S01E03.mkv"
+        );
+        assert_eq!(
+            renamed_with("show.s01e01.a.b.c.DDP5.1.mkv", spaced()),
+            "Show S01E01 a.b.c DDP5.1.mkv"
+        );
+    }
+
+    #[test]
+    fn keeps_recap_and_intro_episodes() {
+        let info = parse("Show.S01E02.5.720p.mkv");
+        assert_eq!(info.episode_number_part.as_deref(), Some("E02.5"));
+        assert_eq!(info.remainder_part.as_deref(), Some("720p"));
+        assert_eq!(
+            parse("Show.S01E02.1080p.mkv")
+                .episode_number_part
+                .as_deref(),
+            Some("E02")
+        );
+        assert_eq!(
+            parse("Show.S01E02.5.1.mkv").episode_number_part.as_deref(),
+            Some("E02")
+        );
+        assert_eq!(renamed("show.s01e02.5.mkv"), "Show.S01E02.5.mkv");
+        assert_eq!(renamed("show s1e2.5 recap.mkv"), "Show.S01E02.5.recap.mkv");
+        assert_eq!(renamed("show.s01e00.mkv"), "Show.S01E00.mkv");
+        let options = Options {
+            template: Some(
+                "{show_spaced} - {season}{episode} - {remainder_spaced}.{ext}".to_string(),
+            ),
+            ..Options::default()
+        };
+        assert_eq!(
+            renamed_with("show.s01e02.5.recap.mkv", options),
+            "Show - S01E02.5 - recap.mkv"
+        );
+    }
+
+    #[test]
+    fn renames_to_the_dot_format() {
+        assert_eq!(
+            renamed("show.name.s01e02.720p.WEB-DL.mkv"),
+            "Show.Name.S01E02.720p.WEB-DL.mkv"
+        );
+        assert_eq!(renamed("Show.S01E05.pt.2.mkv"), "Show.S01E05.Part.2.mkv");
+        assert_eq!(
+            renamed("The.Office.S01E01.Web.of.Lies.1080p.mkv"),
+            "The.Office.S01E01.web.of.lies.1080p.mkv"
+        );
+        assert_eq!(
+            renamed_with(
+                "show.s01e02.1080p.mkv",
+                Options {
+                    canonical_tags: false,
+                    strip_remainder: false,
+                    ..Options::default()
+                }
+            ),
+            "Show.S01E02.1080p.mkv"
+        );
+    }
+
+    #[test]
+    fn output_is_left_alone_on_a_second_run() {
+        for name in [
+            "Show.Name.S01E02.720p.mkv",
+            "Show.S01E01E02.mkv",
+            "Show.S01E05.Part.2.mkv",
+            "The.Office.US.S02E01.mkv",
+        ] {
+            assert!(plan(&[name]).is_empty(), "{}", name);
+        }
+        let options = Options {
+            separator: ' ',
+            ..Options::default()
+        };
+        assert!(plan_with(&["Show Name S01E02 720p.mkv"], options).is_empty());
+    }
+
+    #[test]
+    fn under_padded_tags_are_fixed_once() {
+        let dir = TempDir::new();
+        dir.touch("Show.S1E1.mkv");
+        assert_eq!(run_with(&test_options(&dir), "y\n").0, 0);
+        assert_eq!(dir.files(), vec!["Show.S01E01.mkv"]);
+        // A second run over its own output has nothing to do
+        let (code, summary) = run_with(&test_options(&dir), "");
+        assert_eq!(code, 0);
+        assert!(summary.actions.is_empty());
+    }
+
+    #[test]
+    fn mixed_tag_styles_come_out_alike() {
+        let planned = plan(&["show.s01e01.mkv", "show 1x02.mkv", "Show.S01.E03.mkv"]);
+        let mut targets: Vec<&str> = planned.iter().map(|(_, to)| to.as_str()).collect();
+        targets.sort();
+        assert_eq!(
+            targets,
+            vec!["Show.S01E01.mkv", "Show.S01E02.mkv", "Show.S01E03.mkv"]
+        );
+    }
+
+    #[test]
+    fn renames_with_options() {
+        let absolute = Options {
+            absolute: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            renamed_with("[SubsPlease] Frieren - 135 [1080p].mkv", absolute),
+            "Frieren.E135.1080p.mkv"
+        );
+        let spaced = Options {
+            separator: ' ',
+            ..Options::default()
+        };
+        assert_eq!(
+            renamed_with("show.s01e02.pt.1.ddp5.1.mkv", spaced),
+            "Show S01E02 Part 1 DDP5.1.mkv"
+        );
+        let lower = Options {
+            case: Case::Lower,
+            ..Options::default()
+        };
+        assert_eq!(
+            renamed_with("The.Show.S01E02.WEB-DL.mkv", lower),
+            "the.show.S01E02.web-dl.mkv"
+        );
+        let template = Options {
+            template: Some("{show_spaced} - {season}{episode}.{ext}".to_string()),
+            ..Options::default()
+        };
+        assert_eq!(
+            renamed_with("the.show.s01e02.720p.mkv", template),
+            "The Show - S01E02.mkv"
+        );
+        let ids = Options {
+            keep_ids: true,
+            id_position: IdPosition::End,
+            ..Options::default()
+        };
+        assert_eq!(
+            renamed_with("show {tvdb-78901}.s01e02.mkv", ids),
+            "Show.S01E02.{tvdb-78901}.mkv"
+        );
+    }
+
+    #[test]
+    fn sidecars_follow_their_video() {
+        let mut planned = plan(&["show.s01e02.mkv", "show.s01e02.en.srt", "show.s01e02.txt"]);
+        planned.sort();
+        assert_eq!(
+            planned,
+            vec![
+                (
+                    "show.s01e02.en.srt".to_string(),
+                    "Show.S01E02.en.srt".to_string()
+                ),
+                ("show.s01e02.mkv".to_string(), "Show.S01E02.mkv".to_string()),
+            ]
+        );
+        let options = Options {
+            rename_sidecars: false,
+            ..Options::default()
+        };
+        assert_eq!(
+            plan_with(&["show.s01e02.mkv", "show.s01e02.srt"], options).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn sidecar_extensions_choose_what_follows() {
+        let names = [
+            "show.s01e02.mkv",
+            "show.s01e02.en.srt",
+            "show.s01e02.ass",
+            "show.s01e02.nfo",
+        ];
+        let renamed_names = |sidecar_extensions: Option<&str>| {
+            let options = Options {
+                sidecar_extensions: sidecar_extensions
+                    .map(|list| ExtensionList::parse(list).unwrap()),
+                ..Options::default()
+            };
+            let mut planned: Vec<String> = plan_with(&names, options)
+                .into_iter()
+                .map(|(_, new)| new)
+                .collect();
+            planned.sort();
+            planned
+        };
+        // Subtitles follow by default; the .nfo stays
+        assert_eq!(
+            renamed_names(None),
+            vec!["Show.S01E02.ass", "Show.S01E02.en.srt", "Show.S01E02.mkv"]
+        );
+        // A plain list replaces the defaults
+        assert_eq!(
+            renamed_names(Some("srt")),
+            vec!["Show.S01E02.en.srt", "Show.S01E02.mkv"]
+        );
+        // "+" adds to them
+        assert_eq!(
+            renamed_names(Some("+nfo")),
+            vec![
+                "Show.S01E02.ass",
+                "Show.S01E02.en.srt",
+                "Show.S01E02.mkv",
+                "Show.S01E02.nfo"
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_episodes() {
+        let options = Options {
+            episode_filters: parse_episode_filters("S01E02").unwrap(),
+            ..Options::default()
+        };
+        let planned = plan_with(&["show.s01e01.mkv", "show.s01e02.mkv"], options);
+        assert_eq!(
+            planned,
+            vec![("show.s01e02.mkv".to_string(), "Show.S01E02.mkv".to_string())]
+        );
+    }
+
+    #[test]
+    fn stripped_remainders_can_conflict() {
+        let dir = TempDir::new();
+        dir.touch("Show.S01E01.720p.mkv");
+        dir.touch("Show.S01E01.1080p.mkv");
+        let options = Options {
+            strip_remainder: true,
+            json: true,
+            ..test_options(&dir)
+        };
+        let (code, summary) = run_with(&options, "");
+        assert_eq!(code, 1);
+        assert_eq!(summary.conflicts, 1);
+        assert!(summary.actions.iter().all(|action| action.conflict));
+        assert_eq!(dir.files().len(), 2);
+    }
+
+    #[test]
+    fn warns_about_mixed_show_names_in_a_folder() {
+        let dir = TempDir::new();
+        dir.touch("The.Office.S01E01.mkv");
+        dir.touch("office.us.s01e02.mkv");
+        dir.touch("Other/office.us.s01e03.mkv");
+        let options = Options {
+            dry_run: true,
+            recursive: true,
+            ..test_options(&dir)
+        };
+        let (_, summary) = run_with(&options, "");
+        assert_eq!(summary.mixed_show_names.len(), 1);
+        assert_eq!(
+            summary.mixed_show_names[0].1,
+            vec!["Office.Us".to_string(), "The.Office".to_string()]
+        );
+    }
+
+    #[test]
+    fn existing_targets_conflict() {
+        let dir = TempDir::new();
+        dir.touch("show.s01e01.mkv");
+        dir.touch("Show.S01E01.mkv");
+        let (code, summary) = run_with(&test_options(&dir), "y\n");
+        assert_eq!((code, summary.conflicts), (1, 1));
+    }
+
+    #[test]
+    fn force_asks_before_overwriting() {
+        let dir = TempDir::new();
+        fs::write(dir.touch("show.s01e01.mkv"), b"new").unwrap();
+        dir.touch("Show.S01E01.mkv");
+        dir.touch("show.s01e02.mkv");
+        dir.touch("Show.S01E02.mkv");
+        let options = Options {
+            force: true,
+            ..test_options(&dir)
+        };
+        // Overwrite E01, keep E02; then confirm
+        let (code, summary) = run_with(&options, "y\nn\ny\n");
+        assert_eq!((code, summary.conflicts, summary.renamed), (0, 0, 1));
+        assert_eq!(
+            dir.files(),
+            vec!["Show.S01E01.mkv", "Show.S01E02.mkv", "show.s01e02.mkv"]
+        );
+        assert_eq!(fs::read(dir.0.join("Show.S01E01.mkv")).unwrap(), b"new");
+
+        // Declining every overwrite leaves nothing to do
+        let (code, summary) = run_with(&options, "n\n");
+        assert_eq!((code, summary.outcome), (0, "nothing_to_do"));
+    }
+
+    #[test]
+    fn force_reports_conflicts_before_asking() {
+        let dir = TempDir::new();
+        dir.touch("show.s01e01.mkv");
+        dir.touch("Show.S01E01.mkv");
+        dir.touch("show.s01e02.mkv");
+        dir.touch("show.1x02.mkv");
+        let options = Options {
+            force: true,
+            ..test_options(&dir)
+        };
+        // Declining would drop E01 from the plan, but the shared E02 target stops the run first
+        let (code, summary) = run_with(&options, "n\n");
+        assert_eq!((code, summary.conflicts), (1, 1));
+        assert_eq!(summary.actions.len(), 3);
+        assert_eq!(dir.files().len(), 4);
+    }
+
+    #[test]
+    fn series_folders_give_the_season() {
+        let options = Options {
+            recursive: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            plan_with(&["Series 2/Show - 14.mkv"], options),
+            vec![(
+                "Series 2/Show - 14.mkv".to_string(),
+                "Series 2/Show.S02E14.mkv".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn lowercases_extensions() {
+        let options = || Options {
+            lowercase_ext: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            plan_with(&["Show.S01E02.MKV"], options()),
+            vec![("Show.S01E02.MKV".to_string(), "Show.S01E02.mkv".to_string())]
+        );
+        assert!(plan_with(&["Show.S01E02.mkv"], options()).is_empty());
+        // The extension keeps its case by default
+        assert!(plan(&["Show.S01E02.MKV"]).is_empty());
+    }
+
+    #[test]
+    fn pads_numbers_uniformly() {
+        let names = ["show.s01e09.mkv", "show.s01e120.mkv"];
+        let pad = |pad| {
+            plan_with(
+                &names,
+                Options {
+                    pad,
+                    ..Options::default()
+                },
+            )
+            .into_iter()
+            .map(|(_, new)| new)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            pad(Padding::Width(2)),
+            vec!["Show.S01E09.mkv", "Show.S01E120.mkv"]
+        );
+        assert_eq!(
+            pad(Padding::Width(3)),
+            vec!["Show.S001E009.mkv", "Show.S001E120.mkv"]
+        );
+        assert_eq!(
+            pad(Padding::Auto),
+            vec!["Show.S01E009.mkv", "Show.S01E120.mkv"]
+        );
+        assert_eq!(pad_tag("E1-E3", 2), "E01-E03");
+        assert_eq!(Padding::parse("auto"), Ok(Padding::Auto));
+        assert!(Padding::parse("9").is_err());
+    }
+
+    #[test]
+    fn skips_hidden_files_and_samples() {
+        let names = [
+            "show.s01e01.mkv",
+            ".show.s01e02.mkv",
+            "show.s01e02-sample.mkv",
+            "sample.mkv",
+            "the.sampler.s01e03.mkv",
+        ];
+        assert_eq!(
+            plan(&names),
+            vec![
+                ("show.s01e01.mkv".to_string(), "Show.S01E01.mkv".to_string()),
+                (
+                    "the.sampler.s01e03.mkv".to_string(),
+                    "The.Sampler.S01E03.mkv".to_string()
+                ),
+            ]
+        );
+        let options = Options {
+            include_samples: true,
+            ..Options::default()
+        };
+        assert!(
+            plan_with(&names, options)
+                .iter()
+                .any(|(old, _)| old == "show.s01e02-sample.mkv")
+        );
+    }
+
+    #[test]
+    fn organizes_into_season_folders() {
+        let dir = TempDir::new();
+        dir.touch("show.s01e02.mkv");
+        dir.touch("show.s00e01.mkv");
+        dir.touch("Season 02/show.s02e01.mkv");
+        dir.touch("Season 01/Show.S01E03.mkv");
+        dir.touch("show.s01e03.mkv");
+        let options = Options {
+            organize: true,
+            recursive: true,
+            ..test_options(&dir)
+        };
+        // The existing "Season 01/Show.S01E03.mkv" is a conflict
+        let (code, summary) = run_with(&options, "y\n");
+        assert_eq!((code, summary.conflicts), (1, 1));
+
+        fs::remove_file(dir.0.join("show.s01e03.mkv")).unwrap();
+        let (code, _) = run_with(&options, "y\n");
+        assert_eq!(code, 0);
+        assert_eq!(
+            dir.files(),
+            vec![
+                "Season 01/Show.S01E02.mkv",
+                "Season 01/Show.S01E03.mkv",
+                "Season 02/Show.S02E01.mkv",
+                "Specials/Show.S00E01.mkv",
+            ]
+        );
+    }
+
+    #[test]
+    fn respects_the_ignore_file() {
+        let dir = TempDir::new();
+        dir.touch("show.s01e01.mkv");
+        dir.touch("show.s01e01.trailer.mkv");
+        dir.touch("show.s01e02.mkv");
+        dir.touch("show.s01e03.mkv");
+        fs::write(
+            dir.0.join(IGNORE_FILE),
+            "# never touch these\n*trailer*\n\nshow.s01e0[!1].mkv\n",
+        )
+        .unwrap();
+        let (code, summary) = run_with(&test_options(&dir), "y\n");
+        assert_eq!((code, summary.renamed), (0, 1));
+        assert_eq!(
+            dir.files(),
+            vec![
+                ".renameignore",
+                "Show.S01E01.mkv",
+                "show.s01e01.trailer.mkv",
+                "show.s01e02.mkv",
+                "show.s01e03.mkv",
+            ]
+        );
+        assert!(
+            glob_to_regex("a?c.(1).mkv")
+                .unwrap()
+                .is_match("abc.(1).mkv")
+        );
+    }
+
+    #[test]
+    fn renames_season_and_show_folders() {
+        let dir = TempDir::new();
+        dir.touch("breaking.bad/season 1/breaking.bad.s01e01.mkv");
+        dir.touch("breaking.bad/Specials/breaking.bad.s00e01.mkv");
+        dir.touch("breaking.bad/Series 2/breaking.bad.s02e01.mkv");
+        let options = Options {
+            rename_dirs: true,
+            recursive: true,
+            ..test_options(&dir)
+        };
+        let (code, summary) = run_with(&options, "y\n");
+        assert_eq!((code, summary.renamed), (0, 5));
+        assert_eq!(
+            dir.files(),
+            vec![
+                "Breaking Bad/Season 01/Breaking.Bad.S01E01.mkv",
+                "Breaking Bad/Series 2/Breaking.Bad.S02E01.mkv",
+                "Breaking Bad/Specials/Breaking.Bad.S00E01.mkv",
+            ]
+        );
+
+        // The scanned folder itself is never renamed
+        let dir = TempDir::new();
+        dir.touch("show.s01e01.mkv");
+        let options = Options {
+            rename_dirs: true,
+            directory: Some(dir.0.join(".")),
+            ..test_options(&dir)
+        };
+        let (code, summary) = run_with(&options, "y\n");
+        assert_eq!((code, summary.renamed), (0, 1));
+    }
+
+    #[test]
+    fn keeps_the_remainder_case_when_asked() {
+        let names = ["show.s01e02.Director's.Cut.Part.2.720p.mkv"];
+        let options = Options {
+            keep_remainder_case: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            plan_with(&names, options),
+            vec![(
+                names[0].to_string(),
+                "Show.S01E02.Part.2.Director's.Cut.720p.mkv".to_string()
+            )]
+        );
+        assert_eq!(
+            plan(&names)[0].1,
+            "Show.S01E02.Part.2.director's.cut.720p.mkv"
+        );
+    }
+
+    #[test]
+    fn name_and_season_flags_replace_the_prompts() {
+        let dir = TempDir::new();
+        dir.touch("e01.mkv");
+        dir.touch("other.s01e02.mkv");
+        dir.touch("show.e03.mkv");
+        let options = Options {
+            show_name: Some("The Office".to_string()),
+            season: Some(2),
+            assume_yes: true,
+            ..test_options(&dir)
+        };
+        let (code, summary) = run_with(&options, "");
+        assert_eq!((code, summary.renamed), (0, 3));
+        assert_eq!(
+            dir.files(),
+            vec![
+                "Other.S01E02.mkv",
+                "Show.S02E03.mkv",
+                "The.Office.S02E01.mkv"
+            ]
+        );
+
+        // --force-name overrides the show the file names give
+        let names = ["other.s01e02.mkv"];
+        let options = Options {
+            show_name: Some("The Office".to_string()),
+            force_name: true,
+            ..Options::default()
+        };
+        assert_eq!(plan_with(&names, options)[0].1, "The.Office.S01E02.mkv");
+
+        // Without --season, --yes still can't supply a missing season
+        let dir = TempDir::new();
+        dir.touch("e01.mkv");
+        let options = Options {
+            show_name: Some("The Office".to_string()),
+            assume_yes: true,
+            ..test_options(&dir)
+        };
+        assert_eq!(run_with(&options, "").0, EXIT_NEEDS_INPUT);
+    }
+
+    #[test]
+    fn season_flag_reads_like_the_prompt() {
+        for raw in ["3", "03", "S03", "Season 3"] {
+            assert_eq!(parse_season(raw), Some(3), "{}", raw);
+            assert_eq!(
+                parse_season_input(raw, &Options::default()),
+                Ok("S03".to_string())
+            );
+        }
+        assert_eq!(parse_season("three"), None);
+
+        let dir = TempDir::new();
+        dir.touch("e01.mkv");
+        let options = Options {
+            show_name: Some("The Office".to_string()),
+            season: parse_season("S03"),
+            assume_yes: true,
+            ..test_options(&dir)
+        };
+        assert_eq!(run_with(&options, "").0, 0);
+        assert_eq!(dir.files(), vec!["The.Office.S03E01.mkv"]);
+    }
+
+    #[test]
+    fn keeps_a_year_with_the_show_name() {
+        let names = [
+            "Show (2019) S01E02.mkv",
+            "Show.Name.2019.S01E02.mkv",
+            "other.[1999].e03.mkv",
+        ];
+        let info = parse(names[0]);
+        assert_eq!(info.show_name_part.as_deref(), Some("show.2019"));
+        assert_eq!(info.season_prefix_part.as_deref(), Some("S01"));
+        let options = Options {
+            season: Some(1),
+            ..Options::default()
+        };
+        assert_eq!(
+            plan_with(&names, options),
+            vec![
+                (names[0].to_string(), "Show.2019.S01E02.mkv".to_string()),
+                (names[2].to_string(), "Other.1999.S01E03.mkv".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn offers_the_show_typed_for_a_sibling_folder() {
+        let dir = TempDir::new();
+        dir.touch("show/season 1/e01.mkv");
+        dir.touch("show/season 2/e01.mkv");
+        let options = Options {
+            recursive: true,
+            ..test_options(&dir)
+        };
+        // The second folder's show prompt is answered by just pressing Enter
+        let (code, _) = run_with(&options, "My Show\n\n\n\ny\n");
+        assert_eq!(code, 0);
+        assert_eq!(
+            dir.files(),
+            vec![
+                "show/season 1/My.Show.S01E01.mkv",
+                "show/season 2/My.Show.S02E01.mkv",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_json_plans() {
+        for json in [
+            r#"[{"from": "a.mkv"}]"#,
+            r#"{"from": "a.mkv", "to": null}"#,
+            r#"[{"from": "a.mkv"}, {"to": "b.mkv"}]"#,
+            r#"[{"from": "a.mkv", "from": "c.mkv", "to": "b.mkv"}]"#,
+            r#"["a.mkv", "b.mkv"]"#,
+            r#"[{"from": "a.mkv", "to": "b.mkv""#,
+        ] {
+            assert!(parse_plan(json).is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn applies_a_plan_from_another_tool() {
+        assert_eq!(
+            parse_plan("a.mkv\tb.mkv\n\nc d.mkv\te.mkv\n").unwrap(),
+            vec![
+                (PathBuf::from("a.mkv"), PathBuf::from("b.mkv")),
+                (PathBuf::from("c d.mkv"), PathBuf::from("e.mkv")),
+            ]
+        );
+        assert!(parse_plan("a.mkv b.mkv").is_err());
+        let json = r#"[{"from": "a.mkv", "to": "b.mkv", "conflict": false, "status": "not_applied", "error": null}]"#;
+        assert_eq!(
+            parse_plan(json).unwrap(),
+            vec![(PathBuf::from("a.mkv"), PathBuf::from("b.mkv"))]
+        );
+        let reversed = r#"[{"to": "b.mkv", "status": "done", "from": "a.mkv"}]"#;
+        assert_eq!(
+            parse_plan(reversed).unwrap(),
+            vec![(PathBuf::from("a.mkv"), PathBuf::from("b.mkv"))]
+        );
+
+        let dir = TempDir::new();
+        dir.touch("pilot.mkv");
+        dir.touch("taken.mkv");
+        let options = Options {
+            plan: Some(parse_plan("pilot.mkv\tShow.S01E01.mkv\n").unwrap()),
+            ..test_options(&dir)
+        };
+        let (code, summary) = run_with(&options, "y\n");
+        assert_eq!((code, summary.renamed), (0, 1));
+        assert_eq!(dir.files(), vec!["Show.S01E01.mkv", "taken.mkv"]);
+
+        // The usual conflict checks apply, and every source must exist
+        let options = Options {
+            plan: Some(parse_plan("Show.S01E01.mkv\ttaken.mkv\n").unwrap()),
+            ..test_options(&dir)
+        };
+        assert_eq!(run_with(&options, "y\n").1.outcome, "conflicts");
+        let options = Options {
+            plan: Some(parse_plan("missing.mkv\tx.mkv\n").unwrap()),
+            ..test_options(&dir)
+        };
+        assert_eq!(run_with(&options, "y\n").1.outcome, "error");
+    }
+
+    #[test]
+    fn infers_the_show_from_the_folders_other_files() {
+        let dir = TempDir::new();
+        dir.touch("the.expanse.s01e01.mkv");
+        dir.touch("the.expanse.s01e02.mkv");
+        dir.touch("s01e05.mkv");
+        // The folder's show is the prompt's default, so Enter accepts it
+        let (code, _) = run_with(&test_options(&dir), "\n1\ny\n");
+        assert_eq!(code, 0);
+        assert!(dir.files().contains(&"The.Expanse.S01E05.mkv".to_string()));
+
+        // --infer-show takes it without asking
+        let dir = TempDir::new();
+        dir.touch("the.expanse.s01e01.mkv");
+        dir.touch("s01e05.mkv");
+        let options = Options {
+            infer_show: true,
+            assume_yes: true,
+            ..test_options(&dir)
+        };
+        assert_eq!(run_with(&options, "").0, 0);
+        assert_eq!(
+            dir.files(),
+            vec!["The.Expanse.S01E01.mkv", "The.Expanse.S01E05.mkv"]
+        );
+    }
+
+    #[test]
+    fn strips_trailing_release_group_suffixes() {
+        for name in [
+            "show.s01e02.720p-sylix[EZTVx.to].mkv",
+            "show.s01e02.720p-[EZTV].mkv",
+            "show.s01e02.720p-{Group}.mkv",
+            "show.s01e02.720p-RARBG.mkv",
+            "show.s01e02.720p-eztvx.to.mkv",
+        ] {
+            assert_eq!(
+                parse(name).remainder_part.as_deref(),
+                Some("720p"),
+                "{}",
+                name
+            );
+        }
+        // Words after a dash that aren't known groups, and ID tags, stay
+        assert_eq!(
+            parse("show.s01e02.spider-man.mkv")
+                .remainder_part
+                .as_deref(),
+            Some("spider-man")
+        );
+        assert_eq!(
+            parse("show.s01e02-[tmdbid-1396].mkv")
+                .remainder_part
+                .as_deref(),
+            Some("-[tmdbid-1396]")
+        );
+        let keep = Options {
+            keep_suffix: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            parse_with("show.s01e02.720p-RARBG.mkv", &keep)
+                .remainder_part
+                .as_deref(),
+            Some("720p-rarbg")
+        );
+    }
+
+    #[test]
+    fn flags_targets_differing_only_in_case() {
+        let dir = TempDir::new();
+        dir.touch("Show.S01E02.mkv");
+        dir.touch("show.s01e02.MKV");
+        dir.touch("show.s01e03.mkv");
+        let options = Options {
+            case_insensitive: true,
+            ..test_options(&dir)
+        };
+        let (code, summary) = run_with(&options, "y\n");
+        assert_eq!((code, summary.conflicts), (1, 1));
+        assert!(summary.actions.iter().any(|action| action.conflict));
+
+        // A case-sensitive filesystem holds both names
+        let (code, summary) = run_with(&test_options(&dir), "y\n");
+        assert_eq!((code, summary.renamed), (0, 2));
+    }
+
+    #[test]
+    fn splits_episode_titles_from_release_tags() {
+        assert_eq!(
+            split_episode_title("the.pilot.1080p.web-dl"),
+            ("the.pilot", "1080p.web-dl")
+        );
+        assert_eq!(
+            split_episode_title("dts.hd.ma.x264"),
+            ("", "dts.hd.ma.x264")
+        );
+        assert_eq!(split_episode_title("the.pilot"), ("the.pilot", ""));
+        // Titles may hold words that are also tags
+        assert_eq!(
+            split_episode_title("opus.magnum.720p"),
+            ("opus.magnum", "720p")
+        );
+        assert_eq!(
+            split_episode_title("web.of.lies.1080p.nf.web-dl"),
+            ("web.of.lies", "1080p.nf.web-dl")
+        );
+        assert_eq!(split_episode_title("magnum.opus"), ("magnum.opus", ""));
+        assert_eq!(
+            split_episode_title("proper.720p.hdtv"),
+            ("", "proper.720p.hdtv")
+        );
+
+        let options = Options {
+            template: Some(
+                "{show_spaced} - {season}{episode} - {title_spaced} [{tags}].{ext}".to_string(),
+            ),
+            ..Options::default()
+        };
+        assert_eq!(
+            plan_with(
+                &[
+                    "show.s01e02.the.pilot.1080p.web-dl.mkv",
+                    "show.s01e03.720p.mkv",
+                    "show.s01e04.opus.magnum.720p.mkv"
+                ],
+                options
+            ),
+            vec![
+                (
+                    "show.s01e02.the.pilot.1080p.web-dl.mkv".to_string(),
+                    "Show - S01E02 - the pilot [1080p.WEB-DL].mkv".to_string()
+                ),
+                (
+                    "show.s01e03.720p.mkv".to_string(),
+                    "Show - S01E03 [720p].mkv".to_string()
+                ),
+                (
+                    "show.s01e04.opus.magnum.720p.mkv".to_string(),
+                    "Show - S01E04 - opus magnum [720p].mkv".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn retries_only_transient_errors() {
+        assert!(is_transient(&io::Error::from(io::ErrorKind::TimedOut)));
+        assert!(is_transient(&io::Error::from(io::ErrorKind::ResourceBusy)));
+        assert!(!is_transient(&io::Error::from(io::ErrorKind::NotFound)));
+        assert!(!is_transient(&io::Error::from(
+            io::ErrorKind::PermissionDenied
+        )));
+
+        // A missing source fails at once, without waiting on retries
+        let dir = TempDir::new();
+        let options = Options {
+            retries: 5,
+            ..Options::default()
+        };
+        let start = Instant::now();
+        let result =
+            apply_with_retries(&options, &dir.0.join("missing.mkv"), &dir.0.join("new.mkv"));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert!(start.elapsed() < Duration::from_millis(200));
+    }
+
+    #[test]
+    fn checks_names_without_renaming() {
+        let dir = TempDir::new();
+        dir.touch("Show.S01E01.mkv");
+        let options = Options {
+            check: true,
+            ..test_options(&dir)
+        };
+        let (code, summary) = run_with(&options, "");
+        assert_eq!((code, summary.outcome), (0, "conforms"));
+
+        dir.touch("show.s01e02.mkv");
+        dir.touch("E03.mkv");
+        let (code, summary) = run_with(&options, "");
+        assert_eq!((code, summary.outcome), (1, "nonconforming"));
+        assert_eq!(
+            dir.files(),
+            vec!["E03.mkv", "Show.S01E01.mkv", "show.s01e02.mkv"]
+        );
+    }
+
+    #[test]
+    fn bad_season_input_only_skips_files_lacking_a_season() {
+        let dir = TempDir::new();
+        dir.touch("Show.S01E01.mkv"); // Complete
+        dir.touch("show.s01e02.mkv"); // Complete, needs a rename
+        dir.touch("S01E03.mkv"); // Lacks only the show
+        dir.touch("Show - 04.mkv"); // Lacks the season
+        let (code, summary) = run_with(&test_options(&dir), "Show\nnot a season\ny\n");
+        assert_eq!(code, 0);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(
+            dir.files(),
+            vec![
+                "Show - 04.mkv",
+                "Show.S01E01.mkv",
+                "Show.S01E02.mkv",
+                "Show.S01E03.mkv"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn title_command_supplies_episode_titles() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new();
+        let script = dir.0.join("titles.sh");
+        fs::write(
+            &script,
+            "#!/bin/sh\n[ \"$3\" = 2 ] || exit 1\necho \"Title of $1 $2x$3\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let options = Options {
+            title_command: Some(script.display().to_string()),
+            ..Options::default()
+        };
+        assert_eq!(
+            plan_with(
+                &["show.s01e02.old.title.1080p.mkv", "show.s01e03.kept.mkv"],
+                options
+            ),
+            vec![
+                (
+                    "show.s01e02.old.title.1080p.mkv".to_string(),
+                    "Show.S01E02.Title.of.Show.1x2.1080p.mkv".to_string()
+                ),
+                (
+                    "show.s01e03.kept.mkv".to_string(),
+                    "Show.S01E03.kept.mkv".to_string()
+                ),
+            ]
+        );
+
+        // Quoted arguments reach the command whole
+        let options = Options {
+            title_command: Some("printf '%s\\n' 'Quoted Title'".to_string()),
+            ..Options::default()
+        };
+        assert_eq!(
+            renamed_with("show.s01e02.mkv", options),
+            "Show.S01E02.Quoted.Title.mkv"
+        );
+    }
+
+    #[test]
+    fn season_folders_give_the_season() {
+        let options = Options {
+            recursive: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            plan_with(&["Season 3/Show.E05.mkv"], options),
+            vec![(
+                "Season 3/Show.E05.mkv".to_string(),
+                "Season 3/Show.S03E05.mkv".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn folder_levels_give_the_prompt_defaults() {
+        let dir = TempDir::new();
+        dir.touch("The Show/2019/Season 1/e01.mkv");
+        let options = Options {
+            recursive: true,
+            show_from_level: 3,
+            ..test_options(&dir)
+        };
+        let (code, _) = run_with(&options, "\n\ny\n");
+        assert_eq!(code, 0);
+        assert_eq!(
+            dir.files(),
+            vec!["The Show/2019/Season 1/The.Show.S01E01.mkv"]
+        );
+    }
+
+    #[test]
+    fn prompts_once_per_folder() {
+        let dir = TempDir::new();
+        dir.touch("Show/Specials/e01.mkv");
+        dir.touch("Show/Specials/e02.mkv");
+        dir.touch("Other/Season 2/e03.mkv");
+        let options = Options {
+            recursive: true,
+            ..test_options(&dir)
+        };
+        // Folder defaults for "Other/Season 2", then "Show/Specials"; then confirm
+        let (code, summary) = run_with(&options, "\n\n\n\ny\n");
+        assert_eq!(code, 0);
+        assert_eq!(summary.renamed, 3);
+        assert_eq!(
+            dir.files(),
+            vec![
+                "Other/Season 2/Other.S02E03.mkv",
+                "Show/Specials/Show.S00E01.mkv",
+                "Show/Specials/Show.S00E02.mkv",
+            ]
+        );
+    }
+
+    #[test]
+    fn yes_fails_when_prompts_are_needed() {
+        let dir = TempDir::new();
+        dir.touch("e01.mkv");
+        let options = Options {
+            assume_yes: true,
+            ..test_options(&dir)
+        };
+        let (code, summary) = run_with(&options, "");
+        assert_eq!(code, EXIT_NEEDS_INPUT);
+        assert_eq!(summary.outcome, "needs_input");
+    }
+
+    #[test]
+    fn second_tags_are_left_for_review() {
+        let dir = TempDir::new();
+        dir.touch("Show.S01E01.S01E02.mkv");
+        let (code, summary) = run_with(&test_options(&dir), "");
+        assert_eq!(code, 0);
+        assert_eq!(summary.needs_review, vec!["Show.S01E01.S01E02.mkv"]);
+        assert!(summary.actions.is_empty());
+    }
+
+    #[test]
+    fn undoes_the_last_run() {
+        let dir = TempDir::new();
+        dir.touch("show.s01e01.mkv");
+        dir.touch("show.s01e01.en.srt");
+        let options = Options {
+            assume_yes: true,
+            ..test_options(&dir)
+        };
+        assert_eq!(run_with(&options, "").0, 0);
+        assert_eq!(dir.files(), vec!["Show.S01E01.en.srt", "Show.S01E01.mkv"]);
+
+        let undo = Options {
+            undo: true,
+            ..options
+        };
+        assert_eq!(run_with(&undo, "").0, 0);
+        assert_eq!(dir.files(), vec!["show.s01e01.en.srt", "show.s01e01.mkv"]);
+    }
+
+    #[test]
+    fn plans_and_applies_without_prompting() {
+        let dir = TempDir::new();
+        dir.touch("show.s01e01.mkv");
+        dir.touch("show.s01e02.mkv");
+        dir.touch("Show.S01E02.mkv");
+        dir.touch("e03.mkv"); // Would need a season prompt
+        let options = Options {
+            target_fs: FsType::Posix,
+            ..Options::default()
+        };
+        let mut actions = plan_renames(&dir.0, &options).unwrap();
+        let planned: Vec<(String, bool)> = actions
+            .iter()
+            .map(|action| (display_name(&action.to).into_owned(), action.conflict))
+            .collect();
+        assert_eq!(
+            planned,
+            vec![
+                ("Show.S01E01.mkv".to_string(), false),
+                ("Show.S01E02.mkv".to_string(), true),
+            ]
+        );
+        assert_eq!(dir.files().len(), 4);
+
+        assert_eq!(
+            apply_renames(&mut actions, &options, &dir.0).unwrap(),
+            (1, 0)
+        );
+        assert_eq!(actions[0].status, ActionStatus::Done);
+        assert_eq!(actions[1].status, ActionStatus::NotApplied);
+        assert_eq!(
+            dir.files(),
+            vec![
+                "Show.S01E01.mkv",
+                "Show.S01E02.mkv",
+                "e03.mkv",
+                "show.s01e02.mkv"
+            ]
+        );
+    }
+
+    /// Renames among the files of `dir` as given, returning each action's status.
+    fn apply_in(dir: &TempDir, renames: &[(&str, &str)]) -> Vec<ActionStatus> {
+        let mut actions: Vec<ActionRecord> = renames
+            .iter()
+            .map(|(from, to)| ActionRecord {
+                from: dir.0.join(from),
+                to: dir.0.join(to),
+                conflict: false,
+                overwrite: false,
+                status: ActionStatus::NotApplied,
+            })
+            .collect();
+        apply_renames(&mut actions, &Options::default(), &dir.0).unwrap();
+        actions.into_iter().map(|action| action.status).collect()
+    }
+
+    #[test]
+    fn atomic_rolls_back_on_failure() {
+        let dir = TempDir::new();
+        fs::write(dir.0.join("a.mkv"), b"a").unwrap();
+        fs::write(dir.0.join("b.mkv"), b"b").unwrap();
+        dir.touch("c.mkv");
+        let mut actions: Vec<ActionRecord> = [
+            ("a.mkv", "b.mkv"),
+            ("b.mkv", "a.mkv"),
+            ("c.mkv", "C.mkv"),
+            ("missing.mkv", "M.mkv"),
+        ]
+        .iter()
+        .map(|(from, to)| ActionRecord {
+            from: dir.0.join(from),
+            to: dir.0.join(to),
+            conflict: false,
+            overwrite: false,
+            status: ActionStatus::NotApplied,
+        })
+        .collect();
+        let options = Options {
+            atomic: true,
+            ..Options::default()
+        };
+        let (succeeded, failed) = apply_renames(&mut actions, &options, &dir.0).unwrap();
+        assert_eq!((succeeded, failed), (0, 1));
+        assert!(matches!(actions[3].status, ActionStatus::Failed(_)));
+        assert!(
+            !actions
+                .iter()
+                .any(|action| action.status == ActionStatus::Done)
+        );
+        assert_eq!(dir.files(), vec!["a.mkv", "b.mkv", "c.mkv"]);
+        assert_eq!(fs::read(dir.0.join("a.mkv")).unwrap(), b"a");
+        assert!(!dir.0.join(UNDO_LOG).exists());
+    }
+
+    #[test]
+    fn atomic_rollback_restores_overwritten_files() {
+        let dir = TempDir::new();
+        fs::write(dir.0.join("new.mkv"), b"new").unwrap();
+        fs::write(dir.0.join("Old.mkv"), b"old").unwrap();
+        let action = |from: &str, to: &str| ActionRecord {
+            from: dir.0.join(from),
+            to: dir.0.join(to),
+            conflict: false,
+            overwrite: true,
+            status: ActionStatus::NotApplied,
+        };
+        let options = Options {
+            atomic: true,
+            force: true,
+            ..Options::default()
+        };
+        let mut actions = vec![action("new.mkv", "Old.mkv"), action("missing.mkv", "M.mkv")];
+        let (succeeded, failed) = apply_renames(&mut actions, &options, &dir.0).unwrap();
+        assert_eq!((succeeded, failed), (0, 1));
+        assert_eq!(actions[0].status, ActionStatus::RolledBack);
+        assert_eq!(dir.files(), vec!["Old.mkv", "new.mkv"]);
+        assert_eq!(fs::read(dir.0.join("Old.mkv")).unwrap(), b"old");
+
+        // Once the batch succeeds, the replaced file is gone
+        let mut actions = vec![action("new.mkv", "Old.mkv")];
+        assert_eq!(
+            apply_renames(&mut actions, &options, &dir.0).unwrap(),
+            (1, 0)
+        );
+        assert_eq!(dir.files(), vec!["Old.mkv"]);
+        assert_eq!(fs::read(dir.0.join("Old.mkv")).unwrap(), b"new");
+    }
+
+    #[test]
+    fn swaps_names_through_a_temporary_file() {
+        let dir = TempDir::new();
+        fs::write(dir.0.join("a.mkv"), b"a").unwrap();
+        fs::write(dir.0.join("b.mkv"), b"b").unwrap();
+        let statuses = apply_in(&dir, &[("a.mkv", "b.mkv"), ("b.mkv", "a.mkv")]);
+        assert_eq!(statuses, vec![ActionStatus::Done, ActionStatus::Done]);
+        assert_eq!(fs::read(dir.0.join("a.mkv")).unwrap(), b"b");
+        assert_eq!(fs::read(dir.0.join("b.mkv")).unwrap(), b"a");
+        assert_eq!(dir.files(), vec!["a.mkv", "b.mkv"]);
+    }
+
+    #[test]
+    fn orders_chains_and_cycles() {
+        let dir = TempDir::new();
+        for name in ["a", "b", "c", "x", "y"] {
+            fs::write(dir.0.join(name), name).unwrap();
+        }
+        // A three-way cycle, and a chain that must run back to front
+        let statuses = apply_in(
+            &dir,
+            &[("a", "b"), ("b", "c"), ("c", "a"), ("x", "y"), ("y", "z")],
+        );
+        assert!(statuses.iter().all(|status| *status == ActionStatus::Done));
+        for (name, contents) in [("a", "c"), ("b", "a"), ("c", "b"), ("y", "x"), ("z", "y")] {
+            assert_eq!(fs::read_to_string(dir.0.join(name)).unwrap(), contents);
+        }
+        assert_eq!(dir.files(), vec!["a", "b", "c", "y", "z"]);
+    }
+
+    #[test]
+    fn swaps_edited_names_in_a_run() {
+        let dir = TempDir::new();
+        dir.touch("show.s01e01.mkv");
+        dir.touch("show.s01e02.mkv");
+        let options = Options {
+            interactive: true,
+            ..test_options(&dir)
+        };
+        let (code, summary) = run_with(&options, "e\nshow.s01e02.mkv\ne\nshow.s01e01.mkv\ny\n");
+        assert_eq!((code, summary.conflicts, summary.renamed), (0, 0, 2));
+    }
+
+    #[test]
+    fn copies_into_an_output_directory() {
+        let dir = TempDir::new();
+        let library = TempDir::new();
+        dir.touch("show.s01e01.mkv");
+        dir.touch("show.s01e02.mkv");
+        library.touch("Show.S01E02.mkv");
+        let options = Options {
+            mode: Mode::Copy,
+            out_dir: Some(library.0.clone()),
+            assume_yes: true,
+            ..test_options(&dir)
+        };
+        // The output directory is the namespace for collisions
+        let (code, summary) = run_with(&options, "");
+        assert_eq!((code, summary.conflicts), (1, 1));
+
+        fs::remove_file(library.0.join("Show.S01E02.mkv")).unwrap();
+        assert_eq!(run_with(&options, "").0, 0);
+        assert_eq!(dir.files(), vec!["show.s01e01.mkv", "show.s01e02.mkv"]);
+        assert_eq!(library.files(), vec!["Show.S01E01.mkv", "Show.S01E02.mkv"]);
+    }
+
+    #[test]
+    fn cancelled_runs_change_nothing() {
+        let dir = TempDir::new();
+        dir.touch("show.s01e01.mkv");
+        let (code, summary) = run_with(&test_options(&dir), "n\n");
+        assert_eq!((code, summary.outcome), (EXIT_CANCELLED, "cancelled"));
+        assert_eq!(dir.files(), vec!["show.s01e01.mkv"]);
+    }
+
+    #[test]
+    fn declined_undo_and_renormalizing_are_cancelled() {
+        let dir = TempDir::new();
+        dir.touch("show.s01e01.mkv");
+        assert_eq!(run_with(&test_options(&dir), "y\n").0, 0);
+        let undo = Options {
+            undo: true,
+            ..test_options(&dir)
+        };
+        assert_eq!(run_with(&undo, "n\n").0, EXIT_CANCELLED);
+        assert_eq!(dir.files(), vec!["Show.S01E01.mkv"]);
+
+        let dir = TempDir::new();
+        dir.touch("Ame\u{301}lie.mkv");
+        let nfc = Options {
+            nfc_only: true,
+            ..test_options(&dir)
+        };
+        let (code, summary) = run_with(&nfc, "n\n");
+        assert_eq!((code, summary.outcome), (EXIT_CANCELLED, "cancelled"));
+        assert_eq!(dir.files(), vec!["Ame\u{301}lie.mkv"]);
+    }
+
+    #[test]
+    fn renormalizes_names_to_nfc() {
+        let dir = TempDir::new();
+        dir.touch("Ame\u{301}lie.mkv");
+        let options = Options {
+            nfc_only: true,
+            assume_yes: true,
+            ..test_options(&dir)
+        };
+        assert_eq!(run_with(&options, "").0, 0);
+        assert_eq!(dir.files(), vec!["Am\u{e9}lie.mkv"]);
+
+        let undo = Options {
+            undo: true,
+            assume_yes: true,
+            ..test_options(&dir)
+        };
+        assert_eq!(run_with(&undo, "").0, 0);
+        assert_eq!(dir.files(), vec!["Ame\u{301}lie.mkv"]);
+    }
+
+    #[test]
+    fn nfc_twins_conflict() {
+        let dir = TempDir::new();
+        dir.touch("Ame\u{301}lie.mkv");
+        dir.touch("Am\u{e9}lie.mkv");
+        let options = Options {
+            nfc_only: true,
+            assume_yes: true,
+            ..test_options(&dir)
+        };
+        let (code, summary) = run_with(&options, "");
+        assert_eq!((code, summary.conflicts), (1, 1));
+        assert_eq!(dir.files().len(), 2);
+    }
+
+    #[test]
+    fn nfc_targets_shared_by_two_files_conflict() {
+        let dir = TempDir::new();
+        dir.touch("A\u{30a}ngel.S01E01.mkv");
+        dir.touch("\u{212b}ngel.S01E01.mkv");
+        let options = Options {
+            nfc_only: true,
+            assume_yes: true,
+            ..test_options(&dir)
+        };
+        let (code, summary) = run_with(&options, "");
+        assert_eq!((code, summary.conflicts), (1, 1));
+        assert!(summary.actions.iter().all(|action| action.conflict));
+        assert_eq!(dir.files().len(), 2);
+    }
+}
@@ -1,562 +1,743 @@
-use lazy_static::lazy_static;
-use regex::Regex;
-use std::collections::{HashMap, HashSet};
+use rename_episodes::{
+    Ampersand, Case, ColorChoice, ExtensionList, FsType, IdPosition, Mode, Options, Padding,
+    Prompter, RunSummary, ShowAliases, SortOrder, TitleWords, Verbosity, configure_output,
+    parse_char_map, parse_duration, parse_episode_filters, parse_pattern, parse_plan, parse_season,
+    run, validate_template,
+};
 use std::env;
-use std::ffi::OsStr;
 use std::fs;
-use std::io::{self, Write};
+use std::io;
 use std::path::{Path, PathBuf};
 use std::process;
 
-lazy_static! {
-    // Regex to find SxxExx or SxxExxx patterns, case-insensitive. Captures season and episode numbers.
-    static ref SE_RE: Regex = Regex::new(r"(?i)S(\d{1,3})E(\d{1,3})").unwrap();
-    // Regex to find Exx or Exxx patterns (if Sxx is missing), case-insensitive. Captures episode number.
-    static ref E_RE: Regex = Regex::new(r"(?i)E(\d{1,3})").unwrap();
-    // Regex to find common suffix patterns like "-GroupName[Source]" at the end of the filename stem.
-    static ref SUFFIX_RE: Regex = Regex::new(r"-(?:[^-]+)(\[[^\]]+\])$").unwrap();
-}
-
-#[derive(Debug, Clone)]
-struct ParsedInfo {
-    original_path: PathBuf,
-    original_filename: String,
-    extension: String,
-    show_name_part: Option<String>, // Cleaned, lowercase, dot-separated part before SxxExx
-    season_prefix_part: Option<String>, // Formatted as "Sxx"
-    episode_number_part: Option<String>, // Formatted as "Exx"
-    remainder_part: Option<String>, // Cleaned, lowercase, dot-separated part after SxxExx
-    needs_user_input: bool,         // Flag if show name or season needs to be derived/confirmed
-}
-
-#[derive(Debug)]
-enum ParseError {
-    NotAFile,
-    NoFileName,
-}
-
-/// Cleans a string segment: converts to lowercase, replaces spaces with dots, removes multiple dots.
-fn clean_segment(segment: &str) -> String {
-    let mut cleaned = segment.trim().replace(' ', ".");
-    while cleaned.contains("..") {
-        cleaned = cleaned.replace("..", ".");
-    }
-    if cleaned != "." {
-        cleaned = cleaned.trim_matches('.').to_string();
+const USAGE: &str = "\
+Usage: rename-episodes [OPTIONS] [DIRECTORY]
+
+Renames TV episode files in DIRECTORY (default: the current directory) to
+Show.Name.SxxExx.remainder.ext.
+
+Options:
+  --mode <MODE>       How to apply the new names (default: rename)
+                        rename  rename files in place (no --out-dir allowed)
+                        copy    copy to the new name, keep the original
+                        move    move into --out-dir under the new name
+                        link    hard-link the new name, keep the original
+  --copy              Same as --mode copy
+  --out-dir <DIR>     Destination directory for copy, move and link modes (also --output-dir)
+  --summary-json      Write a JSON summary of the run's outcome when it ends
+                      (default path: rename-episodes-summary.json in the scanned directory)
+  --summary-out <FILE>
+                      Write the JSON summary to FILE (implies --summary-json)
+  --profile-timing    Report how long each phase took (also added to the summary)
+  --target-fs <FS>    Naming rules for the destination: auto, posix or windows (default: auto)
+  --case-insensitive  Treat targets differing only in case from an existing file or each
+                      other as conflicts; automatic on macOS, Windows and Windows-rule
+                      filesystems
+  --illegal-char-replacement <STR>
+                      Replace characters illegal on the target filesystem with STR (default: remove)
+  --illegal-char-map <MAP>
+                      Per-character replacements, e.g. ':=-,?=' (overrides the default replacement)
+  --group-episodes-into-ranges
+                      Report the episodes present per show/season as ranges (E01-E10, E12)
+                      and exit without renaming
+  --report-duplicates List episodes present in more than one file (with the rest of each
+                      name, e.g. the quality) and exit without renaming
+  --gaps              Also list the episodes missing between the first and last episode
+                      of each season (e.g. 'Season 01 missing: E04, E07'), then go on
+  --check             Rename nothing; list the files not already named canonically and
+                      exit with 1 if there are any, 0 if all conform (for CI checks)
+  --specials-folder-name <NAME>
+                      Folder label for season 0, e.g. 'Season 00' (default: Specials).
+                      Filenames always keep the S00 tag media servers expect.
+  --preview-open      Show the proposed renames in $PAGER (or less) when they don't fit
+                      on screen; the confirmation prompt follows once the pager exits
+  --mark-processed    After a run with no failures, record the folder as processed in
+                      .rename-episodes-processed
+  --skip-processed    Skip folders whose processed marker is still current (the marker
+                      is invalidated as soon as the folder's video files change)
+  --confirm-threshold <N>
+                      Batches of more than N files must be confirmed by typing the
+                      confirmation word instead of y/yes (default: 100)
+  --confirm-word <WORD>
+                      Word to type for large batches (default: the mode, e.g. RENAME)
+  --keep-ids          Preserve provider ID tags such as [tmdbid-1396] or {tvdb-78901}
+  --id-position <POS> Where preserved ID tags go: after-show or end (default: after-show);
+                      implies --keep-ids
+  --dry-run-interactive
+                      Number the proposed renames and let you deselect entries before
+                      the remaining set is conflict-checked and confirmed in one batch
+  --dry-run           Print the proposed renames and check for conflicts, then exit
+                      without changing anything (exit code 0 when there are no conflicts)
+  --report-unrenamable
+                      At the end, list files that had a season/episode tag but still
+                      couldn't be renamed, naming the missing component for each
+  -r, --recursive     Also scan subdirectories (symlinked directories are not followed);
+                      each file takes its show/season defaults from its own folders
+  --newer-than <AGE>  Only process videos modified within AGE, e.g. 90m, 2h, 1d or 1w;
+                      older files are left alone (for incremental imports)
+  --max-depth <N>     Scan at most N folder levels, 1 being the scanned folder alone;
+                      implies --recursive
+  --subtitle-fallback When a video has no usable SxxExx tag, take season and episode
+                      from its sidecar subtitle (e.g. Show.S01E05.srt) if that name parses
+  --no-nfo            Ignore Kodi .nfo sidecars; by default a video's .nfo (same stem)
+                      supplies its show (<showtitle>), season and episode
+  --episodes <LIST>, --only <LIST>
+                      Only process matching episodes, e.g. S01E05, S01E05-S01E08 or S02;
+                      several selectors can be separated by commas
+  --normalize-to-utf8nfc-only
+                      Only rename files whose names aren't NFC-normalized to their NFC
+                      form (for libraries mixing NFC and NFD names); nothing else changes
+  --template <FMT>    Naming template for new names (default: the dot format,
+                      {show}.{ids}.{season}{episode}.{part}.{remainder}.{ext}); e.g.
+                      '{show_spaced} - {season}{episode} - {remainder_spaced}.{ext}'
+                      {title} and {tags} split {remainder} at its first release tag
+  --pattern <REGEX>   Parse file names with REGEX before the built-in rules; it needs an
+                      'episode' named group and may have 'show', 'season' and 'remainder',
+                      e.g. '(?P<show>.+)_s(?P<season>[0-9]+)_e(?P<episode>[0-9]+)'
+  --ampersand <HOW>   How '&' in names is written: keep, and or plus (default: keep)
+  --undo              Revert the most recent renames recorded in this directory's
+                      .rename-episodes-undo.json (written by every rename or move run)
+  -y, --yes           Apply without asking for confirmation (for cron and scripts); files
+                      that would need a show name or season prompt make the run fail
+  --force             When a new name is taken by a file that isn't being renamed itself,
+                      ask whether to overwrite it instead of aborting on the conflict
+                      (with --yes, overwrite without asking)
+  --atomic            If any action fails, revert the ones already applied so the folder
+                      is left as it was (exit code 1)
+  --retries <N>       Try an action failing with a transient error (a timeout or busy file,
+                      as on flaky network shares) up to N more times, waiting 200ms,
+                      then twice as long each time (default: 1; 0 disables retrying)
+  --ext <LIST>        Video extensions to process, e.g. mkv,mp4,m4v (replaces the
+                      built-in list) or +m4v,divx (adds to it). Also read from
+                      RENAME_EPISODES_EXTS when the flag isn't given
+  --max-parallel-io <N>
+                      Read directories and file metadata on up to N threads while
+                      scanning (default: 1, serial); tune with --profile-timing
+  --no-subtitles      Don't rename subtitle sidecar files along with their video
+  --sidecar-ext <LIST>
+                      Sidecar extensions renamed with their video, e.g. srt,ass (replaces
+                      the subtitle-only default; +nfo adds to it)
+  --no-canonical-tags Leave release tags in the remainder lowercase instead of writing
+                      known ones in their usual casing (WEB-DL, HEVC, DDP5.1)
+  --keep-remainder-case
+                      Keep the casing of the remainder as in the source (Director's.Cut)
+                      instead of lowercasing it
+  --strip-remainder   Drop everything after the episode tag (quality, group, title), so
+                      names become Show.SxxExx.ext; collisions are reported as conflicts
+  --absolute          Number episodes absolutely when a file has no SxxExx tag, as anime
+                      releases do ('[Group] Show - 135 [1080p]' -> Show.E135.1080p)
+  --absolute-season <N>
+                      Put absolute-numbered episodes in season N (Show.S01E135);
+                      implies --absolute
+  --json              Print the proposed renames as a JSON array of objects (from, to,
+                      conflict, status, error) on stdout; other output goes to stderr.
+                      Nothing is applied unless --yes is given too, in which case each
+                      entry's status reports how it went
+  --emit-script       Print the renames as a POSIX shell script ('mv -n -- old new',
+                      quoted) on stdout to review and run yourself; nothing is changed
+  --case <CASE>       Casing of the show name: title, lower, upper or keep (as in the
+                      source); lower and upper also apply to the remainder (default: title)
+  --lowercase         Same as --case lower
+  --name <SHOW>       Show name for files whose names lack one, instead of asking for it
+  --season <N>        Season for files whose names lack one (3, 03 or S03), instead of
+                      asking for it; with --name and --yes a run needs no prompts
+  --show-from-level <N>
+                      Folder level whose name is the default show at the prompt, 1 being
+                      the file's own folder (default: 2, e.g. 3 for Show/Year/Season/file)
+  --season-from-level <N>
+                      Folder level whose name is the default season (default: 1)
+  --infer-show        Give files without a show name the one most files in their folder
+                      have, instead of asking for it
+  --force-name        Use --name for every file, even those naming their own show
+  --plan <FILE>       Apply the renames listed in FILE ('-' for stdin) instead of parsing
+                      names: one 'old<TAB>new' pair per line, or the JSON --json prints.
+                      Conflict checks and confirmation work as usual; with '-', use --yes
+                      or --dry-run, as stdin can't answer prompts
+  --keep-suffix       Keep trailing release-group suffixes (-Group[Source], -[Group],
+                      -RARBG) instead of stripping them
+  --suffix-groups <LIST>
+                      Groups stripped when they end a name as a bare '-GROUP', comma-
+                      separated (default: RARBG,EZTV,EZTVx.to,ETTV,TGx,YTS,YIFY,GalaxyTV)
+  --title-command <CMD>
+                      Run the shell command CMD (via sh -c) with the show, season and
+                      episode number as arguments and use the first line it prints as
+                      the episode title; a failing command keeps the title from the
+                      name. Each episode is asked once
+  --lowercase-ext     Write file extensions in lowercase (Show.S01E02.MKV -> .mkv); by
+                      default the original extension case is kept
+  --separator <SEP>   Separator between words and parts of new names: '.', ' ', '_'
+                      or '-' (also dot, space, underscore, dash); default: '.'
+  --sort <ORDER>      Order of the proposed renames and of applying them: old (original
+                      name, default), new (new name) or episode (season and episode number)
+  --color <WHEN>      Highlight what changes in the proposed renames (removed words red,
+                      added green): auto (default; when stdout is a terminal and NO_COLOR
+                      isn't set), always or never
+  -i, --interactive   Walk the proposed renames one by one to accept (a), edit (e) or
+                      skip (s) each; edited names are conflict-checked with the rest
+  -q, --quiet         Print only errors and the closing summary (the proposed renames
+                      still show when there is a prompt to answer)
+  -v, --verbose       Also print how each file was parsed and why files were left alone
+  --organize          Move each renamed file into a 'Season NN' folder (or the specials
+                      folder) next to it, or under --out-dir, creating it as needed
+  --rename-dir        Also rename the folders holding the files (with -r): season folders
+                      to 'Season NN' and show folders to the show name's casing; the
+                      scanned directory itself is never renamed
+  --include-samples   Also process files named like sample clips (sample.mkv,
+                      Show.S01E02-sample.mkv), which are skipped by default
+  --pad <WIDTH>       Digits in season and episode numbers: 1 to 4, or auto for the
+                      widest number in the batch (E009 next to E120); default: 2
+  --progress          Show 'Parsed X/Y files' and 'Renamed X/Y files' counters on stderr
+                      (shown by default when stdout is a terminal)
+  -h, --help          Print this help and exit
+
+Exit codes:
+  0  Success, nothing to do, or a clean dry run
+  1  Invalid arguments, conflicts, or an error before anything was applied
+  2  Files need a show name or season prompt, which --yes can't answer
+  3  Some renames failed (the others were applied)
+  4  Cancelled at the confirmation prompt
+";
+
+/// Splits `--flag=value` into its parts; plain arguments yield no inline value.
+fn split_flag(arg: &str) -> (&str, Option<&str>) {
+    match arg.split_once('=') {
+        Some((flag, value)) if flag.starts_with("--") => (flag, Some(value)),
+        _ => (arg, None),
     }
-    cleaned.to_lowercase()
 }
 
-/// Capitalizes words in a dot-separated string according to Title Case rules, skipping specific exceptions.
-fn capitalize_title_case(text: &str) -> String {
-    let exceptions: HashSet<&str> = ["the", "of", "and"].iter().cloned().collect();
+/// Parses command-line arguments (excluding the program name) into `Options`.
+fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = split_flag(&arg);
+        let mut value = |name: &str| -> Result<String, String> {
+            match inline_value {
+                Some(v) => Ok(v.to_string()),
+                None => args
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {}", name)),
+            }
+        };
 
-    text.split('.')
-        .enumerate()
-        .map(|(index, word)| {
-            if word.is_empty() {
-                String::new()
-            } else if index == 0 || !exceptions.contains(word) {
-                // Capitalize the first word OR any word not in exceptions
-                let mut chars = word.chars();
-                match chars.next() {
-                    None => String::new(),
-                    Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+        match flag {
+            "-h" | "--help" => options.show_help = true,
+            "--mode" => options.mode = Mode::parse(&value(flag)?)?,
+            "--copy" => options.mode = Mode::Copy,
+            "--out-dir" | "--output-dir" => options.out_dir = Some(PathBuf::from(value(flag)?)),
+            "--summary-json" => options.summary_json = true,
+            "--summary-out" => {
+                options.summary_out = Some(PathBuf::from(value(flag)?));
+                options.summary_json = true;
+            }
+            "--profile-timing" => options.profile_timing = true,
+            "--target-fs" => options.target_fs = FsType::parse(&value(flag)?)?,
+            "--case-insensitive" => options.case_insensitive = true,
+            "--sort" => options.sort = SortOrder::parse(&value(flag)?)?,
+            "--color" => options.color = ColorChoice::parse(&value(flag)?)?,
+            "--illegal-char-replacement" => options.illegal_replacement = value(flag)?,
+            "--group-episodes-into-ranges" => options.group_episodes_into_ranges = true,
+            "--report-duplicates" => options.report_duplicates = true,
+            "--check" => options.check = true,
+            "--gaps" => options.gaps = true,
+            "--preview-open" => options.preview_open = true,
+            "--specials-folder-name" => {
+                let name = value(flag)?;
+                if name.trim().is_empty() {
+                    return Err("--specials-folder-name cannot be empty".to_string());
                 }
-            } else {
-                // Keep exception words lowercase (unless first word)
-                word.to_string()
+                options.specials_folder_name = name;
             }
-        })
-        .filter(|s| !s.is_empty())
-        .collect::<Vec<String>>()
-        .join(".")
-}
-
-/// Attempts to parse filename components (show, season, episode, remainder, extension).
-fn parse_filename(path: &Path) -> Result<ParsedInfo, ParseError> {
-    if !path.is_file() {
-        return Err(ParseError::NotAFile);
-    }
-
-    let original_filename = path
-        .file_name()
-        .ok_or(ParseError::NoFileName)?
-        .to_string_lossy()
-        .into_owned();
-
-    let mut stem = path
-        .file_stem()
-        .map_or(String::new(), |s| s.to_string_lossy().into_owned());
-
-    let extension = path
-        .extension()
-        .map_or(String::new(), |e| e.to_string_lossy().into_owned());
-
-    // 1. Remove suffix like -GroupName[Source] first
-    if let Some(captures) = SUFFIX_RE.captures(&stem) {
-        if let Some(mat) = captures.get(0) {
-            stem.truncate(mat.start());
-            stem = stem.trim_end().to_string();
-        }
-    }
-
-    // 2. Try to find SxxExx
-    let mut show_name_part: Option<String> = None;
-    let mut season_prefix_part: Option<String> = None;
-    let mut episode_number_part: Option<String> = None;
-    let mut remainder_part: Option<String> = None;
-    let mut needs_user_input = false;
-
-    if let Some(se_match) = SE_RE.find(&stem) {
-        let potential_show = clean_segment(&stem[..se_match.start()]);
-        if !potential_show.is_empty() {
-            show_name_part = Some(potential_show);
-        } else {
-            needs_user_input = true; // Show name missing before SxxExx
-        }
-
-        if let Some(caps) = SE_RE.captures(se_match.as_str()) {
-            let season_num: u32 = caps.get(1).unwrap().as_str().parse().unwrap_or(0);
-            season_prefix_part = Some(format!("S{:02}", season_num)); // Force uppercase S
-
-            let episode_num: u32 = caps.get(2).unwrap().as_str().parse().unwrap_or(0);
-            episode_number_part = Some(format!("E{:02}", episode_num)); // Force uppercase E
-        } else {
-            // This case should be unlikely if SE_RE.find matched, but handle defensively
-            needs_user_input = true;
-        }
-
-        let potential_remainder = clean_segment(&stem[se_match.end()..]);
-        if !potential_remainder.is_empty() {
-            remainder_part = Some(potential_remainder);
-        }
-    } else {
-        // SxxExx not found, will need input for Season
-        needs_user_input = true;
-        // Still try to find Exx independently for later reconstruction
-        if let Some(e_match) = E_RE.find(&stem) {
-            if let Some(caps) = E_RE.captures(e_match.as_str()) {
-                let episode_num: u32 = caps.get(1).unwrap().as_str().parse().unwrap_or(0);
-                episode_number_part = Some(format!("E{:02}", episode_num)); // Force uppercase E
-
-                let potential_show = clean_segment(&stem[..e_match.start()]);
-                if !potential_show.is_empty() {
-                    show_name_part = Some(potential_show); // May be overridden by user input later
+            "--illegal-char-map" => options.illegal_map = parse_char_map(&value(flag)?)?,
+            "--mark-processed" => options.mark_processed = true,
+            "--skip-processed" => options.skip_processed = true,
+            "--confirm-threshold" => {
+                let raw = value(flag)?;
+                options.confirm_threshold = raw
+                    .parse()
+                    .map_err(|_| format!("Invalid --confirm-threshold '{}'", raw))?;
+            }
+            "--confirm-word" => {
+                let word = value(flag)?;
+                if word.trim().is_empty() {
+                    return Err("--confirm-word cannot be empty".to_string());
                 }
-
-                let potential_remainder = clean_segment(&stem[e_match.end()..]);
-                if !potential_remainder.is_empty() {
-                    remainder_part = Some(potential_remainder);
+                options.confirm_word = Some(word.trim().to_string());
+            }
+            "--keep-ids" => options.keep_ids = true,
+            "--id-position" => {
+                options.id_position = match value(flag)?.to_lowercase().as_str() {
+                    "after-show" => IdPosition::AfterShow,
+                    "end" => IdPosition::End,
+                    other => {
+                        return Err(format!(
+                            "Unknown ID position '{}' (expected after-show or end)",
+                            other
+                        ));
+                    }
+                };
+                options.keep_ids = true;
+            }
+            "--dry-run-interactive" => options.dry_run_interactive = true,
+            "--dry-run" => options.dry_run = true,
+            "--report-unrenamable" => options.report_unrenamable = true,
+            "-r" | "--recursive" => options.recursive = true,
+            "--atomic" => options.atomic = true,
+            "--retries" => {
+                let raw = value(flag)?;
+                options.retries = raw.parse().map_err(|_| {
+                    format!(
+                        "Invalid --retries '{}' (expected a number of 0 or more)",
+                        raw
+                    )
+                })?;
+            }
+            "--show-from-level" | "--season-from-level" => {
+                let raw = value(flag)?;
+                let level = match raw.parse() {
+                    Ok(level) if level > 0 => level,
+                    _ => {
+                        return Err(format!(
+                            "Invalid {} '{}' (expected a number of 1 or more)",
+                            flag, raw
+                        ));
+                    }
+                };
+                if flag == "--show-from-level" {
+                    options.show_from_level = level;
+                } else {
+                    options.season_from_level = level;
                 }
             }
-        } else {
-            // Neither SxxExx nor Exx found. Treat the whole stem as potential show name.
-            let potential_show = clean_segment(&stem);
-            if !potential_show.is_empty() {
-                show_name_part = Some(potential_show);
+            "--newer-than" => {
+                let raw = value(flag)?;
+                options.newer_than = Some(parse_duration(&raw).ok_or_else(|| {
+                    format!(
+                        "Invalid --newer-than '{}' (expected e.g. 90m, 2h or 1d)",
+                        raw
+                    )
+                })?);
+            }
+            "--max-depth" => {
+                let raw = value(flag)?;
+                options.max_depth = match raw.parse() {
+                    Ok(depth) if depth > 0 => Some(depth),
+                    _ => {
+                        return Err(format!(
+                            "Invalid --max-depth '{}' (expected a number of 1 or more)",
+                            raw
+                        ));
+                    }
+                };
+                options.recursive = true;
             }
+            "--subtitle-fallback" => options.subtitle_fallback = true,
+            "--no-nfo" => options.read_nfo = false,
+            "--episodes" | "--only" => {
+                options.episode_filters = parse_episode_filters(&value(flag)?)?
+            }
+            "--normalize-to-utf8nfc-only" => options.nfc_only = true,
+            "--template" => {
+                let template = value(flag)?;
+                validate_template(&template)?;
+                options.template = Some(template);
+            }
+            "--name" => {
+                let name = value(flag)?;
+                if name.trim().is_empty() {
+                    return Err("--name cannot be empty".to_string());
+                }
+                options.show_name = Some(name.trim().to_string());
+            }
+            "--season" => {
+                let raw = value(flag)?;
+                options.season = Some(parse_season(&raw).ok_or_else(|| {
+                    format!("Invalid --season '{}' (expected e.g. 3, 03 or S03)", raw)
+                })?);
+            }
+            "--force-name" => options.force_name = true,
+            "--infer-show" => options.infer_show = true,
+            "--keep-suffix" => options.keep_suffix = true,
+            "--title-command" => options.title_command = Some(value(flag)?),
+            "--suffix-groups" => {
+                options.suffix_groups = value(flag)?
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|group| !group.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            "--plan" => options.plan_source = Some(PathBuf::from(value(flag)?)),
+            "--keep-remainder-case" => options.keep_remainder_case = true,
+            "--rename-dir" => options.rename_dirs = true,
+            "--organize" => options.organize = true,
+            "--include-samples" => options.include_samples = true,
+            "--pad" => options.pad = Padding::parse(&value(flag)?)?,
+            "--progress" => options.progress = true,
+            "--force" => options.force = true,
+            "--lowercase-ext" => options.lowercase_ext = true,
+            "--pattern" => options.pattern = Some(parse_pattern(&value(flag)?)?),
+            "--ampersand" => options.ampersand = Ampersand::parse(&value(flag)?)?,
+            "--undo" => options.undo = true,
+            "-y" | "--yes" => options.assume_yes = true,
+            "--ext" => {
+                options.extensions =
+                    Some(ExtensionList::parse(&value(flag)?).map_err(|e| format!("--ext: {}", e))?)
+            }
+            "--max-parallel-io" => {
+                let raw = value(flag)?;
+                options.max_parallel_io = match raw.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => {
+                        return Err(format!(
+                            "Invalid --max-parallel-io '{}' (expected a number of 1 or more)",
+                            raw
+                        ));
+                    }
+                };
+            }
+            "--no-subtitles" => options.rename_sidecars = false,
+            "--sidecar-ext" => {
+                options.sidecar_extensions = Some(
+                    ExtensionList::parse(&value(flag)?)
+                        .map_err(|e| format!("--sidecar-ext: {}", e))?,
+                )
+            }
+            "--no-canonical-tags" => options.canonical_tags = false,
+            "--strip-remainder" => options.strip_remainder = true,
+            "--absolute" => options.absolute = true,
+            "--absolute-season" => {
+                let raw = value(flag)?;
+                options.absolute_season = Some(
+                    raw.parse()
+                        .map_err(|_| format!("Invalid --absolute-season '{}'", raw))?,
+                );
+                options.absolute = true;
+            }
+            "--json" => options.json = true,
+            "--emit-script" => options.emit_script = true,
+            "--case" => options.case = Case::parse(&value(flag)?)?,
+            "--lowercase" => options.case = Case::Lower,
+            "--separator" => {
+                options.separator = match value(flag)?.as_str() {
+                    "." | "dot" => '.',
+                    " " | "space" => ' ',
+                    "_" | "underscore" => '_',
+                    "-" | "dash" => '-',
+                    other => {
+                        return Err(format!(
+                            "Unknown separator '{}' (expected '.', ' ', '_' or '-')",
+                            other
+                        ));
+                    }
+                };
+            }
+            "-i" | "--interactive" => options.interactive = true,
+            "-q" | "--quiet" => options.verbosity = Verbosity::Quiet,
+            "-v" | "--verbose" => options.verbosity = Verbosity::Verbose,
+            positional if !positional.starts_with('-') || positional == "-" => {
+                if options.directory.is_some() {
+                    return Err(format!("Unexpected extra argument '{}'", arg));
+                }
+                options.directory = Some(PathBuf::from(positional));
+            }
+            _ => return Err(format!("Unknown argument '{}'", arg)),
         }
     }
 
-    // If essential info (Show or Season) is missing after parsing, confirm user input is needed.
-    if show_name_part.is_none() || season_prefix_part.is_none() {
-        needs_user_input = true;
+    match (options.mode, &options.out_dir) {
+        (Mode::Rename, Some(_)) => {
+            return Err(
+                "--out-dir cannot be used with --mode rename; use --mode move or --mode copy"
+                    .to_string(),
+            );
+        }
+        (Mode::Move, None) => return Err("--mode move requires --out-dir".to_string()),
+        _ => {}
     }
-
-    // If user input is needed for Season, we *must* have found an Episode number.
-    if needs_user_input && season_prefix_part.is_none() && episode_number_part.is_none() {
-        // A warning will be printed later if this is a video file.
+    if options.assume_yes && options.dry_run_interactive {
+        return Err("--yes cannot be combined with --dry-run-interactive".to_string());
     }
-
-    Ok(ParsedInfo {
-        original_path: path.to_path_buf(),
-        original_filename,
-        extension,           // Preserve original extension case
-        show_name_part,      // Store cleaned/lowercase for now
-        season_prefix_part,  // Store "Sxx"
-        episode_number_part, // Store "Exx"
-        remainder_part,      // Store cleaned/lowercase
-        needs_user_input,
-    })
-}
-
-/// Gets the directory name (last component) of a path, if possible. Used for default suggestions.
-fn get_dir_name(path: &Path) -> Option<String> {
-    path.file_name().and_then(OsStr::to_str).map(str::to_string)
-}
-
-/// Prompts the user for input with an optional default value.
-fn prompt_user(prompt_text: &str, default_value: Option<&str>) -> io::Result<String> {
-    match default_value {
-        Some(def) if !def.is_empty() => print!("{} [Default: {}]: ", prompt_text, def),
-        _ => print!("{}: ", prompt_text),
+    if options.emit_script && (options.json || options.undo || options.nfc_only) {
+        return Err(
+            "--emit-script cannot be combined with --json, --undo or --normalize-to-utf8nfc-only"
+                .to_string(),
+        );
     }
-    io::stdout().flush()?;
-
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
-    let trimmed_input = input.trim();
-
-    if trimmed_input.is_empty() && default_value.is_some() {
-        Ok(default_value.unwrap().to_string())
-    } else {
-        Ok(trimmed_input.to_string())
+    if options.json && options.dry_run_interactive {
+        return Err("--json cannot be combined with --dry-run-interactive".to_string());
     }
+    if options.interactive && (options.assume_yes || options.json || options.dry_run_interactive) {
+        return Err(
+            "--interactive cannot be combined with --yes, --json or --dry-run-interactive"
+                .to_string(),
+        );
+    }
+    if options.check && (options.undo || options.plan_source.is_some() || options.nfc_only) {
+        return Err(
+            "--check cannot be combined with --undo, --plan or --normalize-to-utf8nfc-only"
+                .to_string(),
+        );
+    }
+    if options.plan_source.is_some()
+        && (options.rename_dirs || options.organize || options.undo || options.nfc_only)
+    {
+        return Err(
+            "--plan cannot be combined with --rename-dir, --organize, --undo or \
+             --normalize-to-utf8nfc-only"
+                .to_string(),
+        );
+    }
+    // The plan used up stdin, so nothing is left to answer prompts with
+    if options.plan_source.as_deref() == Some(Path::new("-"))
+        && (options.interactive
+            || options.dry_run_interactive
+            || !(options.assume_yes || options.dry_run || options.json))
+    {
+        return Err("--plan - reads stdin, so it needs --yes, --dry-run or --json".to_string());
+    }
+    if options.force_name && options.show_name.is_none() {
+        return Err("--force-name requires --name".to_string());
+    }
+    if options.rename_dirs && options.mode != Mode::Rename {
+        return Err("--rename-dir only renames in place".to_string());
+    }
+    if options.nfc_only && options.mode != Mode::Rename {
+        return Err("--normalize-to-utf8nfc-only only renames in place".to_string());
+    }
+
+    Ok(options)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let target_directory = env::current_dir()?;
-    println!("Scanning directory: {}", target_directory.display());
-
-    let script_path = env::current_exe().ok(); // To avoid renaming the script itself
-
-    // Define common video file extensions (lowercase for comparison)
-    let video_extensions: HashSet<String> = [
-        "mkv", "mp4", "avi", "mov", "wmv", "flv", "webm", "mpeg", "mpg", "ts", "m2ts",
-        "vob", // Add others if needed
-    ]
-    .iter()
-    .map(|&s| s.to_lowercase())
-    .collect();
-
-    // Try to get default Show/Season names from parent/grandparent directory names
-    let parent_dir = target_directory.parent();
-    let grandparent_dir = parent_dir.and_then(|p| p.parent());
-    let default_season_dir_name = parent_dir.and_then(get_dir_name);
-    let default_show_dir_name = grandparent_dir.and_then(get_dir_name);
-
-    let mut parsed_files_info: Vec<ParsedInfo> = Vec::new();
-    let mut all_paths_in_dir: HashSet<PathBuf> = HashSet::new(); // Keep track of all items for conflict checking
-    let mut any_file_needs_input = false;
-
-    // Pass 1: Parse all relevant files and identify if user input is globally needed
-    println!("Filtering for video files: {:?}", video_extensions);
-    for entry_result in fs::read_dir(&target_directory)? {
-        let entry = entry_result?;
-        let path = entry.path();
-        all_paths_in_dir.insert(path.clone());
-
-        if let Some(script) = &script_path {
-            if path == *script {
-                continue; // Skip the running script
-            }
-        }
-
-        if path.is_file() {
-            let extension = path
-                .extension()
-                .and_then(OsStr::to_str)
-                .map(str::to_lowercase)
-                .unwrap_or_default();
-
-            if !video_extensions.contains(&extension) {
-                continue; // Skip non-video files
-            }
-
-            // Parse the video file
-            match parse_filename(&path) {
-                Ok(info) => {
-                    // Warn if essential SxxExx info seems missing for a video file
-                    if info.needs_user_input
-                        && info.season_prefix_part.is_none()
-                        && info.episode_number_part.is_none()
-                    {
-                        println!(
-                            "Warning: Video file '{}' is missing Season and Episode identifiers (SxxExx).",
-                            info.original_filename
-                        );
-                    }
-                    if info.needs_user_input {
-                        any_file_needs_input = true;
-                    }
-                    parsed_files_info.push(info);
-                }
-                Err(ParseError::NotAFile) => {} // Should not happen due to is_file check
-                Err(e) => eprintln!("Warning: Could not parse '{}': {:?}", path.display(), e),
-            }
+    let mut options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            eprintln!("Run with --help for usage.");
+            process::exit(1);
         }
-    }
-
-    if parsed_files_info.is_empty() {
-        println!("No eligible video files found to process in this directory.");
+    };
+    if options.show_help {
+        print!("{}", USAGE);
         return Ok(());
     }
-
-    // User Input Phase: Get global Show/Season if any file required it
-    let mut global_show_name: Option<String> = None; // Will store cleaned/lowercase version
-    let mut global_season_prefix: Option<String> = None; // Will store "Sxx"
-
-    if any_file_needs_input {
-        println!("\nSome video files lack Show Name or Season info (Sxx) in the filename.");
-
-        let user_show_name = prompt_user(
-            "Enter Show Name for these files",
-            default_show_dir_name.as_deref(),
-        )?;
-        if !user_show_name.is_empty() {
-            global_show_name = Some(clean_segment(&user_show_name)); // Clean the input
-        } else {
-            println!(
-                "No Show Name provided, files needing it might be skipped or use partial names."
-            );
+    configure_output(&options);
+    if let Some(path) = TitleWords::config_path()
+        && let Ok(contents) = fs::read_to_string(&path)
+    {
+        match TitleWords::parse(&contents) {
+            Ok(words) => options.title_words = words,
+            Err(message) => eprintln!(
+                "Warning: Ignoring '{}': {}; using the built-in word lists.",
+                path.display(),
+                message
+            ),
         }
-
-        let user_season_str = prompt_user(
-            "Enter Season Number (e.g., 1, 02, 15) for these files",
-            default_season_dir_name.as_deref(),
-        )?;
-
-        // Attempt to parse season number and format correctly ("Sxx")
-        let cleaned_season_input =
-            user_season_str.trim_start_matches(|c: char| !c.is_ascii_digit());
-        if let Ok(num) = cleaned_season_input.parse::<u32>() {
-            global_season_prefix = Some(format!("S{:02}", num)); // Ensure uppercase S
-        } else {
-            println!(
-                "Could not parse Season Number '{}'. Files needing it will be skipped.",
-                user_season_str
-            );
-            // If we can't get a valid season, disable applying global overrides for files that needed it
-            any_file_needs_input = false;
+    }
+    if let Some(path) = ShowAliases::config_path()
+        && let Ok(contents) = fs::read_to_string(&path)
+    {
+        match ShowAliases::parse(&contents) {
+            Ok(aliases) => options.show_aliases = aliases,
+            Err(message) => eprintln!(
+                "Warning: Ignoring '{}': {}; no show aliases are applied.",
+                path.display(),
+                message
+            ),
         }
     }
-
-    // Pass 2: Construct Final Names & Prepare Renames
-    let mut proposed_renames: HashMap<PathBuf, PathBuf> = HashMap::new();
-
-    for info in parsed_files_info {
-        // Start with parsed info, potentially override with global input
-        let mut final_show = info.show_name_part.clone();
-        let mut final_season = info.season_prefix_part.clone();
-        let final_episode = info.episode_number_part.clone();
-        let final_remainder = info.remainder_part.clone();
-        let final_extension = info.extension.clone();
-
-        // Apply global overrides only if input was needed for this file and successfully provided
-        if info.needs_user_input && any_file_needs_input {
-            if let Some(global_show) = &global_show_name {
-                final_show = Some(global_show.clone());
-            }
-            if let Some(global_season) = &global_season_prefix {
-                final_season = Some(global_season.clone());
+    if options.extensions.is_none()
+        && let Ok(value) = env::var("RENAME_EPISODES_EXTS")
+    {
+        match ExtensionList::parse(&value) {
+            Ok(list) => options.extensions = Some(list),
+            Err(message) => {
+                eprintln!("Error: RENAME_EPISODES_EXTS: {}", message);
+                process::exit(1);
             }
+        }
+    }
 
-            // Critical check: Can we form "SxxExx" after potential overrides?
-            if final_season.is_none() || final_episode.is_none() {
-                println!(
-                    "Skipping '{}': Cannot determine final Season/Episode ({} / {}) after prompts.",
-                    info.original_filename,
-                    final_season.as_deref().unwrap_or("Missing"),
-                    final_episode.as_deref().unwrap_or("Missing")
-                );
-                continue; // Skip this file if essential parts are missing
+    if let Some(source) = &options.plan_source {
+        let text = if source == Path::new("-") {
+            io::read_to_string(io::stdin())
+        } else {
+            fs::read_to_string(source)
+        };
+        let plan = text
+            .map_err(|e| format!("Cannot read plan '{}': {}", source.display(), e))
+            .and_then(|text| parse_plan(&text));
+        match plan {
+            Ok(plan) => options.plan = Some(plan),
+            Err(message) => {
+                eprintln!("Error: {}", message);
+                process::exit(1);
             }
         }
+    }
 
-        // Construct the new filename stem piece by piece
-        let mut new_stem_parts: Vec<String> = Vec::new();
+    let mut summary = RunSummary::default();
+    let result = run(&options, &mut summary, &mut Prompter::stdio());
 
-        // 1. Show Name (Apply Title Case)
-        if let Some(show) = final_show {
-            if !show.is_empty() {
-                new_stem_parts.push(capitalize_title_case(&show));
-            } else {
-                println!(
-                    "Warning: Skipping '{}' due to empty show name component.",
-                    info.original_filename
-                );
-                continue;
-            }
-        } else {
-            println!(
-                "Warning: Skipping '{}' due to missing show name component.",
-                info.original_filename
-            );
-            continue;
-        }
+    if options.json && result.is_ok() {
+        print!("{}", summary.actions_json());
+    }
 
-        // 2. Season and Episode (Already formatted Sxx and Exx)
-        if let Some(season) = final_season {
-            if let Some(episode) = final_episode {
-                new_stem_parts.push(format!("{}{}", season, episode));
-            } else {
-                println!(
-                    "Warning: Skipping '{}' due to missing episode component.",
-                    info.original_filename
-                );
-                continue;
-            }
-        } else {
-            println!(
-                "Warning: Skipping '{}' due to missing season component.",
-                info.original_filename
+    // The summary is written on every exit path, including conflicts and cancellation.
+    if options.profile_timing {
+        for (phase, elapsed) in &summary.timings {
+            eprintln!(
+                "Timing: {:<6} {:.3} ms",
+                phase,
+                elapsed.as_secs_f64() * 1000.0
             );
-            continue;
         }
-
-        // 3. Remainder (Keep cleaned/lowercase)
-        if let Some(rem) = final_remainder {
-            if !rem.is_empty() {
-                new_stem_parts.push(rem);
+    }
+    if options.summary_json {
+        let summary_path = options.summary_out.clone().or_else(|| {
+            summary
+                .target_directory
+                .as_ref()
+                .map(|dir| dir.join("rename-episodes-summary.json"))
+        });
+        if let Some(path) = summary_path {
+            if result.is_err() {
+                summary.outcome = "error";
             }
-        }
-
-        let new_stem = new_stem_parts.join(".");
-
-        // Reassemble the full filename, preserving original extension case
-        let new_filename_str = if final_extension.is_empty() {
-            new_stem
-        } else {
-            format!("{}.{}", new_stem, final_extension)
-        };
-
-        // Check if the filename actually changed
-        if new_filename_str != info.original_filename {
-            let parent = info
-                .original_path
-                .parent()
-                .unwrap_or_else(|| Path::new("."));
-            let new_path = parent.join(new_filename_str);
-
-            // Check if the *path* actually changed (it might not if only case changed on case-insensitive FS)
-            // We rely on the string comparison above mostly, but add proposed rename only if distinct paths.
-            if new_path != info.original_path {
-                proposed_renames.insert(info.original_path.clone(), new_path);
+            if let Err(e) = fs::write(
+                &path,
+                summary.to_json(options.profile_timing, options.report_unrenamable),
+            ) {
+                eprintln!("Error writing summary '{}': {}", path.display(), e);
             }
         }
     }
 
-    // Display proposed changes
-    if proposed_renames.is_empty() {
-        println!("\nNo files need renaming based on the current rules and inputs.");
-        return Ok(());
-    }
-
-    println!("\nProposed renames:");
-    println!("--------------------");
-    let max_len_old = proposed_renames
-        .keys()
-        .filter_map(|p| p.file_name())
-        .map(|n| n.len())
-        .max()
-        .unwrap_or(0);
+    summary.print_closing_report(&options);
 
-    // Sort for consistent display order
-    let mut sorted_renames: Vec<_> = proposed_renames.iter().collect();
-    sorted_renames.sort_by(|(old_a, _), (old_b, _)| old_a.cmp(old_b));
-
-    for (old, new) in &sorted_renames {
-        // Borrow here for display
-        let old_name = old.file_name().map_or("?", |n| n.to_str().unwrap_or("?"));
-        let new_name = new.file_name().map_or("?", |n| n.to_str().unwrap_or("?"));
-        println!("{:<width$} -> {}", old_name, new_name, width = max_len_old);
+    let exit_code = result?;
+    if exit_code != 0 {
+        process::exit(exit_code);
     }
-    println!("--------------------");
+    Ok(())
+}
 
-    // Conflict Checking
-    let mut potential_conflicts = Vec::new();
-    let target_filenames: HashSet<&PathBuf> = proposed_renames.values().collect(); // Targets being renamed TO
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Check if a target filename already exists in the directory *and* is not itself being renamed from
-    for new_target_path_ref in &target_filenames {
-        let target_path: &PathBuf = *new_target_path_ref;
-        if all_paths_in_dir.contains(target_path) && !proposed_renames.contains_key(target_path) {
-            potential_conflicts.push(format!(
-                "Target '{}' already exists and is not being renamed.",
-                target_path
-                    .file_name()
-                    .map_or("?", |n| n.to_str().unwrap_or("?"))
-            ));
-        }
+    fn args(list: &[&str]) -> Result<Options, String> {
+        parse_args(list.iter().map(|arg| arg.to_string()))
     }
 
-    // Check if multiple files are being renamed TO the same target filename
-    let mut target_counts: HashMap<&PathBuf, usize> = HashMap::new();
-    for target_path in proposed_renames.values() {
-        *target_counts.entry(target_path).or_insert(0) += 1;
+    #[test]
+    fn parses_naming_flags() {
+        let options = args(&["--absolute-season", "2", "--separator", "space", "dir"]).unwrap();
+        assert!(options.absolute);
+        assert_eq!(options.absolute_season, Some(2));
+        assert_eq!(options.separator, ' ');
+        assert_eq!(options.directory, Some(PathBuf::from("dir")));
+        assert!(args(&["--case=lower"]).unwrap().case == Case::Lower);
+        assert!(args(&["--separator", "+"]).is_err());
+        assert!(args(&["--lowercase-ext"]).unwrap().lowercase_ext);
+        assert_eq!(args(&["--pad", "auto"]).unwrap().pad, Padding::Auto);
+        assert!(args(&["--pattern", "(?P<show>.+)"]).is_err());
+        assert_eq!(
+            args(&["--suffix-groups", "NTb, FLUX"])
+                .unwrap()
+                .suffix_groups,
+            vec!["NTb", "FLUX"]
+        );
+        assert_eq!(
+            args(&["--title-command", "tvdb-title"])
+                .unwrap()
+                .title_command
+                .as_deref(),
+            Some("tvdb-title")
+        );
     }
 
-    for (target_path, count) in target_counts {
-        if count > 1 {
-            let conflicting_originals: Vec<String> = proposed_renames
-                .iter()
-                .filter(|&(_, new)| new == target_path)
-                .map(|(old, _)| {
-                    old.file_name()
-                        .map_or("?".to_string(), |n| n.to_string_lossy().into_owned())
-                })
-                .collect();
-            potential_conflicts.push(format!(
-                "Multiple files would be renamed to '{}': {:?}",
-                target_path
-                    .file_name()
-                    .map_or("?", |n| n.to_str().unwrap_or("?")),
-                conflicting_originals
-            ));
-        }
+    #[test]
+    fn parses_show_and_season_flags() {
+        let named = args(&["--name", "The Office", "--season", "2"]).unwrap();
+        assert_eq!(named.show_name.as_deref(), Some("The Office"));
+        assert_eq!(named.season, Some(2));
+        assert!(args(&["--force-name"]).is_err());
+        assert!(args(&["--infer-show"]).unwrap().infer_show);
+        assert_eq!(args(&["--season", "S03"]).unwrap().season, Some(3));
+        assert!(args(&["--season", "three"]).is_err());
+        let levels = args(&["--show-from-level", "3", "--season-from-level", "2"]).unwrap();
+        assert_eq!((levels.show_from_level, levels.season_from_level), (3, 2));
+        assert!(args(&["--show-from-level", "0"]).is_err());
+        assert!(!args(&["--no-nfo"]).unwrap().read_nfo);
     }
 
-    if !potential_conflicts.is_empty() {
-        eprintln!("\nWarning: Potential conflicts detected!");
-        for conflict in potential_conflicts {
-            eprintln!("- {}", conflict);
-        }
-        eprintln!("Please resolve conflicts before proceeding.");
-        process::exit(1); // Abort due to conflicts
+    #[test]
+    fn parses_scan_flags() {
+        assert!(args(&["--case-insensitive"]).unwrap().case_insensitive);
+        assert_eq!(args(&["--sort=episode"]).unwrap().sort, SortOrder::Episode);
+        let depth = args(&["--max-depth", "2"]).unwrap();
+        assert_eq!((depth.recursive, depth.max_depth), (true, Some(2)));
+        assert!(args(&["--max-depth", "0"]).is_err());
+        assert_eq!(
+            args(&["--sidecar-ext", "+nfo"])
+                .unwrap()
+                .sidecar_extensions
+                .unwrap()
+                .extensions,
+            vec!["nfo"]
+        );
+        assert_eq!(
+            args(&["--newer-than", "2h"]).unwrap().newer_than,
+            Some(std::time::Duration::from_secs(7200))
+        );
+        assert!(args(&["--newer-than", "soon"]).is_err());
+        let only = args(&["--only", "S02E05-S02E08"]).unwrap();
+        assert_eq!(only.episode_filters.len(), 1);
     }
 
-    // Confirmation and Renaming
-    print!(
-        "\nProceed with renaming {} file(s)? (y/n) [default: n]: ",
-        proposed_renames.len()
-    );
-    io::stdout().flush()?;
-    let mut confirmation = String::new();
-    io::stdin().read_line(&mut confirmation)?;
-
-    let trimmed_confirmation = confirmation.trim().to_lowercase();
-
-    if trimmed_confirmation == "y" || trimmed_confirmation == "yes" {
-        println!("\nRenaming files...");
-        let mut success_count = 0;
-        let mut error_count = 0;
-
-        // Consume the map for the renaming process, using the sorted order
-        let mut sorted_renames_for_action: Vec<_> = proposed_renames.into_iter().collect();
-        sorted_renames_for_action.sort_by(|(old_a, _), (old_b, _)| old_a.cmp(old_b));
+    #[test]
+    fn parses_modes_and_run_flags() {
+        assert!(args(&["--mode", "rename", "--out-dir", "x"]).is_err());
+        assert!(args(&["--mode", "move"]).is_err());
+        let options = args(&["--copy", "--output-dir", "lib"]).unwrap();
+        assert_eq!(options.mode, Mode::Copy);
+        assert_eq!(options.out_dir, Some(PathBuf::from("lib")));
+        assert_eq!(args(&["--retries", "3"]).unwrap().retries, 3);
+        assert!(args(&["--retries", "-1"]).is_err());
+        assert!(args(&["--atomic"]).unwrap().atomic);
+        assert!(args(&["--check"]).unwrap().check);
+        assert!(args(&["--gaps"]).unwrap().gaps);
+        assert!(args(&["--emit-script"]).unwrap().emit_script);
+        assert_eq!(
+            args(&["--plan", "-", "--yes"]).unwrap().plan_source,
+            Some(PathBuf::from("-"))
+        );
+    }
 
-        for (old, new) in sorted_renames_for_action {
-            // Iterate over owned values now
-            match fs::rename(&old, &new) {
-                // Borrow paths for the rename operation
-                Ok(_) => {
-                    println!(
-                        "Renamed: '{}' to '{}'",
-                        old.file_name().map_or("?", |n| n.to_str().unwrap_or("?")),
-                        new.file_name().map_or("?", |n| n.to_str().unwrap_or("?"))
-                    );
-                    success_count += 1;
-                }
-                Err(e) => {
-                    eprintln!(
-                        "Error renaming '{}' to '{}': {}",
-                        old.file_name().map_or("?", |n| n.to_str().unwrap_or("?")),
-                        new.file_name().map_or("?", |n| n.to_str().unwrap_or("?")),
-                        e
-                    );
-                    error_count += 1;
-                }
-            }
-        }
-        println!("--------------------");
-        println!(
-            "Renaming complete. {} succeeded, {} failed.",
-            success_count, error_count
+    #[test]
+    fn parses_output_flags() {
+        assert_eq!(args(&["-q"]).unwrap().verbosity, Verbosity::Quiet);
+        assert_eq!(
+            args(&["-q", "--verbose"]).unwrap().verbosity,
+            Verbosity::Verbose
         );
-    } else {
-        println!("Renaming cancelled.");
+        assert_eq!(args(&["--color=never"]).unwrap().color, ColorChoice::Never);
+        assert!(args(&["--progress"]).unwrap().progress);
     }
 
-    Ok(())
+    #[test]
+    fn rejects_conflicting_and_unknown_arguments() {
+        assert!(args(&["--yes", "--dry-run-interactive"]).is_err());
+        assert!(args(&["--json", "--dry-run-interactive"]).is_err());
+        assert!(args(&["-i", "--yes"]).is_err());
+        assert!(args(&["--emit-script", "--json"]).is_err());
+        assert!(args(&["--check", "--undo"]).is_err());
+        assert!(args(&["--plan", "-"]).is_err());
+        assert!(args(&["a", "b"]).is_err());
+        assert!(args(&["--bogus"]).is_err());
+    }
 }